@@ -8,9 +8,24 @@ use std::{
 };
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-const KERNEL_LOAD_START_SIGNAL: u8 = 0x01;
 const KERNEL_LOAD_SIZE_ACK_SIGNAL: u8 = 0x02;
 const KERNEL_LOAD_ACK_SIGNAL: u8 = 0x03;
+const KERNEL_LOAD_PROGRESS_SIGNAL: u8 = 0x04;
+/// Start signal announcing that the header carries a timestamp. The plain start signal, 0x01, is
+/// for hosts without a timestamp. Loaders that predate the timestamp only know the plain one.
+const KERNEL_LOAD_START_WITH_TIME_SIGNAL: u8 = 0x05;
+const KERNEL_LOAD_SIZE_NAK_SIGNAL: u8 = 0x15;
+
+/// Number of bytes the loader confirms with each progress signal.
+const KERNEL_LOAD_PROGRESS_INTERVAL: usize = 64 * 1024;
+
+/// Number of bytes sent between checks for progress signals.
+const PROGRESS_POLL_INTERVAL: usize = 1024;
+
+const PROGRESS_BAR_WIDTH: usize = 40;
+
+/// Number of times the header is sent before giving up.
+const KERNEL_LOAD_HEADER_ATTEMPTS: usize = 3;
 
 const KERNEL_TRANSFER_SPEED_BYTE_PER_SECOND: f64 = 90.0 * 1024.0;
 
@@ -127,6 +142,31 @@ async fn serial_action(buff: &mut Vec<u8>, read: usize) {
     }
 }
 
+/// Remove all progress signals from `buff` and return how many there were.
+fn take_progress_signals(buff: &mut Vec<u8>) -> usize {
+    let len = buff.len();
+    buff.retain(|c| *c != KERNEL_LOAD_PROGRESS_SIGNAL);
+    len - buff.len()
+}
+
+/// Render the bytes confirmed by the loader as a progress bar.
+fn progress_bar(confirmed: usize, total: usize) -> String {
+    let confirmed = confirmed.min(total);
+    let filled = if total == 0 {
+        PROGRESS_BAR_WIDTH
+    } else {
+        confirmed * PROGRESS_BAR_WIDTH / total
+    };
+
+    format!(
+        "[{}{}] {}/{} KiB confirmed",
+        "#".repeat(filled),
+        " ".repeat(PROGRESS_BAR_WIDTH - filled),
+        confirmed / 1024,
+        total / 1024
+    )
+}
+
 async fn send_kernel(kernel_path: &PathBuf, async_serial: &mut tokio::fs::File) {
     eprintln!("Uploading kernel...");
     match std::fs::File::open(kernel_path) {
@@ -134,17 +174,51 @@ async fn send_kernel(kernel_path: &PathBuf, async_serial: &mut tokio::fs::File)
             let mut kernel = Vec::new();
             let _ = file.read_to_end(&mut kernel);
 
-            eprintln!("Notifing loader...");
-            let _ = async_serial.write_u8(KERNEL_LOAD_START_SIGNAL).await;
+            // The loader NAKs a header with a corrupted size. Resend it a few times.
+            let mut buff = Vec::new();
+            let mut attempt = 0;
+            loop {
+                eprintln!("Notifing loader...");
+                let _ = async_serial
+                    .write_u8(KERNEL_LOAD_START_WITH_TIME_SIGNAL)
+                    .await;
 
-            eprintln!("Writing kernel size: {} bytes...", kernel.len());
-            for i in 0..4 {
-                let c = ((kernel.len() >> (8 * i)) & 0xFF) as u8;
-                let _ = async_serial.write_u8(c).await;
-            }
+                eprintln!("Writing kernel size: {} bytes...", kernel.len());
+                let size = (kernel.len() as u32).to_le_bytes();
+                for c in size {
+                    let _ = async_serial.write_u8(c).await;
+                }
+                let _ = async_serial
+                    .write_u8(size.iter().fold(0, |acc, c| acc ^ c))
+                    .await;
 
-            let mut buff = Vec::new();
-            while async_serial.read_to_end(&mut buff).await.unwrap() == 0 {}
+                // The loader hands the time to the kernel. Zero tells it that there is none.
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|since_epoch| since_epoch.as_secs())
+                    .unwrap_or(0);
+                eprintln!("Writing timestamp: {}...", timestamp);
+                for c in timestamp.to_le_bytes() {
+                    let _ = async_serial.write_u8(c).await;
+                }
+
+                buff.clear();
+                while async_serial.read_to_end(&mut buff).await.unwrap() == 0 {}
+                if buff.first() != Some(&KERNEL_LOAD_SIZE_NAK_SIGNAL) {
+                    break;
+                }
+
+                attempt += 1;
+                if attempt == KERNEL_LOAD_HEADER_ATTEMPTS {
+                    eprintln!("Loader rejected the kernel size, it may not fit into memory");
+                    return;
+                }
+                eprintln!("Loader rejected the kernel size, resending...");
+
+                // Drop the loader's error message, so that it is not taken for the next reply.
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                let _ = async_serial.read_to_end(&mut buff).await;
+            }
             if buff != [KERNEL_LOAD_SIZE_ACK_SIGNAL] {
                 eprintln!("Did not receive responce to kernel size: {:?}", buff);
                 return;
@@ -156,12 +230,25 @@ async fn send_kernel(kernel_path: &PathBuf, async_serial: &mut tokio::fs::File)
                 KERNEL_TRANSFER_SPEED_BYTE_PER_SECOND / 1024.0
             );
             let now = std::time::Instant::now();
+            let mut confirmed = 0;
             for (i, byte) in kernel.iter().enumerate() {
-                eprint!("\x1b[GSending {}/{} byte", i, kernel.len());
                 let _ = async_serial.write_u8(*byte).await;
                 std::thread::sleep(std::time::Duration::from_secs_f64(
                     1.0 / KERNEL_TRANSFER_SPEED_BYTE_PER_SECOND,
                 ));
+
+                if i % PROGRESS_POLL_INTERVAL == 0 || i + 1 == kernel.len() {
+                    buff.clear();
+                    let _ = async_serial.read_to_end(&mut buff).await;
+                    confirmed += take_progress_signals(&mut buff) * KERNEL_LOAD_PROGRESS_INTERVAL;
+
+                    eprint!(
+                        "\x1b[GSending {}/{} byte {}",
+                        i + 1,
+                        kernel.len(),
+                        progress_bar(confirmed, kernel.len())
+                    );
+                }
             }
             eprintln!("\n Time took: {:#?}", now.elapsed());
             eprintln!(
@@ -169,7 +256,16 @@ async fn send_kernel(kernel_path: &PathBuf, async_serial: &mut tokio::fs::File)
                 (kernel.len() / 1024) as f64 / now.elapsed().as_secs_f64()
             );
 
-            while async_serial.read_to_end(&mut buff).await.unwrap() == 0 {}
+            // The last progress signals may still be on their way.
+            buff.clear();
+            loop {
+                while async_serial.read_to_end(&mut buff).await.unwrap() == 0 {}
+                take_progress_signals(&mut buff);
+                if !buff.is_empty() {
+                    break;
+                }
+            }
+
             if buff != [KERNEL_LOAD_ACK_SIGNAL] {
                 eprintln!("Did not receive responce to kernel successuf upload");
             } else {
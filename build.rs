@@ -1,6 +1,70 @@
-use std::{env, fs, process};
+use std::{collections::BTreeSet, env, fs, process};
+
+/// Run a command and return its trimmed stdout, if it succeeded.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = process::Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// Provide build information to the `version` module.
+fn emit_build_info() {
+    let commit = command_output("git", &["rev-parse", "--short", "HEAD"])
+        .map(|hash| {
+            let dirty = command_output("git", &["status", "--porcelain"])
+                .is_some_and(|status| !status.is_empty());
+            if dirty {
+                hash + "-dirty"
+            } else {
+                hash
+            }
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version =
+        command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=RP4OS_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=RP4OS_RUSTC_VERSION={}", rustc_version);
+    emit_git_rerun_triggers();
+}
+
+/// Rerun the build script whenever the commit or the dirty state may have changed.
+fn emit_git_rerun_triggers() {
+    // HEAD only changes on checkout. A commit on the checked out branch updates the branch's ref
+    // file instead, or `packed-refs` if the ref was packed.
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/packed-refs");
+    if let Ok(head) = fs::read_to_string(".git/HEAD") {
+        if let Some(branch_ref) = head.trim().strip_prefix("ref: ") {
+            println!("cargo:rerun-if-changed=.git/{}", branch_ref);
+        }
+    }
+
+    // Staging changes the index, but editing a file only changes the file. Cargo scans watched
+    // directories recursively, so watching the top-level entries catches every edit, including
+    // new untracked files.
+    println!("cargo:rerun-if-changed=.git/index");
+    let top_level: BTreeSet<String> = command_output("git", &["ls-files"])
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|path| path.split('/').next())
+        .map(str::to_string)
+        .collect();
+    for entry in top_level {
+        println!("cargo:rerun-if-changed={}", entry);
+    }
+}
 
 fn main() {
+    emit_build_info();
+
     let ld_script_path = match env::var("LD_SCRIPT_PATH") {
         Ok(var) => var,
         _ => process::exit(0),
@@ -1,12 +1,14 @@
 use aarch64_cpu::{asm, registers::*};
 use core::arch::global_asm;
-use tock_registers::interfaces::Writeable;
+use tock_registers::interfaces::{Readable, Writeable};
 
 // Assembly counterpart to this file.
 global_asm!(
     include_str!("boot.s"),
+    CONST_CURRENTEL_EL1 = const 0x4,
     CONST_CURRENTEL_EL2 = const 0x8,
-    CONST_CORE_ID_MASK = const 0b11
+    CONST_CORE_ID_MASK = const 0b11,
+    CONST_STACK_PAINT_PATTERN = const rp4os::memory::stack::STACK_PAINT_PATTERN
 );
 
 /// The Rust entry of the `kernel` binary.
@@ -18,6 +20,12 @@ global_asm!(
 /// - Exception return from EL2 must must continue execution in EL1 with `kernel_init()`.
 #[no_mangle]
 pub unsafe extern "C" fn _start_rust(phys_boot_core_stack_end_exclusive_addr: u64) -> ! {
+    // A kernel uploaded by a re-entered kernelloader starts in EL1, where the EL1 setup of the
+    // previous kernel is still in place.
+    if CurrentEL.matches_all(CurrentEL::EL::EL1) {
+        crate::kernel_init()
+    }
+
     prepare_el2_to_el1_transition(phys_boot_core_stack_end_exclusive_addr);
 
     // Use `eret` to "return" to EL1. This results in execution of kernel_init() in EL1.
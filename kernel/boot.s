@@ -13,11 +13,19 @@
 .section .text._start
 
 _start:
-	// Only proceed if the core executes in EL2. Park it otherwise.
+	// Keep the boot timestamp that the kernelloader passes in x1. The firmware passes zero.
+	mov	x19, x1
+
+	// Only proceed if the core executes in EL2, or in EL1 when entered from a kernelloader that
+	// was re-entered by a previous kernel. Park it otherwise.
 	mrs	x0, CurrentEL
 	cmp	x0, {CONST_CURRENTEL_EL2}
+	b.eq	.L_el_checked
+	cmp	x0, {CONST_CURRENTEL_EL1}
 	b.ne	.L_parking_loop
 
+.L_el_checked:
+
 	// Only proceed on the boot core. Park it otherwise.
 	mrs	x0, MPIDR_EL1
 	and	x0, x0, {CONST_CORE_ID_MASK}
@@ -33,10 +41,27 @@ _start:
 
 .L_bss_init_loop:
 	cmp	x0, x1
-	b.eq	.L_prepare_rust
+	b.eq	.L_paint_stack
 	stp	xzr, xzr, [x0], #16
 	b	.L_bss_init_loop
 
+	// Paint the boot core stack, so that its high-water mark can be measured later.
+.L_paint_stack:
+	ADR_REL	x0, __boot_core_stack_paint_start
+	ADR_REL	x1, __boot_core_stack_end_exclusive
+	mov	x2, {CONST_STACK_PAINT_PATTERN}
+
+.L_paint_stack_loop:
+	cmp	x0, x1
+	b.eq	.L_store_boot_timestamp
+	stp	x2, x2, [x0], #16
+	b	.L_paint_stack_loop
+
+	// Store the boot timestamp now that bss is initialized.
+.L_store_boot_timestamp:
+	ADR_REL	x0, BOOT_TIMESTAMP  // provided by bsp/__board_name__/mod.rs
+	str	x19, [x0]
+
 	// Prepare the jump to Rust code.
 .L_prepare_rust:
 	// Set the stack pointer.
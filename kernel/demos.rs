@@ -0,0 +1,208 @@
+//! Demos that `kernel_main()` can end in.
+//!
+//! The demo is picked at compile time by [`DEFAULT_DEMO`]. Selecting it with `demo=<name>` on a
+//! boot command line is not implemented: the kernelloader only hands the kernel a timestamp in
+//! x1, and the firmware's command line is only reachable through the device tree, which the
+//! kernel does not parse. A command line needs both a `boot_console` protocol extension and
+//! memory that the kernel's early boot does not clear. Once there is one, the name only needs to
+//! be passed to [`run`].
+
+use alloc::vec;
+use core::time::Duration;
+use rp4os::{
+    bsp::drivers::bcm2xxx_gpio::{Edge, GPIOFunction, GPIOPin, Pull},
+    console::interface::{Read, Write},
+    exception::asynchronous::ThreadContext,
+    *,
+};
+
+/// A demo is entered after init and never returns.
+pub struct Demo {
+    pub name: &'static str,
+    pub run: fn(&ThreadContext) -> !,
+}
+
+/// The demo run if none was selected.
+#[cfg(not(feature = "headless"))]
+pub const DEFAULT_DEMO: &str = "echo";
+
+/// The demo run if none was selected.
+#[cfg(feature = "headless")]
+pub const DEFAULT_DEMO: &str = "selftest";
+
+/// All available demos.
+static DEMOS: &[Demo] = &[
+    Demo {
+        name: "echo",
+        run: echo,
+    },
+    Demo {
+        name: "button",
+        run: button,
+    },
+    Demo {
+        name: "heap",
+        run: heap,
+    },
+    Demo {
+        name: "irq",
+        run: irq,
+    },
+    Demo {
+        name: "timer",
+        run: timer,
+    },
+    Demo {
+        name: "selftest",
+        run: selftest_exit,
+    },
+    Demo {
+        name: "selftest-loop",
+        run: selftest_loop,
+    },
+    Demo {
+        name: "hang",
+        run: hang,
+    },
+];
+
+/// Echo UART input. The echoing itself is done by the UART's IRQ handler through the input
+/// router, or by polling if IRQs are not available.
+fn echo(_tc: &ThreadContext) -> ! {
+    info!("Echoing input now");
+
+    if bsp::driver::interrupt_controller_error().is_none() {
+        console::input_router::register_normal_consumer(|byte| {
+            console::console().write_char(byte as char)
+        });
+        cpu::idle_loop();
+    }
+
+    let console = console::console();
+    loop {
+        let c = console.read_char();
+        console.write_char(c);
+    }
+}
+
+/// Report presses of a button between GPIO 17 and ground, header pin 11 and 9. The core sleeps in
+/// the idle loop until the GPIO IRQ wakes it up.
+fn button(_tc: &ThreadContext) -> ! {
+    let pin = GPIOPin::new(17);
+
+    match bsp::driver::gpio() {
+        Some(gpio) if bsp::driver::interrupt_controller_error().is_none() => {
+            gpio.set_function(pin, GPIOFunction::Input);
+            gpio.set_pull(pin, Pull::Up);
+            gpio.enable_interrupt(pin, Edge::Falling, |pin| {
+                info!("Button on GPIO {} pressed", pin)
+            });
+
+            info!("Waiting for button presses on GPIO {}", pin);
+        }
+        _ => warn!("Button demo needs the GPIO driver and IRQs"),
+    }
+
+    cpu::idle_loop();
+}
+
+/// Allocate from the heap and show the usage before and after.
+fn heap(_tc: &ThreadContext) -> ! {
+    info!("Kernel heap:");
+    memory::heap_alloc::KERNEL_HEAP_ALLOCATOR.print_usage();
+
+    let heap_baseline = memory::heap_alloc::KERNEL_HEAP_ALLOCATOR.live_allocations();
+    {
+        let _numbers = vec![1, 2, 3, 4];
+
+        info!("Kernel heap:");
+        memory::heap_alloc::KERNEL_HEAP_ALLOCATOR.print_usage();
+    }
+    memory::heap_alloc::KERNEL_HEAP_ALLOCATOR.assert_no_leaks(heap_baseline);
+
+    info!("Kernel heap:");
+    memory::heap_alloc::KERNEL_HEAP_ALLOCATOR.print_usage();
+
+    cpu::idle_loop();
+}
+
+/// Show the registered IRQ handlers and the interrupt controller state.
+fn irq(_tc: &ThreadContext) -> ! {
+    info!("Registered IRQ handlers:");
+    exception::asynchronous::irq_manager().print_handler();
+
+    info!("Interrupt controller state:");
+    exception::asynchronous::irq_manager().dump_state();
+
+    cpu::idle_loop();
+}
+
+/// Print the uptime once per second.
+fn timer(tc: &ThreadContext) -> ! {
+    loop {
+        if let Err(e) = time::sleep(tc, Duration::from_secs(1)) {
+            panic!("Sleeping failed: {}", e);
+        }
+
+        info!("Uptime: {:?}", time::uptime());
+    }
+}
+
+/// Run the self-tests again on every received character. Meant for regression checks on real
+/// hardware, where there is no QEMU to exit.
+fn selftest_loop(_tc: &ThreadContext) -> ! {
+    let console = console::console();
+
+    loop {
+        info!("Press any key to run the self-tests");
+        console.read_char();
+
+        // Failures are printed by the tests.
+        let _ = selftest::run_all();
+    }
+}
+
+/// Exit QEMU with the result of the self-tests, for automated runs without a terminal.
+fn selftest_exit(_tc: &ThreadContext) -> ! {
+    match selftest::run_all() {
+        Ok(()) => {
+            info!("Self-tests passed, exiting");
+            qemu::exit_success()
+        }
+        Err(number) => {
+            warn!("Self-test #{} failed, exiting with code {}", number, number);
+            qemu::exit_failure(number as u32)
+        }
+    }
+}
+
+/// Hang with IRQs masked, so that the heartbeat stops and the watchdog resets the board.
+fn hang(_tc: &ThreadContext) -> ! {
+    if bsp::watchdog::is_armed() {
+        warn!(
+            "Hanging on purpose, the watchdog resets the board within {:?}",
+            bsp::watchdog::DEFAULT_TIMEOUT
+        );
+    } else {
+        warn!("Hanging on purpose, but the watchdog is not armed. Build with the watchdog feature");
+    }
+
+    exception::local_irq_mask();
+    loop {
+        cpu::nop();
+    }
+}
+
+/// Run the demo with the given name, or list the available ones if there is none.
+pub fn run(name: &str, tc: &ThreadContext) -> ! {
+    if let Some(demo) = DEMOS.iter().find(|demo| demo.name == name) {
+        (demo.run)(tc)
+    }
+
+    warn!("Unknown demo '{}', available:", name);
+    for demo in DEMOS {
+        warn!("      {}", demo.name);
+    }
+
+    cpu::idle_loop();
+}
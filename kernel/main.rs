@@ -6,42 +6,106 @@
 
 extern crate alloc;
 
-use alloc::vec;
+use console::interface::Statistics;
+use exception::asynchronous::ThreadContext;
 use rp4os::*;
+use time::boot_timeline::BootPhase;
 
 mod boot;
+mod demos;
 
 /// Early init code.
 ///
 /// # Safety
 ///
 /// - Only a single core must be active and running this function.
-/// - The init calls in this function must appear in the correct order.
+/// - The init calls in this function must appear in the correct order. This is checked at
+///   runtime with [`state::InitPhase`] tracking.
 unsafe fn kernel_init() -> ! {
+    time::boot_timeline::mark(BootPhase::Start);
+
     exception::set_exception_vector();
 
     let phys_kernel_tables_base_addr = match memory::mmu::kernel_map_binary() {
         Err(string) => panic!("Error mapping kernel binary: {}", string),
         Ok(addr) => addr,
     };
+    time::boot_timeline::mark(BootPhase::MmuMapped);
 
     if let Err(e) = memory::mmu::enable_mmu_and_caching(phys_kernel_tables_base_addr) {
         panic!("Enabling MMU failed: {}", e);
     }
+    memory::mmu::verify_kernel_mappings();
+    time::boot_timeline::mark(BootPhase::MmuEnabled);
 
-    memory::post_enable_init();
+    if let Err(e) = memory::post_enable_init() {
+        panic!("Error initializing memory subsystem: {}", e);
+    }
+    time::boot_timeline::mark(BootPhase::MemoryInit);
 
     // Initialize the BSP driver subsystem.
     if let Err(x) = bsp::driver::init() {
         panic!("Error initializing BSP driver subsystem: {}", x);
     }
 
+    if let Err(e) = selftest::arm_init_faults() {
+        panic!("Error arming driver init faults: {}", e);
+    }
+
     // Initialize all device drivers.
     driver::DRIVER_MANAGER.init_drivers_and_irqs();
     // println! is usable from here on.
+    time::boot_timeline::mark(BootPhase::DriversInit);
 
-    // Unmask interrupts on the boot CPU core.
-    exception::local_irq_unmask();
+    #[cfg(feature = "gdbstub")]
+    if let Err(e) = gdbstub::init() {
+        warn!("Debugger breakpoints not available: {}", e);
+    }
+
+    // The translation tables can only be changed during init, with IRQs still masked.
+    selftest::run_init();
+
+    if let Some(boot_time) = bsp::boot_time() {
+        if let Err(e) = time::wall_clock::wall_clock().set(boot_time) {
+            warn!("Ignoring the boot time from the kernelloader: {}", e);
+        }
+    }
+
+    // Unmask interrupts on the boot CPU core, unless there is no working interrupt controller.
+    match bsp::driver::interrupt_controller_error() {
+        None => {
+            if let Err(e) = time::timer::init() {
+                panic!("Error initializing the timer: {}", e);
+            }
+            if let Err(e) = bsp::watchdog::start_heartbeat() {
+                panic!("Error starting the watchdog heartbeat: {}", e);
+            }
+            // Without the heartbeat, the watchdog would reset the board, so only arm it here.
+            #[cfg(feature = "watchdog")]
+            match bsp::watchdog::arm(bsp::watchdog::DEFAULT_TIMEOUT) {
+                Ok(()) => info!(
+                    "Watchdog armed, the board resets if the kernel hangs for {:?}",
+                    bsp::watchdog::DEFAULT_TIMEOUT
+                ),
+                Err(e) => warn!("Watchdog not armed: {}", e),
+            }
+
+            exception::local_irq_unmask();
+            time::boot_timeline::mark(BootPhase::IrqUnmasked);
+        }
+        Some(e) => {
+            warn!("!!! Interrupt controller failed to initialize: {} !!!", e);
+            warn!("!!! IRQs are disabled, falling back to polled operation !!!");
+        }
+    }
+
+    // Data that is only written during init is protected from here on. The tables can only be
+    // changed with IRQs masked.
+    if let Err(e) =
+        exception::asynchronous::exec_with_irq_masked(|| memory::mmu::mark_ro_after_init())
+    {
+        panic!("Error protecting read-only after init data: {}", e);
+    }
 
     // Announce conclusion of the kernel_init() phase.
     state::state_manager().transition_to_single_core_main();
@@ -52,12 +116,10 @@ unsafe fn kernel_init() -> ! {
 
 /// The main function running after the early init.
 fn kernel_main() -> ! {
-    info!(
-        "[0] {} version {}",
-        env!("CARGO_PKG_NAME"),
-        env!("CARGO_PKG_VERSION")
-    );
-    info!("[1] Booting on: {}", bsp::board_name());
+    // kernel_main() runs in thread context and never returns.
+    let tc = unsafe { ThreadContext::new() };
+
+    version::print_banner();
 
     info!("MMU online:");
     memory::mmu::kernel_print_mappings();
@@ -73,25 +135,55 @@ fn kernel_main() -> ! {
         time::resolution().as_nanos()
     );
 
-    info!("[2] Drivers loaded:");
-    driver::DRIVER_MANAGER.enumerate();
+    if let Some(now) = time::wall_clock() {
+        info!("Wall-clock time: {} UTC", now);
+    }
 
-    info!("Registered IRQ handlers:");
-    exception::asynchronous::irq_manager().print_handler();
+    info!("Clock rates:");
+    bsp::clock::print_rates();
+
+    info!("CPU caches:");
+    cpu::cache::print_info();
+
+    match (
+        bsp::thermal::temperature_millidegrees(),
+        bsp::thermal::max_temperature(),
+    ) {
+        (Ok(temp), Ok(max)) => info!(
+            "SoC temperature: {}.{} C (throttling at {}.{} C)",
+            temp / 1000,
+            temp % 1000 / 100,
+            max / 1000,
+            max % 1000 / 100
+        ),
+        (Err(e), _) | (_, Err(e)) => warn!("SoC temperature not available: {}", e),
+    }
 
-    info!("Kernel heap:");
-    memory::heap_alloc::KERNEL_HEAP_ALLOCATOR.print_usage();
+    info!("Boot timeline:");
+    time::boot_timeline::print();
 
-    {
-        let _numbers = vec![1, 2, 3, 4];
+    info!("[2] Drivers loaded:");
+    driver::DRIVER_MANAGER.enumerate();
 
-        info!("Kernel heap:");
-        memory::heap_alloc::KERNEL_HEAP_ALLOCATOR.print_usage();
-    }
+    info!("Self-tests:");
+    // Failures are printed by the tests. A headless run repeats them in the demo, together with
+    // the destructive ones, and exits with the result.
+    let _ = selftest::run_boot();
+
+    // The self-tests are the deepest calls of the boot sequence.
+    memory::stack::check_boot_core_stack();
 
     info!("Kernel heap:");
     memory::heap_alloc::KERNEL_HEAP_ALLOCATOR.print_usage();
 
-    info!("Echoing input now");
-    cpu::wait_forever();
+    let console = console::console();
+    info!("Console:");
+    info!(
+        "      Chars written: {}, Chars read: {}, RX overruns: {}",
+        console.chars_written(),
+        console.chars_read(),
+        console.rx_overruns()
+    );
+
+    demos::run(demos::DEFAULT_DEMO, &tc)
 }
@@ -7,10 +7,19 @@
 use rp4os::*;
 
 mod boot;
+mod xmodem;
 
 const KERNEL_LOAD_START_SIGNAL: u8 = 0x01;
 const KERNEL_LOAD_SIZE_ACK_SIGNAL: u8 = 0x02;
 const KERNEL_LOAD_ACK_SIGNAL: u8 = 0x03;
+const KERNEL_LOAD_PROGRESS_SIGNAL: u8 = 0x04;
+/// Like `KERNEL_LOAD_START_SIGNAL`, but the header carries the host's time after the size. Hosts
+/// that predate the timestamp send the plain start signal and keep working.
+const KERNEL_LOAD_START_WITH_TIME_SIGNAL: u8 = 0x05;
+const KERNEL_LOAD_SIZE_NAK_SIGNAL: u8 = 0x15;
+
+/// Number of received bytes after which a progress signal is sent to the host.
+const KERNEL_LOAD_PROGRESS_INTERVAL: usize = 64 * 1024;
 
 /// Early init code.
 ///
@@ -19,6 +28,9 @@ const KERNEL_LOAD_ACK_SIGNAL: u8 = 0x03;
 /// - Only a single core must be active and running this function.
 /// - The init calls in this function must appear in the correct order.
 unsafe fn kernel_init() -> ! {
+    // The loader runs with the MMU off and without a heap.
+    state::init_phase_tracker().skip_to(state::InitPhase::BspDriverInit);
+
     // Initialize the BSP driver subsystem.
     if let Err(x) = bsp::driver::init() {
         panic!("Error initializing BSP driver subsystem: {}", x);
@@ -32,19 +44,13 @@ unsafe fn kernel_init() -> ! {
     kernel_main()
 }
 
-/// The main function running after the early init.
-fn kernel_main() -> ! {
-    println!("[Loader] Loaded on {:^37}", bsp::board_name());
-    println!("[Loader]  Waiting for ready signal...");
-
+/// Receive the kernel with the custom protocol of `boot_console`, after its start signal was
+/// read. `with_time` tells if the start signal announced a timestamp in the header.
+///
+/// Returns the host's Unix time in seconds, zero if it sent none, or `None` if the upload was
+/// rejected or bytes were lost.
+fn receive_custom(kernel_addr: *mut u8, with_time: bool) -> Option<u64> {
     let console = console::console();
-    console.flush();
-
-    // Discard any spurious received characters before starting with the loader protocol.
-    console.clear_rx();
-
-    // Wait for ready signal
-    while console.read_char() as u8 != KERNEL_LOAD_START_SIGNAL {}
 
     // Read the binary's size.
     let mut size: u32 = u32::from(console.read_char() as u8);
@@ -52,26 +58,113 @@ fn kernel_main() -> ! {
     size |= u32::from(console.read_char() as u8) << 16;
     size |= u32::from(console.read_char() as u8) << 24;
 
+    // XOR of the size's bytes, to catch the most damaging corruption.
+    let size_checksum = console.read_char() as u8;
+    let size_valid = size_checksum == size.to_le_bytes().iter().fold(0, |acc, b| acc ^ b);
+
+    // Read the host's Unix time in seconds. Zero if the host did not provide one.
+    let mut timestamp: u64 = 0;
+    if with_time {
+        for i in 0..8 {
+            timestamp |= u64::from(console.read_char() as u8) << (8 * i);
+        }
+    }
+    let timestamp_received_at = time::uptime();
+
+    // A corrupt size must not make the loader write past DRAM or over itself. Reject it and wait
+    // for the host to start over.
+    let size = size as usize;
+    if !size_valid || !bsp::memory::load_range_valid(size) {
+        console.write_char(KERNEL_LOAD_SIZE_NAK_SIGNAL as char);
+        console.flush();
+        if size_valid {
+            println!("[Loader]  Rejected kernel size of {} bytes", size);
+        } else {
+            println!("[Loader]  Rejected kernel size, checksum mismatch");
+        }
+        return None;
+    }
+
     // Ack signal
     console.write_char(KERNEL_LOAD_SIZE_ACK_SIGNAL as char);
 
-    let kernel_addr: *mut u8 = bsp::memory::board_default_load_addr() as *mut u8;
+    let rx_overruns_before = console.rx_overruns();
+
     unsafe {
-        // Read the kernel byte by byte.
+        // Read the kernel byte by byte. The range was checked above.
         for i in 0..size {
-            core::ptr::write_volatile(kernel_addr.offset(i as isize), console.read_char() as u8)
+            core::ptr::write_volatile(kernel_addr.add(i), console.read_char() as u8);
+
+            // Lets the host show confirmed progress and know that the board is still receiving.
+            if (i + 1) % KERNEL_LOAD_PROGRESS_INTERVAL == 0 {
+                console.write_char(KERNEL_LOAD_PROGRESS_SIGNAL as char);
+            }
         }
     }
 
+    // The protocol has no checksum over the kernel. A lost byte shifts everything after it, so
+    // refuse to run it. The host takes anything but the ack as a failed upload.
+    let rx_overruns = console.rx_overruns() - rx_overruns_before;
+    if rx_overruns != 0 {
+        console.write_char(KERNEL_LOAD_SIZE_NAK_SIGNAL as char);
+        console.flush();
+        println!(
+            "[Loader]  Lost {} bytes of the kernel, upload again",
+            rx_overruns
+        );
+        return None;
+    }
+
     // Ack signal
     console.write_char(KERNEL_LOAD_ACK_SIGNAL as char);
     console.flush();
 
+    // Account for the time the upload took.
+    if timestamp != 0 {
+        timestamp += (time::uptime() - timestamp_received_at).as_secs();
+    }
+
+    Some(timestamp)
+}
+
+/// The main function running after the early init.
+fn kernel_main() -> ! {
+    println!("[Loader] Loaded on {:^37}", bsp::board_name());
+    println!("[Loader]  Waiting for ready signal...");
+    println!("[Loader]  Press 'C' to upload with XMODEM-CRC instead");
+
+    let console = console::console();
+    console.flush();
+
+    // Discard any spurious received characters before starting with the loader protocol.
+    console.clear_rx();
+
+    let kernel_addr: *mut u8 = bsp::memory::board_default_load_addr() as *mut u8;
+
+    // The first received byte selects the protocol.
+    let timestamp = loop {
+        match console.read_char() as u8 {
+            signal @ (KERNEL_LOAD_START_SIGNAL | KERNEL_LOAD_START_WITH_TIME_SIGNAL) => {
+                let with_time = signal == KERNEL_LOAD_START_WITH_TIME_SIGNAL;
+                if let Some(timestamp) = receive_custom(kernel_addr, with_time) {
+                    break timestamp;
+                }
+            }
+            xmodem::CRC_MODE | xmodem::NAK => match xmodem::receive(kernel_addr) {
+                // XMODEM does not transfer the time.
+                Ok(_) => break 0,
+                Err(x) => println!("[Loader]  XMODEM upload failed: {}", x),
+            },
+            _ => (),
+        }
+    };
+
     println!("[Loader]  Loaded! Executing the payload now\n");
 
-    // Use black magic to create a function pointer.
-    let kernel: fn() -> ! = unsafe { core::mem::transmute(kernel_addr) };
+    // Use black magic to create a function pointer. The kernel's boot code expects the timestamp
+    // in x1.
+    let kernel: extern "C" fn(u64, u64) -> ! = unsafe { core::mem::transmute(kernel_addr) };
 
     // Jump to loaded kernel!
-    kernel()
+    kernel(0, timestamp)
 }
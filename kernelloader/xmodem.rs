@@ -0,0 +1,169 @@
+//! XMODEM-CRC receiver.
+//!
+//! Allows uploading the kernel with standard terminal programs instead of `boot_console`. The
+//! receiver starts the transfer by repeatedly sending `C`, which asks the sender for 128 byte
+//! blocks protected by a CRC-16. Every block is answered with `ACK`, or with `NAK` to have it sent
+//! again. The sender ends the transfer with `EOT`.
+//!
+//! The last block is padded by the sender, so a few bytes of padding end up after the kernel.
+
+use core::time::Duration;
+use rp4os::{bsp, console, time};
+
+const SOH: u8 = 0x01;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+pub const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+/// Sent by the receiver to request a transfer with CRC-16 instead of the original checksum.
+pub const CRC_MODE: u8 = b'C';
+
+const BLOCK_SIZE: usize = 128;
+
+/// How long to wait for the sender after each `C`.
+const START_INTERVAL: Duration = Duration::from_secs(3);
+/// Number of `C`s to send before giving up.
+const START_RETRIES: usize = 20;
+/// How long to wait for each byte of a block.
+const BYTE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Number of consecutive bad blocks after which the transfer is canceled.
+const MAX_ERRORS: usize = 10;
+
+fn read_byte(timeout: Duration) -> Option<u8> {
+    let console = console::console();
+    let deadline = time::uptime() + timeout;
+
+    loop {
+        if let Some(c) = console.try_read_char() {
+            return Some(c as u8);
+        }
+
+        if time::uptime() >= deadline {
+            return None;
+        }
+    }
+}
+
+fn send_byte(byte: u8) {
+    console::console().write_char(byte as char);
+}
+
+/// Drop incoming bytes until the line is quiet, so that the sender is in sync again.
+fn purge() {
+    while read_byte(BYTE_TIMEOUT).is_some() {}
+}
+
+fn cancel() {
+    send_byte(CAN);
+    send_byte(CAN);
+    console::console().flush();
+}
+
+/// CRC-16/XMODEM: polynomial 0x1021, initial value 0.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, byte| {
+        let mut crc = crc ^ (u16::from(*byte) << 8);
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Read the rest of a block after its `SOH`. Returns the block number and fills `data`, or `None`
+/// if the block timed out or is corrupt.
+fn read_block(data: &mut [u8; BLOCK_SIZE]) -> Option<u8> {
+    let number = read_byte(BYTE_TIMEOUT)?;
+    let number_complement = read_byte(BYTE_TIMEOUT)?;
+
+    for byte in data.iter_mut() {
+        *byte = read_byte(BYTE_TIMEOUT)?;
+    }
+
+    let crc = (u16::from(read_byte(BYTE_TIMEOUT)?) << 8) | u16::from(read_byte(BYTE_TIMEOUT)?);
+
+    if number != !number_complement || crc != crc16(data) {
+        return None;
+    }
+
+    Some(number)
+}
+
+/// Wait for the sender to start the transfer. Returns the first byte it sent.
+fn start() -> Result<u8, &'static str> {
+    for _ in 0..START_RETRIES {
+        send_byte(CRC_MODE);
+
+        if let Some(byte) = read_byte(START_INTERVAL) {
+            return Ok(byte);
+        }
+    }
+
+    Err("Sender did not start the transfer")
+}
+
+/// Receive a file to `dest` and return the number of bytes written, including the padding of the
+/// last block.
+pub fn receive(dest: *mut u8) -> Result<usize, &'static str> {
+    let mut data = [0u8; BLOCK_SIZE];
+    let mut expected: u8 = 1;
+    let mut received = 0;
+    let mut errors = 0;
+
+    let mut header = Some(start()?);
+
+    loop {
+        let block = match header {
+            Some(SOH) => read_block(&mut data),
+            Some(EOT) => {
+                send_byte(ACK);
+                console::console().flush();
+                return Ok(received);
+            }
+            Some(CAN) => return Err("Transfer canceled by the sender"),
+            _ => None,
+        };
+
+        match block {
+            Some(number) if number == expected => {
+                // Same check as for the custom protocol: never write past DRAM or over the loader.
+                if !bsp::memory::load_range_valid(received + BLOCK_SIZE) {
+                    cancel();
+                    return Err("Kernel does not fit into memory");
+                }
+
+                for (i, byte) in data.iter().enumerate() {
+                    unsafe { core::ptr::write_volatile(dest.add(received + i), *byte) };
+                }
+
+                received += BLOCK_SIZE;
+                expected = expected.wrapping_add(1);
+                errors = 0;
+                send_byte(ACK);
+            }
+            // The sender missed our ACK and repeated the previous block.
+            Some(number) if number == expected.wrapping_sub(1) => send_byte(ACK),
+            Some(_) => {
+                cancel();
+                return Err("Block sequence error");
+            }
+            None => {
+                errors += 1;
+                if errors > MAX_ERRORS {
+                    cancel();
+                    return Err("Too many bad blocks");
+                }
+
+                purge();
+                // Until the first block arrived, keep asking for CRC mode.
+                send_byte(if received == 0 { CRC_MODE } else { NAK });
+            }
+        }
+
+        header = read_byte(START_INTERVAL);
+    }
+}
@@ -0,0 +1,33 @@
+use super::Domain;
+use aarch64_cpu::asm::barrier;
+
+/// Data Memory Barrier: orders memory accesses before the barrier against the ones after it, as
+/// observed by the agents of the given domain.
+#[inline(always)]
+pub fn dmb(domain: Domain) {
+    match domain {
+        Domain::SY => barrier::dmb(barrier::SY),
+        Domain::ISH => barrier::dmb(barrier::ISH),
+        Domain::OSH => barrier::dmb(barrier::OSH),
+        Domain::NSH => barrier::dmb(barrier::NSH),
+    }
+}
+
+/// Data Synchronization Barrier: waits until all memory accesses, cache and TLB maintenance
+/// before the barrier have completed for the given domain.
+#[inline(always)]
+pub fn dsb(domain: Domain) {
+    match domain {
+        Domain::SY => barrier::dsb(barrier::SY),
+        Domain::ISH => barrier::dsb(barrier::ISH),
+        Domain::OSH => barrier::dsb(barrier::OSH),
+        Domain::NSH => barrier::dsb(barrier::NSH),
+    }
+}
+
+/// Instruction Synchronization Barrier: flushes the pipeline, so that instructions after the
+/// barrier observe all context changes, e.g. system register writes, made before it.
+#[inline(always)]
+pub fn isb() {
+    barrier::isb(barrier::SY)
+}
@@ -0,0 +1,149 @@
+use super::{CacheGeometry, CacheKind, CacheLevel};
+use crate::{
+    cpu::barrier::{self, Domain},
+    memory::{Address, Virtual},
+};
+use aarch64_cpu::registers::*;
+use core::arch::{asm, global_asm};
+use tock_registers::interfaces::Readable;
+
+// Assembly counterpart to this file.
+global_asm!(include_str!("cache.s"));
+
+// Provided by cache.s.
+extern "C" {
+    fn __clean_invalidate_dcache_all();
+}
+
+/// Maximum number of cache levels described by CLIDR_EL1.
+const MAX_LEVELS: u8 = 7;
+
+/// Return the size of the smallest data cache line in the system in bytes.
+#[inline(always)]
+fn dcache_line_size() -> usize {
+    // DminLine is the log2 of the number of 4 byte words in a line.
+    4 << CTR_EL0.read(CTR_EL0::DminLine)
+}
+
+/// Return the size of the smallest instruction cache line in the system in bytes.
+#[inline(always)]
+fn icache_line_size() -> usize {
+    4 << CTR_EL0.read(CTR_EL0::IminLine)
+}
+
+/// Return the smallest cache line size of all data and instruction caches in bytes.
+///
+/// Maintenance by address steps through memory with this size, so that no line is skipped.
+#[inline(always)]
+pub fn line_size() -> usize {
+    dcache_line_size().min(icache_line_size())
+}
+
+fn read_clidr() -> u64 {
+    let clidr: u64;
+    unsafe { asm!("mrs {}, CLIDR_EL1", out(reg) clidr, options(nomem, nostack)) };
+
+    clidr
+}
+
+/// Return the implemented cache levels, starting at level 1.
+pub fn levels() -> impl Iterator<Item = CacheLevel> {
+    let clidr = read_clidr();
+
+    (0..MAX_LEVELS)
+        .map(move |level| {
+            // Ctype<n> is 3 bits per level, starting at bit 0.
+            let kind = match (clidr >> (level * 3)) & 0b111 {
+                0b001 => Some(CacheKind::Instruction),
+                0b010 => Some(CacheKind::Data),
+                0b011 => Some(CacheKind::Separate),
+                0b100 => Some(CacheKind::Unified),
+                _ => None,
+            };
+
+            kind.map(|kind| CacheLevel {
+                level: level + 1,
+                kind,
+            })
+        })
+        .take_while(|level| level.is_some())
+        .flatten()
+}
+
+/// Return the geometry of the data or unified cache, or the instruction cache, at the given level.
+pub fn geometry(level: u8, instruction: bool) -> CacheGeometry {
+    // CSSELR_EL1: Level is bits [3:1] and zero based, InD is bit 0.
+    let csselr = (u64::from(level - 1) << 1) | u64::from(instruction);
+    let ccsidr: u64;
+
+    unsafe {
+        asm!("msr CSSELR_EL1, {}", in(reg) csselr, options(nostack));
+        // Make the selection visible to the CCSIDR_EL1 read.
+        barrier::isb();
+        asm!("mrs {}, CCSIDR_EL1", out(reg) ccsidr, options(nomem, nostack));
+    }
+
+    // Layout without FEAT_CCIDX, which the Cortex-A72 does not implement:
+    // LineSize [2:0] is log2(bytes) - 4, Associativity [12:3] and NumSets [27:13] are minus one.
+    CacheGeometry {
+        line_size: 1 << ((ccsidr & 0b111) + 4),
+        associativity: ((ccsidr >> 3) & 0x3FF) as usize + 1,
+        num_sets: ((ccsidr >> 13) & 0x7FFF) as usize + 1,
+    }
+}
+
+/// Clean and invalidate the data cache lines covering the given range to the point of coherency.
+///
+/// Afterwards, the memory holds the latest data and no stale copies are left in the caches, so
+/// that the range can be accessed with caching disabled or by a non-coherent agent.
+pub fn clean_invalidate_dcache_range(start: Address<Virtual>, size: usize) {
+    let line_size = line_size();
+    let end = start.as_usize() + size;
+    let mut addr = crate::align_down(start.as_usize(), line_size);
+
+    while addr < end {
+        unsafe { asm!("dc civac, {}", in(reg) addr, options(nostack)) };
+        addr += line_size;
+    }
+
+    barrier::dsb(Domain::SY);
+}
+
+/// Invalidate the data cache lines covering the given range to the point of coherency, without
+/// writing them back.
+///
+/// Used for memory that was written with data caching off, so that memory holds the latest data.
+/// A clean would instead write stale dirty lines over it. Lines only partially covered by the range
+/// are dropped as a whole, so the range should be cache line aligned.
+pub fn invalidate_dcache_range(start: Address<Virtual>, size: usize) {
+    let line_size = line_size();
+    let end = start.as_usize() + size;
+    let mut addr = crate::align_down(start.as_usize(), line_size);
+
+    while addr < end {
+        unsafe { asm!("dc ivac, {}", in(reg) addr, options(nostack)) };
+        addr += line_size;
+    }
+
+    barrier::dsb(Domain::SY);
+}
+
+/// Clean and invalidate all data and unified caches of the executing core to the point of
+/// coherency, by set/way.
+///
+/// Set/way operations are not broadcast to other cores and race with speculative fills, so this is
+/// only meant for turning caches off or on, not for sharing data. To leave no dirty lines behind,
+/// data caching must already be disabled in SCTLR_EL1, otherwise any memory access after the call
+/// can allocate lines again.
+pub fn clean_invalidate_all_data() {
+    unsafe { __clean_invalidate_dcache_all() }
+}
+
+/// Invalidate the whole instruction cache to the point of unification.
+#[inline(always)]
+pub fn invalidate_icache() {
+    unsafe { asm!("ic iallu", options(nostack)) };
+
+    barrier::dsb(Domain::SY);
+    barrier::isb();
+}
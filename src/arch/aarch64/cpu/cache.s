@@ -0,0 +1,78 @@
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+.section .text
+
+//------------------------------------------------------------------------------
+// fn __clean_invalidate_dcache_all()
+//
+// Clean and invalidate all data and unified caches up to the point of coherency by set/way.
+//
+// Follows "Example code for cache maintenance instructions" from the ARMv8-A Architecture Reference
+// Manual. Only x0-x11 are used and no memory is accessed, so the routine can run while data
+// caching is being turned off.
+//------------------------------------------------------------------------------
+__clean_invalidate_dcache_all:
+	// Level of Coherency: the number of levels that need maintenance. x3 = LoC << 1.
+	mrs	x0, CLIDR_EL1
+	ubfx	x3, x0, #24, #3
+	lsl	x3, x3, #1
+	cbz	x3, .L_finished
+
+	// x10 = current level << 1, as used by CSSELR_EL1 and the set/way operand.
+	mov	x10, #0
+
+.L_level_loop:
+	// Ctype for this level: CLIDR_EL1 >> (level * 3).
+	add	x2, x10, x10, lsr #1
+	lsr	x1, x0, x2
+	and	x1, x1, #7
+	// Skip levels with no cache or only an instruction cache.
+	cmp	x1, #2
+	b.lt	.L_next_level
+
+	// Select the data or unified cache of this level and read its geometry.
+	msr	CSSELR_EL1, x10
+	isb
+	mrs	x1, CCSIDR_EL1
+
+	// x2 = log2(line size in bytes), the set field position.
+	and	x2, x1, #7
+	add	x2, x2, #4
+	// x4 = number of ways - 1.
+	ubfx	x4, x1, #3, #10
+	// x5 = the way field position, which is left aligned in the operand.
+	clz	w5, w4
+	// x7 = number of sets - 1.
+	ubfx	x7, x1, #13, #15
+
+.L_set_loop:
+	mov	x9, x4
+
+.L_way_loop:
+	// Operand = way << x5 | set << x2 | level << 1.
+	lsl	x6, x9, x5
+	orr	x11, x10, x6
+	lsl	x6, x7, x2
+	orr	x11, x11, x6
+	dc	cisw, x11
+	subs	x9, x9, #1
+	b.ge	.L_way_loop
+	subs	x7, x7, #1
+	b.ge	.L_set_loop
+
+.L_next_level:
+	add	x10, x10, #2
+	cmp	x3, x10
+	b.gt	.L_level_loop
+
+.L_finished:
+	// Restore the cache size selection to level 1 and wait for the maintenance to complete.
+	msr	CSSELR_EL1, xzr
+	dsb	sy
+	isb
+	ret
+
+.size	__clean_invalidate_dcache_all, . - __clean_invalidate_dcache_all
+.type	__clean_invalidate_dcache_all, function
+.global	__clean_invalidate_dcache_all
@@ -2,6 +2,14 @@ use aarch64_cpu::asm;
 
 pub use asm::nop;
 
+/// Put the core into low-power state until an interrupt arrives.
+///
+/// Wakes up on pending interrupts even if they are masked in PSTATE.
+#[inline(always)]
+pub fn wait_for_interrupt() {
+    asm::wfi()
+}
+
 /// Pause execution on the core.
 #[inline(always)]
 pub fn wait_forever() -> ! {
@@ -9,3 +17,41 @@ pub fn wait_forever() -> ! {
         asm::wfe()
     }
 }
+
+/// Jump to `entry` and never return.
+///
+/// # Safety
+///
+/// - `entry` must point to valid code that does not expect any state from the caller.
+pub unsafe fn jump_to(entry: usize) -> ! {
+    core::arch::asm!("br {}", in(reg) entry, options(noreturn, nostack))
+}
+
+/// Enable or disable software stepping of EL1 code.
+///
+/// While enabled, an exception return with SPSR_EL1.SS set executes a single instruction and then
+/// takes a software step exception. The OS lock is released as well, since it blocks all debug
+/// exceptions after a cold reset.
+pub fn set_single_step(enable: bool) {
+    // MDSCR_EL1.SS enables stepping, MDSCR_EL1.KDE debug exceptions taken from EL1 to EL1.
+    const MDSCR_SS_KDE: u64 = (1 << 0) | (1 << 13);
+
+    let mut mdscr: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, MDSCR_EL1", out(reg) mdscr, options(nomem, nostack));
+
+        if enable {
+            mdscr |= MDSCR_SS_KDE;
+        } else {
+            mdscr &= !MDSCR_SS_KDE;
+        }
+
+        core::arch::asm!(
+            "msr OSLAR_EL1, xzr",
+            "msr MDSCR_EL1, {}",
+            "isb",
+            in(reg) mdscr,
+            options(nostack)
+        );
+    }
+}
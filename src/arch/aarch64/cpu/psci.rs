@@ -0,0 +1,30 @@
+use core::arch::asm;
+
+/// Issue a PSCI call through the Secure Monitor Call conduit.
+///
+/// Follows the SMC64 calling convention: function id and arguments are passed in x0-x3, the result
+/// is returned in x0, and x4-x17 are not preserved by older firmware.
+///
+/// # Safety
+///
+/// - The firmware must implement an SMC handler. Otherwise the `smc` instruction is undefined and
+///   traps.
+#[inline(always)]
+pub unsafe fn call(function_id: u32, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+    let ret: i64;
+
+    asm!(
+        "smc #0",
+        inlateout("x0") function_id as u64 => ret,
+        inlateout("x1") arg0 => _,
+        inlateout("x2") arg1 => _,
+        inlateout("x3") arg2 => _,
+        lateout("x4") _, lateout("x5") _, lateout("x6") _, lateout("x7") _,
+        lateout("x8") _, lateout("x9") _, lateout("x10") _, lateout("x11") _,
+        lateout("x12") _, lateout("x13") _, lateout("x14") _, lateout("x15") _,
+        lateout("x16") _, lateout("x17") _,
+        options(nostack)
+    );
+
+    ret
+}
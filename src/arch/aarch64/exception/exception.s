@@ -7,7 +7,7 @@
 .macro CALL_WITH_CONTEXT handler
 __vector_\handler:
 	// Make room on the stack for the exception context.
-	sub	sp,  sp,  #16 * 17
+	sub	sp,  sp,  #16 * 18
 
 	// Store all general purpose registers on the stack.
 	stp	x0,  x1,  [sp, #16 * 0]
@@ -26,14 +26,16 @@ __vector_\handler:
 	stp	x26, x27, [sp, #16 * 13]
 	stp	x28, x29, [sp, #16 * 14]
 
-	// Add the exception link register (ELR_EL1), saved program status (SPSR_EL1) and exception
-	// syndrome register (ESR_EL1).
+	// Add the exception link register (ELR_EL1), saved program status (SPSR_EL1), exception
+	// syndrome register (ESR_EL1) and the stack pointer from before the context was pushed.
 	mrs	x1,  ELR_EL1
 	mrs	x2,  SPSR_EL1
 	mrs	x3,  ESR_EL1
+	add	x4,  sp,  #16 * 18
 
 	stp	lr,  x1,  [sp, #16 * 15]
 	stp	x2,  x3,  [sp, #16 * 16]
+	str	x4,       [sp, #16 * 17]
 
 	// x0 is the first argument for the function called through `\handler`.
 	mov	x0,  sp
@@ -49,6 +51,18 @@ __vector_\handler:
 .type	__vector_\handler, function
 .endm
 
+/// Continue at `__stack_overflow` if the stack pointer lies below the boot core stack, where the
+/// exception context can not be saved. TPIDR_EL1 is otherwise unused and serves as scratch
+/// register, so that no register of the interrupted code is lost.
+.macro BRANCH_ON_STACK_OVERFLOW
+	msr	TPIDR_EL1, x0
+	adrp	x0,  __boot_core_stack_start
+	add	x0,  x0,  #:lo12:__boot_core_stack_start
+	cmp	sp,  x0
+	mrs	x0,  TPIDR_EL1
+	b.lo	__stack_overflow
+.endm
+
 .macro FIQ_SUSPEND
 1:	wfe
 	b	1b
@@ -69,7 +83,8 @@ __exception_vector_start:
 //
 // # Safety
 //
-// - It must be ensured that `CALL_WITH_CONTEXT` <= 0x80 bytes.
+// - It must be ensured that `CALL_WITH_CONTEXT`, plus `BRANCH_ON_STACK_OVERFLOW` where used, is
+//   <= 0x80 bytes.
 .org 0x000
 	CALL_WITH_CONTEXT current_el0_synchronous
 .org 0x080
@@ -81,6 +96,7 @@ __exception_vector_start:
 
 // Current exception level with SP_ELx, x > 0.
 .org 0x200
+	BRANCH_ON_STACK_OVERFLOW
 	CALL_WITH_CONTEXT current_elx_synchronous
 .org 0x280
 	CALL_WITH_CONTEXT current_elx_irq
@@ -110,6 +126,58 @@ __exception_vector_start:
 	CALL_WITH_CONTEXT lower_aarch32_serror
 .org 0x800
 
+//------------------------------------------------------------------------------
+// Stack overflow
+//------------------------------------------------------------------------------
+// The stack ran into its guard page. Save the context on the overflow stack in SP_EL0 instead,
+// which is set up by `set_exception_vector()`. The handler never returns.
+__stack_overflow:
+	msr	SPSel, #0
+	CALL_WITH_CONTEXT current_elx_stack_overflow
+
+//------------------------------------------------------------------------------
+// fn __exception_capture_context(context: *mut ExceptionContext)
+//------------------------------------------------------------------------------
+// Store the caller's registers in the layout of `CALL_WITH_CONTEXT`, for register dumps outside
+// of exceptions. x0 holds the argument, the ELR_EL1 slot the return address and the SPSR_EL1 slot
+// the current PSTATE. ESR_EL1 is stored as zero.
+__exception_capture_context:
+	stp	x0,  x1,  [x0, #16 * 0]
+	stp	x2,  x3,  [x0, #16 * 1]
+	stp	x4,  x5,  [x0, #16 * 2]
+	stp	x6,  x7,  [x0, #16 * 3]
+	stp	x8,  x9,  [x0, #16 * 4]
+	stp	x10, x11, [x0, #16 * 5]
+	stp	x12, x13, [x0, #16 * 6]
+	stp	x14, x15, [x0, #16 * 7]
+	stp	x16, x17, [x0, #16 * 8]
+	stp	x18, x19, [x0, #16 * 9]
+	stp	x20, x21, [x0, #16 * 10]
+	stp	x22, x23, [x0, #16 * 11]
+	stp	x24, x25, [x0, #16 * 12]
+	stp	x26, x27, [x0, #16 * 13]
+	stp	x28, x29, [x0, #16 * 14]
+
+	// PSTATE in the SPSR format. The flags, the mask bits and the mode bits (M[3:2] = EL,
+	// M[0] = SPSel) are at the same positions in the special registers that hold them.
+	mrs	x1,  NZCV
+	mrs	x2,  DAIF
+	orr	x1,  x1,  x2
+	mrs	x2,  CurrentEL
+	orr	x1,  x1,  x2
+	mrs	x2,  SPSel
+	orr	x1,  x1,  x2
+	mov	x2,  sp
+
+	stp	lr,  lr,  [x0, #16 * 15]
+	stp	x1,  xzr, [x0, #16 * 16]
+	str	x2,       [x0, #16 * 17]
+
+	ret
+
+.size	__exception_capture_context, . - __exception_capture_context
+.type	__exception_capture_context, function
+
 //------------------------------------------------------------------------------
 // fn __exception_restore_context()
 //------------------------------------------------------------------------------
@@ -136,7 +204,7 @@ __exception_restore_context:
 	ldp	x26, x27, [sp, #16 * 13]
 	ldp	x28, x29, [sp, #16 * 14]
 
-	add	sp,  sp,  #16 * 17
+	add	sp,  sp,  #16 * 18
 
 	eret
 
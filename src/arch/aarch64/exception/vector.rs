@@ -1,6 +1,14 @@
-use crate::exception::asynchronous::{irq_manager, IRQContext};
-use aarch64_cpu::{asm::barrier, registers::*};
-use core::{arch::global_asm, cell::UnsafeCell, fmt};
+#[cfg(feature = "gdbstub")]
+use crate::gdbstub::{self, GdbRegisters};
+use crate::{
+    cpu::barrier,
+    exception::asynchronous::{irq_manager, IRQContext},
+    info,
+    memory::{safe_access, Address},
+    trace,
+};
+use aarch64_cpu::registers::*;
+use core::{arch::global_asm, cell::UnsafeCell, fmt, mem::MaybeUninit};
 use tock_registers::{
     interfaces::{Readable, Writeable},
     registers::InMemoryRegister,
@@ -9,7 +17,18 @@ use tock_registers::{
 // Assembly counterpart to this file.
 global_asm!(include_str!("exception.s"));
 
-/// Init exception handling by setting the exception vector base address register.
+/// Size of the stack that a stack overflow is reported on.
+const OVERFLOW_STACK_SIZE: usize = 16 * 1024;
+
+#[repr(C, align(16))]
+struct OverflowStack([u8; OVERFLOW_STACK_SIZE]);
+
+/// The stack the exception handler switches to when the boot core stack overflowed, since the
+/// exception context can not be saved on the overflowed stack itself.
+static mut OVERFLOW_STACK: OverflowStack = OverflowStack([0; OVERFLOW_STACK_SIZE]);
+
+/// Init exception handling by setting the exception vector base address register and the overflow
+/// stack.
 ///
 /// # Safety
 ///
@@ -25,8 +44,12 @@ pub unsafe fn set_exception_vector() {
 
     VBAR_EL1.set(__exception_vector_start.get() as u64);
 
+    // The kernel runs on SP_EL1, so SP_EL0 is free to hold the overflow stack.
+    let overflow_stack = core::ptr::addr_of_mut!(OVERFLOW_STACK) as u64;
+    SP_EL0.set(overflow_stack + OVERFLOW_STACK_SIZE as u64);
+
     // Force VBAR update to complete before next instruction.
-    barrier::isb(barrier::SY);
+    barrier::isb();
 }
 
 /// Wrapper structs for memory copies of registers.
@@ -40,6 +63,41 @@ impl EsrEL1 {
     fn exception_class(&self) -> Option<ESR_EL1::EC::Value> {
         self.0.read_as_enum(ESR_EL1::EC)
     }
+
+    #[inline(always)]
+    fn is_abort(&self) -> bool {
+        use ESR_EL1::EC::Value::*;
+
+        matches!(
+            self.exception_class(),
+            Some(InstrAbortLowerEL | InstrAbortCurrentEL | DataAbortLowerEL | DataAbortCurrentEL)
+        )
+    }
+
+    /// For aborts, whether FAR_EL1 holds the faulting address. It does unless the FnV bit is set.
+    #[inline(always)]
+    fn abort_far_valid(&self) -> bool {
+        self.0.read(ESR_EL1::ISS) & ISS_ABORT_FNV == 0
+    }
+}
+
+/// Bits of the ISS of instruction and data aborts.
+const ISS_ABORT_FNV: u64 = 1 << 10;
+const ISS_DATA_ABORT_WNR: u64 = 1 << 6;
+const ISS_ABORT_FSC_MASK: u64 = 0b11_1111;
+
+/// Describe the fault status code of an instruction or data abort.
+fn fault_status_description(fsc: u64) -> &'static str {
+    match fsc {
+        0b00_0000..=0b00_0011 => "Address size fault",
+        0b00_0100..=0b00_0111 => "Translation fault",
+        0b00_1001..=0b00_1011 => "Access flag fault",
+        0b00_1101..=0b00_1111 => "Permission fault",
+        0b01_0000 => "Synchronous external abort",
+        0b10_0001 => "Alignment fault",
+        0b11_0000 => "TLB conflict abort",
+        _ => "N/A",
+    }
 }
 
 #[rustfmt::skip]
@@ -85,17 +143,53 @@ impl core::fmt::Display for EsrEL1 {
 
         // Exception class.
         let ec_translation = match self.exception_class() {
+            Some(ESR_EL1::EC::Value::Unknown) => "Unknown reason, e.g. undefined instruction",
+            Some(ESR_EL1::EC::Value::SVC64) => "SVC instruction, AArch64",
+            Some(ESR_EL1::EC::Value::InstrAbortLowerEL) => "Instruction Abort, lower EL",
+            Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => "Instruction Abort, current EL",
+            Some(ESR_EL1::EC::Value::PCAlignmentFault) => "PC alignment fault",
+            Some(ESR_EL1::EC::Value::DataAbortLowerEL) => "Data Abort, lower EL",
             Some(ESR_EL1::EC::Value::DataAbortCurrentEL) => "Data Abort, current EL",
+            Some(ESR_EL1::EC::Value::SPAlignmentFault) => "SP alignment fault",
+            Some(ESR_EL1::EC::Value::SoftwareStepCurrentEL) => "Software step, current EL",
+            Some(ESR_EL1::EC::Value::Brk64) => "BRK instruction, AArch64",
             _ => "N/A",
         };
         writeln!(f, " - {}", ec_translation)?;
 
         // Raw print of instruction specific syndrome.
-        write!(f, "Instr Specific Syndrome (ISS): {:#x}", self.0.read(ESR_EL1::ISS))
+        let iss = self.0.read(ESR_EL1::ISS);
+        write!(f, "Instr Specific Syndrome (ISS): {:#x}", iss)?;
+
+        // Decoded syndrome of the exception classes that are most likely to be seen.
+        if self.is_abort() {
+            let fsc = iss & ISS_ABORT_FSC_MASK;
+            write!(f, "\n      Fault Status Code: {:#x} - {}", fsc, fault_status_description(fsc))?;
+
+            // The fault status codes up to the permission faults encode the lookup level.
+            if fsc <= 0b00_1111 {
+                write!(f, ", level {}", fsc & 0b11)?;
+            }
+
+            if matches!(
+                self.exception_class(),
+                Some(ESR_EL1::EC::Value::DataAbortLowerEL | ESR_EL1::EC::Value::DataAbortCurrentEL)
+            ) {
+                let access = if iss & ISS_DATA_ABORT_WNR != 0 { "Write" } else { "Read" };
+                write!(f, "\n      Access: {}", access)?;
+            }
+        } else if self.exception_class() == Some(ESR_EL1::EC::Value::SVC64) {
+            write!(f, "\n      SVC immediate: {:#x}", iss & 0xFFFF)?;
+        }
+
+        Ok(())
     }
 }
 
 /// The exception context as it is stored on the stack on exception entry.
+///
+/// The layout must match the stores of `CALL_WITH_CONTEXT` and `__exception_capture_context` in
+/// exception.s, which is checked at compile time below.
 #[repr(C)]
 struct ExceptionContext {
     /// General Purpose Registers.
@@ -112,8 +206,28 @@ struct ExceptionContext {
 
     /// Exception syndrome register.
     esr_el1: EsrEL1,
+
+    /// The stack pointer at the time the exception happened. For exceptions from a lower EL, and
+    /// for a stack overflow, it is the stack pointer the context was pushed to instead.
+    sp: u64,
+
+    /// Keeps the size a multiple of 16, as required for the stack pointer.
+    _padding: u64,
 }
 
+// Offsets used by exception.s.
+const _: () = {
+    use core::mem::{offset_of, size_of};
+
+    assert!(offset_of!(ExceptionContext, gpr) == 0);
+    assert!(offset_of!(ExceptionContext, lr) == 16 * 15);
+    assert!(offset_of!(ExceptionContext, elr_el1) == 16 * 15 + 8);
+    assert!(offset_of!(ExceptionContext, spsr_el1) == 16 * 16);
+    assert!(offset_of!(ExceptionContext, esr_el1) == 16 * 16 + 8);
+    assert!(offset_of!(ExceptionContext, sp) == 16 * 17);
+    assert!(size_of::<ExceptionContext>() == 16 * 18);
+};
+
 impl ExceptionContext {
     #[inline(always)]
     fn exception_class(&self) -> Option<ESR_EL1::EC::Value> {
@@ -124,33 +238,25 @@ impl ExceptionContext {
     fn fault_address_valid(&self) -> bool {
         use ESR_EL1::EC::Value::*;
 
+        if self.esr_el1.is_abort() {
+            return self.esr_el1.abort_far_valid();
+        }
+
         match self.exception_class() {
             None => false,
             Some(ec) => matches!(
                 ec,
-                InstrAbortLowerEL
-                    | InstrAbortCurrentEL
-                    | PCAlignmentFault
-                    | DataAbortLowerEL
-                    | DataAbortCurrentEL
-                    | WatchpointLowerEL
-                    | WatchpointCurrentEL
+                PCAlignmentFault | WatchpointLowerEL | WatchpointCurrentEL
             ),
         }
     }
-}
-
-/// Human readable print of the exception context.
-impl fmt::Display for ExceptionContext {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "{}", self.esr_el1)?;
-
-        if self.fault_address_valid() {
-            writeln!(f, "FAR_EL1: {:#018x}", FAR_EL1.get() as usize)?;
-        }
 
+    /// Print the program status, the program counter, the stack pointer and the general purpose
+    /// registers.
+    fn fmt_registers(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "{}", self.spsr_el1)?;
         writeln!(f, "ELR_EL1: {:#018x}", self.elr_el1)?;
+        writeln!(f, "SP     : {:#018x}", self.sp)?;
         writeln!(f)?;
         writeln!(f, "General purpose register:")?;
 
@@ -167,6 +273,100 @@ impl fmt::Display for ExceptionContext {
     }
 }
 
+/// Human readable print of the exception context.
+impl fmt::Display for ExceptionContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.esr_el1)?;
+
+        if self.fault_address_valid() {
+            let far = FAR_EL1.get() as usize;
+            writeln!(f, "FAR_EL1: {:#018x}", far)?;
+
+            if let Some(description) =
+                crate::bsp::memory::mmu::guard_region_fault(Address::new(far))
+            {
+                writeln!(f, "{}", description)?;
+            }
+        }
+
+        self.fmt_registers(f)
+    }
+}
+
+/// The registers of the code that captured them, in the layout of an exception context.
+struct RegisterSnapshot(ExceptionContext);
+
+impl RegisterSnapshot {
+    /// Capture the registers of the caller.
+    ///
+    /// x0 holds the address of the snapshot and ELR_EL1 the return address of the capture
+    /// routine.
+    fn capture() -> Self {
+        // Provided by exception.s.
+        extern "C" {
+            fn __exception_capture_context(context: *mut ExceptionContext);
+        }
+
+        let mut context = MaybeUninit::<ExceptionContext>::uninit();
+        unsafe {
+            __exception_capture_context(context.as_mut_ptr());
+            Self(context.assume_init())
+        }
+    }
+}
+
+impl fmt::Display for RegisterSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "PSTATE shown as SPSR_EL1, program counter as ELR_EL1.")?;
+        self.0.fmt_registers(f)
+    }
+}
+
+/// Print the registers of the caller, e.g. from the panic handler.
+pub fn print_register_snapshot() {
+    info!("Register snapshot:\n{}", RegisterSnapshot::capture());
+}
+
+/// Hand a breakpoint or software step exception to the gdb stub and resume with the registers it
+/// returns.
+#[cfg(feature = "gdbstub")]
+fn enter_debugger(e: &mut ExceptionContext) {
+    let mut regs = GdbRegisters::default();
+
+    regs.x[..30].copy_from_slice(&e.gpr);
+    regs.x[30] = e.lr;
+    regs.sp = e.sp;
+    regs.pc = e.elr_el1;
+    regs.cpsr = e.spsr_el1.0.get() as u32;
+
+    gdbstub::handle_exception(&mut regs);
+
+    e.gpr.copy_from_slice(&regs.x[..30]);
+    e.lr = regs.x[30];
+    e.elr_el1 = regs.pc;
+    e.spsr_el1.0.set(u64::from(regs.cpsr));
+
+    // Unless the debugger moved the PC, it still points at the `brk`, which would trap again. A
+    // breakpoint of the debugger is removed by the debugger itself before it resumes, and after a
+    // step the PC points at an instruction that was not executed yet.
+    if e.exception_class() != Some(ESR_EL1::EC::Value::Brk64)
+        || gdbstub::is_inserted_breakpoint(e.elr_el1 as usize)
+    {
+        return;
+    }
+
+    let instruction = unsafe { core::ptr::read_volatile(e.elr_el1 as *const u32) };
+    if instruction & BRK_MASK == BRK_OPCODE {
+        e.elr_el1 += 4;
+    }
+}
+
+/// Encoding of `brk #imm16`, with the immediate masked out.
+#[cfg(feature = "gdbstub")]
+const BRK_MASK: u32 = 0xFFE0_001F;
+#[cfg(feature = "gdbstub")]
+const BRK_OPCODE: u32 = 0xD420_0000;
+
 /// Prints verbose information about the exception and then panics.
 fn default_exception_handler(exc: &ExceptionContext) {
     panic!(
@@ -193,13 +393,47 @@ extern "C" fn current_el0_serror(_e: &mut ExceptionContext) {
 
 #[no_mangle]
 extern "C" fn current_elx_synchronous(e: &mut ExceptionContext) {
+    // Without the gdb stub, a `brk` ends in the default handler like any other unexpected
+    // exception.
+    #[cfg(feature = "gdbstub")]
+    if matches!(
+        e.exception_class(),
+        Some(ESR_EL1::EC::Value::Brk64 | ESR_EL1::EC::Value::SoftwareStepCurrentEL)
+    ) {
+        enter_debugger(e);
+        return;
+    }
+
+    // A fault of `memory::safe_read32()` and friends resumes at their recovery code.
+    if e.exception_class() == Some(ESR_EL1::EC::Value::DataAbortCurrentEL) {
+        if let Some(recovery_addr) = safe_access::take_fault_recovery() {
+            e.elr_el1 = recovery_addr as u64;
+            return;
+        }
+    }
+
     default_exception_handler(e);
 }
 
+/// Entered on the overflow stack if a synchronous exception was taken with the stack pointer below
+/// the boot core stack.
+#[no_mangle]
+extern "C" fn current_elx_stack_overflow(e: &mut ExceptionContext) {
+    panic!(
+        "Kernel stack overflow!\n\n\
+        {}",
+        e
+    );
+}
+
 #[no_mangle]
 extern "C" fn current_elx_irq(_e: &mut ExceptionContext) {
+    trace::event(trace::id::IRQ_ENTRY, 0);
+
     let token = unsafe { &IRQContext::new() };
     irq_manager().handle_pending_irqs(token);
+
+    trace::event(trace::id::IRQ_EXIT, 0);
 }
 
 #[no_mangle]
@@ -0,0 +1,71 @@
+use core::{arch::asm, sync::atomic::AtomicUsize};
+
+/// Read a u32 from `addr`, returning `None` if the access faults.
+///
+/// The address of the recovery label is stored in `recovery_slot` right before the access. On a
+/// fault, the exception handler resumes execution there.
+///
+/// # Safety
+///
+/// - `recovery_slot` must be the executing core's slot and IRQs must be masked.
+/// - `addr` must be 4 byte aligned.
+pub unsafe fn read32(addr: usize, recovery_slot: &AtomicUsize) -> Option<u32> {
+    let value: u32;
+    let ok: u64;
+
+    asm!(
+        "adr {tmp}, 2f",
+        "str {tmp}, [{slot}]",
+        "ldr {value:w}, [{addr}]",
+        "mov {ok}, #1",
+        "b 3f",
+        // Recovery: the exception handler continues here if the load faulted.
+        "2:",
+        "mov {value:w}, wzr",
+        "mov {ok}, #0",
+        "3:",
+        "str xzr, [{slot}]",
+        addr = in(reg) addr,
+        slot = in(reg) recovery_slot.as_ptr(),
+        tmp = out(reg) _,
+        value = out(reg) value,
+        ok = out(reg) ok,
+        options(nostack)
+    );
+
+    if ok == 1 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Write a u32 to `addr`, returning `false` if the access faults.
+///
+/// # Safety
+///
+/// - Same as for [`read32`].
+pub unsafe fn write32(addr: usize, value: u32, recovery_slot: &AtomicUsize) -> bool {
+    let ok: u64;
+
+    asm!(
+        "adr {tmp}, 2f",
+        "str {tmp}, [{slot}]",
+        "str {value:w}, [{addr}]",
+        "mov {ok}, #1",
+        "b 3f",
+        // Recovery: the exception handler continues here if the store faulted.
+        "2:",
+        "mov {ok}, #0",
+        "3:",
+        "str xzr, [{slot}]",
+        addr = in(reg) addr,
+        value = in(reg) value,
+        slot = in(reg) recovery_slot.as_ptr(),
+        tmp = out(reg) _,
+        ok = out(reg) ok,
+        options(nostack)
+    );
+
+    ok == 1
+}
@@ -1,26 +1,59 @@
-//! Only 64 KiB granule is supported.
+//! The 64 KiB and the 4 KiB granule are supported, selected by the BSP's `MSKernel`.
 
 use crate::{
-    bsp,
-    memory::{mmu::MS512MiB, Address, Physical},
+    bsp::{self, memory::mmu::MSKernel},
+    cpu::barrier,
+    exception,
+    memory::{
+        mmu::{MS4KiB, MS64KiB, MemorySize},
+        Address, Physical, Virtual,
+    },
+    util::ArrayString,
 };
-use aarch64_cpu::{asm::barrier, registers::*};
+use aarch64_cpu::registers::*;
+use core::fmt::Write;
 use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
 
 use super::{AddressSpace, MMUEnableError, MemoryManagementUnit};
 
+/// Number of descriptors in a table, which always occupies exactly one granule.
+pub const ENTRIES_PER_TABLE: usize = MSKernel::SIZE / core::mem::size_of::<u64>();
+
+/// Memory covered by a level 2 entry, i.e. by a whole level 3 table or a block. 512 MiB with the
+/// 64 KiB granule, 2 MiB with the 4 KiB granule.
+pub type MSLvl2Entry = MemorySize<{ ENTRIES_PER_TABLE * MSKernel::SIZE }>;
+
 impl<const AS_SIZE: usize> AddressSpace<AS_SIZE> {
     /// Checks for architectural restrictions.
+    #[allow(clippy::assertions_on_constants)]
     pub const fn arch_address_space_size_sanity_checks() {
-        // Size must be at least one full 512 MiB table.
-        assert!((AS_SIZE % MS512MiB::SIZE) == 0);
+        assert!(MSKernel::SIZE == MS64KiB::SIZE || MSKernel::SIZE == MS4KiB::SIZE);
+
+        // Size must be at least one full level 3 table.
+        assert!((AS_SIZE % MSLvl2Entry::SIZE) == 0);
 
         // Check for 48 bit virtual address size as maximum, which is supported by any ARMv8
         // version.
         assert!(AS_SIZE <= (1 << 48));
+
+        // Walks start at level 2, which resolves 42 bits with the 64 KiB granule and 30 bits with
+        // the 4 KiB granule. Bigger address spaces would need a level 1 table.
+        assert!(AS_SIZE <= ENTRIES_PER_TABLE * MSLvl2Entry::SIZE);
     }
 }
 
+/// The operands of the `tlbi vae1is` instructions that invalidate `num_pages` pages starting at
+/// `start`, one per page.
+pub fn tlbi_va_operands(start: Address<Virtual>, num_pages: usize) -> impl Iterator<Item = u64> {
+    // The operand is VA[55:12], independent of the granule.
+    (0..num_pages).map(move |i| ((start.as_usize() + i * MSKernel::SIZE) >> 12) as u64)
+}
+
+#[cfg(not(feature = "granule_4k"))]
+const TG0: tock_registers::fields::FieldValue<u64, TCR_EL1::Register> = TCR_EL1::TG0::KiB_64;
+#[cfg(feature = "granule_4k")]
+const TG0: tock_registers::fields::FieldValue<u64, TCR_EL1::Register> = TCR_EL1::TG0::KiB_4;
+
 /// Memory Management Unit type.
 pub struct Aarch64Mmu;
 
@@ -39,13 +72,22 @@ impl Aarch64Mmu {
     }
 
     /// Configure various settings of stage 1 of the EL1 translation regime.
+    ///
+    /// The walk starts at level 2 for both granules, as the address space is limited accordingly.
+    ///
+    /// Only TTBR0 is used and the kernel runs identity mapped in the low half. Moving it to the
+    /// high half through TTBR1 is not done yet. It needs more than a `T1SZ` and a second table set:
+    /// the boot code runs position dependent Rust before the MMU is on, the linker script and
+    /// `LinkerLayout` give physical addresses that double as virtual ones, and the BSP's
+    /// `kernel_virt_to_phys_region()` assumes the identity. That wants a relocated link address
+    /// and a trampoline, and can only be brought up with a debugger on the board.
     fn configure_translation_control(&self) {
         let t0sz = (64 - bsp::memory::mmu::KernelVirtAddrSpace::SIZE_SHIFT) as u64;
 
         TCR_EL1.write(
             TCR_EL1::TBI0::Used
                 + TCR_EL1::IPS::Bits_40
-                + TCR_EL1::TG0::KiB_64
+                + TG0
                 + TCR_EL1::SH0::Inner
                 + TCR_EL1::ORGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
                 + TCR_EL1::IRGN0::WriteBack_ReadAlloc_WriteAlloc_Cacheable
@@ -67,15 +109,31 @@ impl MemoryManagementUnit for Aarch64Mmu {
         }
 
         // Fail early if translation granule is not supported.
-        if !ID_AA64MMFR0_EL1.matches_all(ID_AA64MMFR0_EL1::TGran64::Supported) {
-            return Err(MMUEnableError::Other(
-                "Translation granule not supported in HW",
-            ));
+        #[cfg(not(feature = "granule_4k"))]
+        let granule_supported = ID_AA64MMFR0_EL1.matches_all(ID_AA64MMFR0_EL1::TGran64::Supported);
+        #[cfg(feature = "granule_4k")]
+        let granule_supported = ID_AA64MMFR0_EL1.matches_all(ID_AA64MMFR0_EL1::TGran4::Supported);
+
+        if !granule_supported {
+            let mut message = ArrayString::new();
+            let _ = write!(
+                message,
+                "Translation granule not supported in HW, ID_AA64MMFR0_EL1: {:#x}",
+                ID_AA64MMFR0_EL1.get()
+            );
+
+            return Err(MMUEnableError::WithContext(message));
         }
 
         // Prepare the memory attribute indirection register.
         self.set_up_mair();
 
+        // The table walker is not ordered against the core's own stores. The writes of the tables
+        // must be complete before the walker can be pointed at them, which a DMB would not
+        // guarantee. The tables must also not be shadowed by stale cache lines, see
+        // `MemoryManagementUnit::enable_mmu_and_caching()`.
+        barrier::dsb(barrier::Domain::ISH);
+
         // Set the "Translation Table Base Register".
         TTBR0_EL1.set_baddr(phys_tables_base_addr.as_usize() as u64);
 
@@ -84,13 +142,61 @@ impl MemoryManagementUnit for Aarch64Mmu {
         // Switch the MMU on.
         //
         // First, force all previous changes to be seen before the MMU is enabled.
-        barrier::isb(barrier::SY);
+        barrier::isb();
 
         // Enable the MMU and turn on data and instruction caching.
         SCTLR_EL1.modify(SCTLR_EL1::M::Enable + SCTLR_EL1::C::Cacheable + SCTLR_EL1::I::Cacheable);
 
         // Force MMU init to complete before next instruction.
-        barrier::isb(barrier::SY);
+        barrier::isb();
+
+        Ok(())
+    }
+
+    unsafe fn disable_mmu_and_caching(&self) -> Result<(), MMUEnableError> {
+        if !self.is_enabled() {
+            return Err(MMUEnableError::AlreadyDisabled);
+        }
+
+        // Nothing in between may access memory. Writes with data caching off would bypass dirty
+        // lines, which the clean would then write back on top of them. So the whole sequence is a
+        // single asm block working on registers only.
+        core::arch::asm!(
+            // 1. Turn off data caching. From here on, no new lines are allocated.
+            "mrs x12, SCTLR_EL1",
+            "bic x12, x12, #(1 << 2)", // C: data cache
+            "msr SCTLR_EL1, x12",
+            "isb",
+            // 2. Write all dirty lines back to memory. Clobbers x0-x11, but no memory.
+            "bl __clean_invalidate_dcache_all",
+            // 3. Drop all translations, they are stale once the tables are touched uncached.
+            "tlbi vmalle1",
+            "dsb sy",
+            "isb",
+            // 4. Turn off the MMU and instruction caching.
+            "bic x12, x12, #(1 << 0)",  // M: MMU
+            "bic x12, x12, #(1 << 12)", // I: instruction cache
+            "msr SCTLR_EL1, x12",
+            "isb",
+            // 5. Drop instructions that were fetched with the old configuration.
+            "ic iallu",
+            "dsb sy",
+            "isb",
+            out("x0") _,
+            out("x1") _,
+            out("x2") _,
+            out("x3") _,
+            out("x4") _,
+            out("x5") _,
+            out("x6") _,
+            out("x7") _,
+            out("x9") _,
+            out("x10") _,
+            out("x11") _,
+            out("x12") _,
+            out("x30") _,
+            options(nostack)
+        );
 
         Ok(())
     }
@@ -99,4 +205,44 @@ impl MemoryManagementUnit for Aarch64Mmu {
     fn is_enabled(&self) -> bool {
         SCTLR_EL1.matches_all(SCTLR_EL1::M::Enable)
     }
+
+    fn invalidate_tlb_range(&self, start: Address<Virtual>, num_pages: usize) {
+        // The descriptor writes must be complete before the walkers can refetch them.
+        barrier::dsb(barrier::Domain::ISH);
+
+        for operand in tlbi_va_operands(start, num_pages) {
+            unsafe { core::arch::asm!("tlbi vae1is, {}", in(reg) operand, options(nostack)) };
+        }
+
+        barrier::dsb(barrier::Domain::ISH);
+        barrier::isb();
+    }
+
+    fn invalidate_tlb_all(&self) {
+        barrier::dsb(barrier::Domain::ISH);
+
+        unsafe { core::arch::asm!("tlbi vmalle1is", options(nostack)) };
+
+        barrier::dsb(barrier::Domain::ISH);
+        barrier::isb();
+    }
+
+    fn is_mapped(&self, virt_addr: Address<Virtual>) -> bool {
+        // PAR_EL1 is shared with anyone else doing address translations, e.g. IRQ handlers.
+        let par = exception::asynchronous::exec_with_irq_masked(|| {
+            let par: u64;
+
+            unsafe {
+                // Stage 1 translation for an EL1 read. Faults are reported in PAR_EL1, not raised.
+                core::arch::asm!("at s1e1r, {}", in(reg) virt_addr.as_usize(), options(nostack));
+                barrier::isb();
+                core::arch::asm!("mrs {}, PAR_EL1", out(reg) par, options(nomem, nostack));
+            }
+
+            par
+        });
+
+        // PAR_EL1.F is set if the translation faulted.
+        par & 1 == 0
+    }
 }
@@ -1,20 +1,25 @@
 //! Architectural translation table.
 //!
-//! Only 64 KiB granule is supported.
+//! Supports the 64 KiB and the 4 KiB granule. Regions that are aligned to and sized in multiples of
+//! a level 2 entry's window (512 MiB or 2 MiB) are mapped with level 2 blocks instead of level 3
+//! pages.
 
 use tock_registers::{
     fields::FieldValue,
-    interfaces::{Readable, Writeable},
+    interfaces::{ReadWriteable, Readable, Writeable},
     register_bitfields,
     registers::InMemoryRegister,
 };
 
-use crate::memory::{
-    mmu::{
-        AccessPermissions, AddressSpace, AssociatedTranslationTable, AttributeFields, MS512MiB,
-        MS64KiB, MemAttributes, MemoryRegion, PageAddress,
+use crate::{
+    bsp::memory::mmu::MSKernel,
+    memory::{
+        mmu::{
+            AccessPermissions, AddressSpace, AssociatedTranslationTable, AttributeFields,
+            MSLvl2Entry, MemAttributes, MemoryRegion, PageAddress, ENTRIES_PER_TABLE,
+        },
+        Address, Physical, Virtual,
     },
-    Address, Physical, Virtual,
 };
 
 use super::TranslationTable;
@@ -31,6 +36,7 @@ register_bitfields! {u64,
     STAGE1_TABLE_DESCRIPTOR [
         /// Physical address of the next descriptor.
         NEXT_LEVEL_TABLE_ADDR_64KiB OFFSET(16) NUMBITS(32) [], // [47:16]
+        NEXT_LEVEL_TABLE_ADDR_4KiB OFFSET(12) NUMBITS(36) [], // [47:12]
 
         TYPE  OFFSET(1) NUMBITS(1) [
             Block = 0,
@@ -61,6 +67,7 @@ register_bitfields! {u64,
 
         /// Physical address of the next table descriptor (lvl2) or the page descriptor (lvl3).
         OUTPUT_ADDR_64KiB OFFSET(16) NUMBITS(32) [], // [47:16]
+        OUTPUT_ADDR_4KiB OFFSET(12) NUMBITS(36) [], // [47:12]
 
         /// Access flag.
         AF       OFFSET(10) NUMBITS(1) [
@@ -97,15 +104,26 @@ register_bitfields! {u64,
     ]
 }
 
-/// Big monolithic struct for storing the translation tables. Individual levels must be 64 KiB
-/// aligned, so the lvl3 is put first.
+// The address fields matching the granule. Addresses are stored in units of the granule.
+#[cfg(not(feature = "granule_4k"))]
+use STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR_64KiB as OUTPUT_ADDR;
+#[cfg(not(feature = "granule_4k"))]
+use STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR_64KiB as NEXT_LEVEL_TABLE_ADDR;
+
+#[cfg(feature = "granule_4k")]
+use STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR_4KiB as OUTPUT_ADDR;
+#[cfg(feature = "granule_4k")]
+use STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR_4KiB as NEXT_LEVEL_TABLE_ADDR;
+
+/// Big monolithic struct for storing the translation tables. Individual levels must be aligned to
+/// the granule, so the lvl3 is put first. 64 KiB alignment satisfies both granules.
 #[repr(C)]
 #[repr(align(65536))]
 pub struct FixedSizeTranslationTable<const NUM_TABLES: usize> {
-    /// Page descriptors, covering 64 KiB windows per entry.
-    lvl3: [[PageDescriptor; 8192]; NUM_TABLES],
+    /// Page descriptors, covering one granule per entry.
+    lvl3: [[PageDescriptor; ENTRIES_PER_TABLE]; NUM_TABLES],
 
-    /// Table descriptors, covering 512 MiB windows.
+    /// Table descriptors, covering `MSLvl2Entry` windows.
     lvl2: [TableDescriptor; NUM_TABLES],
 
     /// Have the tables been initialized?
@@ -116,13 +134,14 @@ impl<const NUM_TABLES: usize> FixedSizeTranslationTable<NUM_TABLES> {
     /// Create an instance.
     #[allow(clippy::assertions_on_constants)]
     pub const fn new() -> Self {
-        assert!(crate::bsp::memory::mmu::MSKernel::SIZE == MS64KiB::SIZE);
-
         // Can't have a zero-sized address space.
         assert!(NUM_TABLES > 0);
 
+        // All level 3 tables must be reachable from the single level 2 table.
+        assert!(NUM_TABLES <= ENTRIES_PER_TABLE);
+
         Self {
-            lvl3: [[PageDescriptor::new_zeroed(); 8192]; NUM_TABLES],
+            lvl3: [[PageDescriptor::new_zeroed(); ENTRIES_PER_TABLE]; NUM_TABLES],
             lvl2: [TableDescriptor::new_zeroed(); NUM_TABLES],
             initialized: false,
         }
@@ -135,8 +154,8 @@ impl<const NUM_TABLES: usize> FixedSizeTranslationTable<NUM_TABLES> {
         virt_page_addr: PageAddress<Virtual>,
     ) -> Result<(usize, usize), &'static str> {
         let addr = virt_page_addr.address().as_usize();
-        let lvl2_index = addr >> MS512MiB::SHIFT;
-        let lvl3_index = (addr & MS512MiB::MASK) >> MS64KiB::SHIFT;
+        let lvl2_index = addr >> MSLvl2Entry::SHIFT;
+        let lvl3_index = (addr & MSLvl2Entry::MASK) >> MSKernel::SHIFT;
 
         if lvl2_index > (NUM_TABLES - 1) {
             return Err("Virtual page is out of bounds of translation table");
@@ -157,7 +176,7 @@ impl<const NUM_TABLES: usize> FixedSizeTranslationTable<NUM_TABLES> {
         let (lvl2_index, lvl3_index) = self.lvl2_lvl3_index_from_page_addr(virt_page_addr)?;
         let desc = &mut self.lvl3[lvl2_index][lvl3_index];
 
-        if desc.is_valid() {
+        if desc.is_valid() || self.lvl2[lvl2_index].is_block() {
             return Err("Virtual page is already mapped");
         }
 
@@ -165,17 +184,103 @@ impl<const NUM_TABLES: usize> FixedSizeTranslationTable<NUM_TABLES> {
         Ok(())
     }
 
+    /// Sets the block descriptor for the level 2 window starting at the supplied page address.
+    ///
+    /// Doesn't allow overriding a window that contains any valid page.
+    fn set_block_descriptor(
+        &mut self,
+        virt_page_addr: PageAddress<Virtual>,
+        new_desc: &TableDescriptor,
+    ) -> Result<(), &'static str> {
+        let (lvl2_index, _) = self.lvl2_lvl3_index_from_page_addr(virt_page_addr)?;
+
+        if self.lvl2[lvl2_index].is_block() || self.lvl3[lvl2_index].iter().any(|d| d.is_valid()) {
+            return Err("Virtual page is already mapped");
+        }
+
+        self.lvl2[lvl2_index] = *new_desc;
+        Ok(())
+    }
+
     /// Returns the PageDescriptor corresponding to the supplied page address.
+    ///
+    /// For a page of a block mapping, the equivalent page descriptor is derived from the block.
     #[inline(always)]
     fn get_descriptor(
         &self,
         virt_page_addr: PageAddress<Virtual>,
-    ) -> Result<&PageDescriptor, &'static str> {
+    ) -> Result<PageDescriptor, &'static str> {
         let (lvl2_index, lvl3_index) = self.lvl2_lvl3_index_from_page_addr(virt_page_addr)?;
-        let desc = &self.lvl3[lvl2_index][lvl3_index];
+        let lvl2_desc = &self.lvl2[lvl2_index];
+
+        if lvl2_desc.is_block() {
+            return Ok(lvl2_desc.block_page_descriptor(lvl3_index));
+        }
 
-        Ok(desc)
+        Ok(self.lvl3[lvl2_index][lvl3_index])
     }
+
+    /// Checks the page before it is unmapped or changed. Fails if it is not mapped, or if it
+    /// belongs to a block that is not completely inside `virt_region`, as blocks can only be
+    /// changed as a whole.
+    fn check_page_changeable(
+        &self,
+        virt_region: &MemoryRegion<Virtual>,
+        virt_page_addr: PageAddress<Virtual>,
+    ) -> Result<(), &'static str> {
+        if !self.get_descriptor(virt_page_addr)?.is_valid() {
+            return Err("Virtual page is not mapped");
+        }
+
+        let (lvl2_index, _) = self.lvl2_lvl3_index_from_page_addr(virt_page_addr)?;
+        if !self.lvl2[lvl2_index].is_block() {
+            return Ok(());
+        }
+
+        let block_start = lvl2_index << MSLvl2Entry::SHIFT;
+        let block_end_exclusive = block_start + MSLvl2Entry::SIZE;
+
+        if block_start < virt_region.start_page.address().as_usize()
+            || block_end_exclusive > virt_region.end_page_exclusive.address().as_usize()
+        {
+            return Err("Virtual region covers only part of a block mapping");
+        }
+
+        Ok(())
+    }
+
+    /// Checks that changing the page to `attr` keeps its memory type.
+    ///
+    /// The descriptors are rewritten in place. That is only allowed for the permissions, a
+    /// different memory type needs break-before-make, which would fault code that runs from or
+    /// uses the page in the meantime.
+    fn check_mem_attributes_kept(
+        &self,
+        virt_page_addr: PageAddress<Virtual>,
+        attr: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        let desc = self.get_descriptor(virt_page_addr)?;
+
+        if desc.try_attributes()?.mem_attributes != attr.mem_attributes {
+            return Err("Memory type of a live mapping can not be changed");
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks if a mapping can be done with level 2 blocks.
+fn is_block_mappable(
+    virt_region: &MemoryRegion<Virtual>,
+    phys_region: &MemoryRegion<Physical>,
+) -> bool {
+    crate::is_aligned(
+        virt_region.start_page.address().as_usize(),
+        MSLvl2Entry::SIZE,
+    ) && crate::is_aligned(
+        phys_region.start_page.address().as_usize(),
+        MSLvl2Entry::SIZE,
+    ) && crate::is_aligned(virt_region.size(), MSLvl2Entry::SIZE)
 }
 
 impl<const NUM_TABLES: usize> TranslationTable for FixedSizeTranslationTable<NUM_TABLES> {
@@ -184,8 +289,12 @@ impl<const NUM_TABLES: usize> TranslationTable for FixedSizeTranslationTable<NUM
             return;
         }
 
-        // Populate the l2 entries.
+        // Populate the l2 entries, keeping block mappings.
         for (i, lvl2_entry) in self.lvl2.iter_mut().enumerate() {
+            if lvl2_entry.is_block() {
+                continue;
+            }
+
             let phys_table_addr = self.lvl3[i].phys_start_addr();
 
             let new_desc = TableDescriptor::from_phys_addr(phys_table_addr);
@@ -216,6 +325,21 @@ impl<const NUM_TABLES: usize> TranslationTable for FixedSizeTranslationTable<NUM
             return Err("Tried to map outside of physical address space");
         }
 
+        if is_block_mappable(virt_region, phys_region) {
+            let pages_per_block = MSLvl2Entry::SIZE >> MSKernel::SHIFT;
+
+            for (phys_block_addr, virt_block_addr) in phys_region
+                .as_range()
+                .zip(virt_region.as_range())
+                .step_by(pages_per_block)
+            {
+                let new_desc = TableDescriptor::new_block(phys_block_addr, attr);
+                self.set_block_descriptor(virt_block_addr, &new_desc)?;
+            }
+
+            return Ok(());
+        }
+
         for (phys_page_addr, virt_page_addr) in phys_region.as_range().zip(virt_region.as_range()) {
             let new_desc = PageDescriptor::new(phys_page_addr, attr);
             self.set_descriptor(virt_page_addr, &new_desc)?;
@@ -224,6 +348,60 @@ impl<const NUM_TABLES: usize> TranslationTable for FixedSizeTranslationTable<NUM
         Ok(())
     }
 
+    unsafe fn unmap(&mut self, virt_region: &MemoryRegion<Virtual>) -> Result<(), &'static str> {
+        assert!(self.initialized, "Translation tables not initialized");
+
+        // Check all pages first, so that an error leaves the tables untouched.
+        for virt_page_addr in virt_region.as_range() {
+            self.check_page_changeable(virt_region, virt_page_addr)?;
+        }
+
+        for virt_page_addr in virt_region.as_range() {
+            let (lvl2_index, lvl3_index) = self.lvl2_lvl3_index_from_page_addr(virt_page_addr)?;
+
+            // The block's lvl3 table is unused, so restoring the pointer to it leaves the whole
+            // window unmapped.
+            if self.lvl2[lvl2_index].is_block() {
+                let phys_table_addr = self.lvl3[lvl2_index].phys_start_addr();
+                self.lvl2[lvl2_index] = TableDescriptor::from_phys_addr(phys_table_addr);
+            }
+
+            self.lvl3[lvl2_index][lvl3_index] = PageDescriptor::new_zeroed();
+        }
+
+        Ok(())
+    }
+
+    unsafe fn change_attributes(
+        &mut self,
+        virt_region: &MemoryRegion<Virtual>,
+        attr: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        assert!(self.initialized, "Translation tables not initialized");
+
+        // Check all pages first, so that an error leaves the tables untouched.
+        for virt_page_addr in virt_region.as_range() {
+            self.check_page_changeable(virt_region, virt_page_addr)?;
+            self.check_mem_attributes_kept(virt_page_addr, attr)?;
+        }
+
+        for virt_page_addr in virt_region.as_range() {
+            let (lvl2_index, lvl3_index) = self.lvl2_lvl3_index_from_page_addr(virt_page_addr)?;
+
+            let lvl2_desc = &mut self.lvl2[lvl2_index];
+            if lvl2_desc.is_block() {
+                *lvl2_desc = TableDescriptor::new_block(lvl2_desc.block_output_addr(), attr);
+                continue;
+            }
+
+            let desc = &mut self.lvl3[lvl2_index][lvl3_index];
+
+            *desc = PageDescriptor::new(desc.output_page_addr(), attr);
+        }
+
+        Ok(())
+    }
+
     fn try_page_attributes(
         &self,
         virt_page_addr: PageAddress<Virtual>,
@@ -236,6 +414,19 @@ impl<const NUM_TABLES: usize> TranslationTable for FixedSizeTranslationTable<NUM
 
         page_desc.try_attributes()
     }
+
+    fn try_virt_to_phys(
+        &self,
+        virt_addr: Address<Virtual>,
+    ) -> Result<Address<Physical>, &'static str> {
+        let page_desc = self.get_descriptor(PageAddress::from(virt_addr.align_down_page()))?;
+
+        if !page_desc.is_valid() {
+            return Err("Virtual page is not mapped");
+        }
+
+        Ok(page_desc.output_page_addr().address() + virt_addr.offset_into_page())
+    }
 }
 
 trait StartAddr {
@@ -249,7 +440,7 @@ impl<T, const N: usize> StartAddr for [T; N] {
     }
 }
 
-/// A table descriptor for 64 KiB aperture.
+/// A table descriptor.
 ///
 /// The output points to the next table.
 #[derive(Copy, Clone)]
@@ -270,18 +461,58 @@ impl TableDescriptor {
     pub fn from_phys_addr(phys_next_lvl_table_addr: Address<Physical>) -> Self {
         let val = InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(0);
 
-        let shifted = phys_next_lvl_table_addr.as_usize() >> MS64KiB::SHIFT;
+        let shifted = phys_next_lvl_table_addr.as_usize() >> MSKernel::SHIFT;
         val.write(
-            STAGE1_TABLE_DESCRIPTOR::NEXT_LEVEL_TABLE_ADDR_64KiB.val(shifted as u64)
+            NEXT_LEVEL_TABLE_ADDR.val(shifted as u64)
                 + STAGE1_TABLE_DESCRIPTOR::TYPE::Table
                 + STAGE1_TABLE_DESCRIPTOR::VALID::True,
         );
 
         TableDescriptor { value: val.get() }
     }
+
+    /// Create a block descriptor that maps a level 2 window to the supplied address.
+    ///
+    /// A block has the same attribute fields as a page descriptor, only the type differs.
+    pub fn new_block(
+        phys_output_addr: PageAddress<Physical>,
+        attribute_fields: &AttributeFields,
+    ) -> Self {
+        let page_desc = PageDescriptor::new(phys_output_addr, attribute_fields);
+
+        let val = InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(page_desc.value);
+        val.modify(STAGE1_TABLE_DESCRIPTOR::TYPE::Block);
+
+        TableDescriptor { value: val.get() }
+    }
+
+    /// Returns true if the descriptor is a valid block.
+    fn is_block(&self) -> bool {
+        let val = InMemoryRegister::<u64, STAGE1_TABLE_DESCRIPTOR::Register>::new(self.value);
+
+        val.is_set(STAGE1_TABLE_DESCRIPTOR::VALID)
+            && val.read_as_enum(STAGE1_TABLE_DESCRIPTOR::TYPE)
+                == Some(STAGE1_TABLE_DESCRIPTOR::TYPE::Value::Block)
+    }
+
+    /// Returns the descriptor of the page at `lvl3_index` inside the block, with the block's
+    /// attributes.
+    fn block_page_descriptor(&self, lvl3_index: usize) -> PageDescriptor {
+        let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value);
+
+        let shifted = val.read(OUTPUT_ADDR) + lvl3_index as u64;
+        val.modify(OUTPUT_ADDR.val(shifted) + STAGE1_PAGE_DESCRIPTOR::TYPE::Page);
+
+        PageDescriptor { value: val.get() }
+    }
+
+    /// Returns the physical address the block points to.
+    fn block_output_addr(&self) -> PageAddress<Physical> {
+        self.block_page_descriptor(0).output_page_addr()
+    }
 }
 
-/// A page descriptor with 64 KiB aperture.
+/// A page descriptor with an aperture of one granule.
 ///
 /// The output points to physical memory.
 #[derive(Copy, Clone)]
@@ -305,9 +536,9 @@ impl PageDescriptor {
     ) -> Self {
         let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(0);
 
-        let shifted = phys_output_addr.address().as_usize() as u64 >> MS64KiB::SHIFT;
+        let shifted = phys_output_addr.address().as_usize() as u64 >> MSKernel::SHIFT;
         val.write(
-            STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR_64KiB.val(shifted)
+            OUTPUT_ADDR.val(shifted)
                 + STAGE1_PAGE_DESCRIPTOR::AF::True
                 + STAGE1_PAGE_DESCRIPTOR::TYPE::Page
                 + STAGE1_PAGE_DESCRIPTOR::VALID::True
@@ -317,6 +548,14 @@ impl PageDescriptor {
         Self { value: val.get() }
     }
 
+    /// Returns the physical page the descriptor points to.
+    fn output_page_addr(&self) -> PageAddress<Physical> {
+        let shifted = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value)
+            .read(OUTPUT_ADDR);
+
+        PageAddress::from((shifted << MSKernel::SHIFT) as usize)
+    }
+
     /// Returns the valid bit.
     fn is_valid(&self) -> bool {
         InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value)
@@ -331,9 +570,9 @@ impl PageDescriptor {
 
 impl<const S: usize> AssociatedTranslationTable for AddressSpace<S>
 where
-    [u8; Self::SIZE >> MS512MiB::SHIFT]: Sized,
+    [u8; Self::SIZE >> MSLvl2Entry::SHIFT]: Sized,
 {
-    type Table = FixedSizeTranslationTable<{ Self::SIZE >> MS512MiB::SHIFT }>;
+    type Table = FixedSizeTranslationTable<{ Self::SIZE >> MSLvl2Entry::SHIFT }>;
 }
 
 /// Convert the HW-specific attributes of the MMU to kernel's generic memory attributes.
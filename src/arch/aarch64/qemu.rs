@@ -0,0 +1,23 @@
+use core::arch::asm;
+
+/// Issue a semihosting call.
+///
+/// The operation is passed in w0 and the parameter block address in x1. The result is returned in
+/// x0.
+///
+/// # Safety
+///
+/// - Semihosting must be enabled in the emulator. On real hardware, `hlt` is undefined and traps.
+#[inline(always)]
+pub unsafe fn semihosting_call(operation: u32, parameter: u64) -> u64 {
+    let ret: u64;
+
+    asm!(
+        "hlt #0xF000",
+        inlateout("x0") operation as u64 => ret,
+        in("x1") parameter,
+        options(nostack)
+    );
+
+    ret
+}
@@ -1,10 +1,11 @@
-use aarch64_cpu::{asm::barrier, registers::*};
+use crate::cpu::barrier;
+use aarch64_cpu::registers::*;
 use core::{
     num::{NonZeroU128, NonZeroU32, NonZeroU64},
     ops::{Add, Div},
     time::Duration,
 };
-use tock_registers::interfaces::Readable;
+use tock_registers::interfaces::{Readable, Writeable};
 
 use super::NANOSEC_PER_SEC;
 
@@ -37,6 +38,32 @@ pub fn spin_for(duration: Duration) -> Result<(), &'static str> {
     Ok(())
 }
 
+/// Program the physical timer's comparator to fire at `deadline`, measured like `uptime()`.
+pub fn set_compare(deadline: Duration) -> Result<(), &'static str> {
+    let deadline: TimerCounter = deadline.try_into()?;
+
+    CNTP_CVAL_EL0.set(deadline.0);
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
+
+    Ok(())
+}
+
+/// Stop the physical timer's comparator, which also deasserts its IRQ.
+pub fn disable_compare() {
+    CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::CLEAR);
+}
+
+/// The raw value of the physical counter, without an ordering barrier.
+#[inline(always)]
+pub fn counter() -> u64 {
+    TimerCounter::from_cntpct_direct().0
+}
+
+/// Convert a raw counter value into the time it represents.
+pub fn counter_to_duration(counter: u64) -> Duration {
+    TimerCounter(counter).into()
+}
+
 fn arch_timer_counter_frequency() -> NonZeroU32 {
     // Read volatile is needed here to prevent the compiler from optimizing
     // ARCH_TIMER_COUNTER_FREQUENCY away.
@@ -57,7 +84,7 @@ impl TimerCounter {
     #[inline(always)]
     pub fn from_cntpct() -> Self {
         // Prevent that the counter is read ahead of time due to out-of-order execution.
-        barrier::isb(barrier::SY);
+        barrier::isb();
         let cnt = CNTPCT_EL0.get();
         Self(cnt)
     }
@@ -1,175 +0,0 @@
-use crate::{
-    bsp::drivers::common::MMIODerefWrapper,
-    driver,
-    memory::{Address, Virtual},
-    synchronization::{IRQSafeNullLock, Mutex},
-};
-use tock_registers::{
-    interfaces::{ReadWriteable, Writeable},
-    register_bitfields, register_structs,
-    registers::ReadWrite,
-};
-
-use super::gicv2::IRQNumber;
-
-// GPIO registers.
-//
-// Descriptions taken from
-// - https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf
-// - https://datasheets.raspberrypi.org/bcm2711/bcm2711-peripherals.pdf
-register_bitfields! {
-    u32,
-
-    /// GPIO Function Select 1
-    GPFSEL1 [
-        /// Pin 15
-        FSEL15 OFFSET(15) NUMBITS(3) [
-            Input = 0b000,
-            Output = 0b001,
-            AltFunc0 = 0b100  // PL011 UART RX
-
-        ],
-
-        /// Pin 14
-        FSEL14 OFFSET(12) NUMBITS(3) [
-            Input = 0b000,
-            Output = 0b001,
-            AltFunc0 = 0b100  // PL011 UART TX
-        ]
-    ],
-
-    /// GPIO Pull-up/down Register
-    ///
-    /// BCM2837 only.
-    GPPUD [
-        /// Controls the actuation of the internal pull-up/down control line to ALL the GPIO pins.
-        PUD OFFSET(0) NUMBITS(2) [
-            Off = 0b00,
-            PullDown = 0b01,
-            PullUp = 0b10
-        ]
-    ],
-
-    /// GPIO Pull-up/down Clock Register 0
-    ///
-    /// BCM2837 only.
-    GPPUDCLK0 [
-        /// Pin 15
-        PUDCLK15 OFFSET(15) NUMBITS(1) [
-            NoEffect = 0,
-            AssertClock = 1
-        ],
-
-        /// Pin 14
-        PUDCLK14 OFFSET(14) NUMBITS(1) [
-            NoEffect = 0,
-            AssertClock = 1
-        ]
-    ],
-
-    /// GPIO Pull-up / Pull-down Register 0
-    ///
-    /// BCM2711 only.
-    GPIO_PUP_PDN_CNTRL_REG0 [
-        /// Pin 15
-        GPIO_PUP_PDN_CNTRL15 OFFSET(30) NUMBITS(2) [
-            NoResistor = 0b00,
-            PullUp = 0b01
-        ],
-
-        /// Pin 14
-        GPIO_PUP_PDN_CNTRL14 OFFSET(28) NUMBITS(2) [
-            NoResistor = 0b00,
-            PullUp = 0b01
-        ]
-    ]
-}
-
-register_structs! {
-    #[allow(non_snake_case)]
-    RegisterBlock {
-        (0x00 => _reserved1),
-        (0x04 => GPFSEL1: ReadWrite<u32, GPFSEL1::Register>),
-        (0x08 => _reserved2),
-        (0x94 => GPPUD: ReadWrite<u32, GPPUD::Register>),
-        (0x98 => GPPUDCLK0: ReadWrite<u32, GPPUDCLK0::Register>),
-        (0x9C => _reserved3),
-        (0xE4 => GPIO_PUP_PDN_CNTRL_REG0: ReadWrite<u32, GPIO_PUP_PDN_CNTRL_REG0::Register>),
-        (0xE8 => @END),
-    }
-}
-
-/// Abstraction for the associated MMIO registers.
-type Registers = MMIODerefWrapper<RegisterBlock>;
-
-struct GPIOInner {
-    registers: Registers,
-}
-
-impl GPIOInner {
-    /// Create an instance.
-    ///
-    /// # Safety
-    ///
-    /// - The user must ensure to provide a correct MMIO start address.
-    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
-        Self {
-            registers: Registers::new(mmio_start_addr),
-        }
-    }
-
-    /// Disable pull-up/down on pins 14 and 15.
-    fn disable_pud_14_15_bcm2711(&mut self) {
-        self.registers.GPIO_PUP_PDN_CNTRL_REG0.write(
-            GPIO_PUP_PDN_CNTRL_REG0::GPIO_PUP_PDN_CNTRL15::PullUp
-                + GPIO_PUP_PDN_CNTRL_REG0::GPIO_PUP_PDN_CNTRL14::PullUp,
-        );
-    }
-
-    /// Map PL011 UART as standard output.
-    ///
-    /// TX to pin 14
-    /// RX to pin 15
-    pub fn map_pl011_uart(&mut self) {
-        // Select the UART on pins 14 and 15.
-        self.registers
-            .GPFSEL1
-            .modify(GPFSEL1::FSEL15::AltFunc0 + GPFSEL1::FSEL14::AltFunc0);
-
-        // Disable pull-up/down on pins 14 and 15.
-        self.disable_pud_14_15_bcm2711();
-    }
-}
-
-/// Representation of the GPIO HW.
-pub struct GPIO {
-    inner: IRQSafeNullLock<GPIOInner>,
-}
-
-impl GPIO {
-    pub const COMPATIBLE: &'static str = "BCM GPIO";
-
-    /// Create an instance.
-    ///
-    /// # Safety
-    ///
-    /// - The user must ensure to provide a correct MMIO start address.
-    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
-        Self {
-            inner: IRQSafeNullLock::new(GPIOInner::new(mmio_start_addr)),
-        }
-    }
-
-    /// Concurrency safe version of `GPIOInner.map_pl011_uart()`
-    pub fn map_pl011_uart(&self) {
-        self.inner.lock(|inner| inner.map_pl011_uart())
-    }
-}
-
-impl driver::interface::DeviceDriver for GPIO {
-    type IRQNumberType = IRQNumber;
-
-    fn compatible(&self) -> &'static str {
-        Self::COMPATIBLE
-    }
-}
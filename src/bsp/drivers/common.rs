@@ -1,52 +0,0 @@
-use core::{marker::PhantomData, ops::Deref};
-
-use crate::memory::{Address, Virtual};
-
-pub struct MMIODerefWrapper<T> {
-    start_addr: Address<Virtual>,
-    phantom: PhantomData<fn() -> T>,
-}
-
-impl<T> MMIODerefWrapper<T> {
-    /// Create an instance.
-    pub const unsafe fn new(start_addr: Address<Virtual>) -> Self {
-        Self {
-            start_addr,
-            phantom: PhantomData,
-        }
-    }
-}
-
-impl<T> Deref for MMIODerefWrapper<T> {
-    type Target = T;
-
-    fn deref(&self) -> &Self::Target {
-        unsafe { &*(self.start_addr.as_usize() as *const _) }
-    }
-}
-
-/// A wrapper type for usize with integrated range bound check.
-#[derive(Copy, Clone)]
-pub struct BoundedUsize<const MAX_INCLUSIVE: usize>(usize);
-
-impl<const MAX_INCLUSIVE: usize> BoundedUsize<{ MAX_INCLUSIVE }> {
-    pub const MAX_INCLUSIVE: usize = MAX_INCLUSIVE;
-
-    /// Creates a new instance if number <= MAX_INCLUSIVE.
-    pub const fn new(number: usize) -> Self {
-        assert!(number <= MAX_INCLUSIVE);
-
-        Self(number)
-    }
-
-    /// Return the wrapped number.
-    pub const fn get(self) -> usize {
-        self.0
-    }
-}
-
-impl<const MAX_INCLUSIVE: usize> core::fmt::Display for BoundedUsize<{ MAX_INCLUSIVE }> {
-    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
@@ -1,4 +0,0 @@
-pub mod bcm2xxx_gpio;
-pub mod bcm2xxx_pl011;
-pub mod common;
-pub mod gicv2;
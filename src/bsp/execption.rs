@@ -1,3 +0,0 @@
-use crate::bsp::drivers::gicv2::IRQNumber;
-
-pub const PL011_UART_IRQ: IRQNumber = IRQNumber::new(153);
@@ -1,9 +1,19 @@
-pub mod cpu;
-pub mod driver;
-pub mod drivers;
-pub mod execption;
-pub mod memory;
-
-pub fn board_name() -> &'static str {
-    "Raspberry Pi 4"
-}
+//! Board support packages.
+//!
+//! Exactly one board is selected with a `board-*` cargo feature. It provides the memory map, the
+//! driver set and `board_name()`, which are re-exported from here.
+
+#[cfg(not(any(feature = "board-rpi4", feature = "board-qemu-virt")))]
+compile_error!("No board selected, enable one of the `board-*` features");
+
+#[cfg(all(feature = "board-rpi4", feature = "board-qemu-virt"))]
+compile_error!("Multiple boards selected, enable only one of the `board-*` features");
+
+#[cfg(feature = "board-qemu-virt")]
+compile_error!("The QEMU virt board is not implemented yet");
+
+#[cfg(feature = "board-rpi4")]
+mod raspberrypi;
+
+#[cfg(feature = "board-rpi4")]
+pub use raspberrypi::*;
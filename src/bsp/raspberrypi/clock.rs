@@ -0,0 +1,58 @@
+//! BSP clock rates.
+//!
+//! The clocks are managed by the firmware. Their rates are read through the mailbox property
+//! interface.
+
+use crate::info;
+
+/// Property tag to read the current rate of a clock.
+const TAG_GET_CLOCK_RATE: u32 = 0x0003_0002;
+
+/// The clocks as identified by the firmware.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ClockId {
+    Emmc = 1,
+    Uart = 2,
+    Arm = 3,
+    Core = 4,
+}
+
+impl ClockId {
+    /// All known clocks, in the order they are printed.
+    pub const ALL: [Self; 4] = [Self::Arm, Self::Core, Self::Uart, Self::Emmc];
+
+    /// Human readable name of the clock.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Emmc => "EMMC",
+            Self::Uart => "UART",
+            Self::Arm => "ARM",
+            Self::Core => "Core",
+        }
+    }
+}
+
+/// Return the current rate of a clock in Hz.
+pub fn get_rate(clock: ClockId) -> Result<u32, &'static str> {
+    let mailbox = super::driver::mailbox().ok_or("Mailbox driver not initialized")?;
+
+    let mut values = [clock as u32, 0];
+    mailbox.call(TAG_GET_CLOCK_RATE, &mut values)?;
+
+    // The firmware reports a rate of zero for clocks that do not exist.
+    match values[1] {
+        0 => Err("Clock not available"),
+        rate => Ok(rate),
+    }
+}
+
+/// Print the rates of all known clocks.
+pub fn print_rates() {
+    for clock in ClockId::ALL {
+        match get_rate(clock) {
+            Ok(rate) => info!("      {: <4}: {} Hz", clock.name(), rate),
+            Err(e) => info!("      {: <4}: {}", clock.name(), e),
+        }
+    }
+}
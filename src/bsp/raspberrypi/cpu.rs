@@ -0,0 +1,14 @@
+/// Used by `arch` code to find the early boot core.
+#[no_mangle]
+#[link_section = ".text._start_arguments"]
+pub static BOOT_CORE_ID: u64 = 0;
+
+/// Number of cores of the BCM2711.
+pub const NUM_CORES: usize = 4;
+
+/// Whether the firmware implements PSCI.
+///
+/// The stock Raspberry Pi armstub uses a spin-table for the secondary cores and disables the `smc`
+/// instruction, so PSCI calls would trap. Set this when booting through a firmware that provides
+/// PSCI, e.g. Trusted Firmware-A.
+pub const PSCI_AVAILABLE: bool = false;
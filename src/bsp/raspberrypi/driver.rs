@@ -0,0 +1,329 @@
+use crate::{
+    bsp::execption::GPIO_IRQ,
+    console::{self, interface::Write},
+    driver::DeviceDriverDescriptor,
+    driver::DRIVER_MANAGER,
+    exception::asynchronous::set_irq_manager,
+    memory::mmu::{kernel_map_mmio, MMIODescriptor},
+    state::{init_phase_tracker, InitPhase},
+    synchronization::{InitStateLock, ReadWriteExclusive},
+};
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// The UART used as console. Only one of them is compiled in, selected by the `mini_uart` feature.
+#[cfg(not(feature = "mini_uart"))]
+mod console_uart {
+    pub use crate::bsp::{
+        drivers::bcm2xxx_pl011::PL011Uart as Uart,
+        execption::PL011_UART_IRQ as IRQ,
+        memory::map::mmio::{PL011_UART_SIZE as SIZE, PL011_UART_START as START},
+    };
+
+    /// Route the UART to the GPIO pins.
+    pub fn map_pins(gpio: &crate::bsp::drivers::bcm2xxx_gpio::GPIO) {
+        gpio.map_pl011_uart();
+    }
+}
+
+/// The UART used as console. Only one of them is compiled in, selected by the `mini_uart` feature.
+#[cfg(feature = "mini_uart")]
+mod console_uart {
+    pub use crate::bsp::{
+        drivers::bcm2xxx_mini_uart::MiniUart as Uart,
+        execption::MINI_UART_IRQ as IRQ,
+        memory::map::mmio::{MINI_UART_SIZE as SIZE, MINI_UART_START as START},
+    };
+
+    /// Route the UART to the GPIO pins.
+    pub fn map_pins(gpio: &crate::bsp::drivers::bcm2xxx_gpio::GPIO) {
+        gpio.map_mini_uart();
+    }
+}
+
+/// The IRQ of the console UART.
+pub const CONSOLE_UART_IRQ: crate::exception::asynchronous::IRQNumber = console_uart::IRQ;
+
+/// Tells whether the driver instance in the `MaybeUninit` static next to it is initialized.
+///
+/// `set()` stores with Release after the instance was written and `is_ready()` loads with Acquire,
+/// so a core that sees the flag set also sees the initialized instance.
+struct ReadyFlag(AtomicBool);
+
+impl ReadyFlag {
+    const fn new() -> Self {
+        Self(AtomicBool::new(false))
+    }
+
+    fn set(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+static mut UART: MaybeUninit<console_uart::Uart> = MaybeUninit::uninit();
+static UART_READY: ReadyFlag = ReadyFlag::new();
+
+static mut GPIO: MaybeUninit<super::drivers::bcm2xxx_gpio::GPIO> = MaybeUninit::uninit();
+static GPIO_READY: ReadyFlag = ReadyFlag::new();
+
+static mut PM_WATCHDOG: MaybeUninit<super::drivers::bcm2711_pm_wdog::PMWatchdog> =
+    MaybeUninit::uninit();
+static PM_WATCHDOG_READY: ReadyFlag = ReadyFlag::new();
+
+static mut MAILBOX: MaybeUninit<super::drivers::bcm2711_mailbox::Mailbox> = MaybeUninit::uninit();
+static MAILBOX_READY: ReadyFlag = ReadyFlag::new();
+
+static mut INTERRUPT_CONTROLLER: MaybeUninit<super::drivers::gicv2::GICv2> = MaybeUninit::uninit();
+static INTERRUPT_CONTROLLER_READY: ReadyFlag = ReadyFlag::new();
+
+/// Why the interrupt controller could not be instantiated, if it failed.
+static INTERRUPT_CONTROLLER_ERROR: InitStateLock<Option<&'static str>> = InitStateLock::new(None);
+
+/// This must be called only after successful init of the memory subsystem.
+unsafe fn instantiate_uart() -> Result<(), &'static str> {
+    let mmio_descriptor = MMIODescriptor::new(console_uart::START, console_uart::SIZE);
+    let virt_addr = kernel_map_mmio(console_uart::Uart::COMPATIBLE, &mmio_descriptor)?;
+
+    UART.write(console_uart::Uart::new(virt_addr));
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the UART driver.
+unsafe fn post_init_uart() -> Result<(), &'static str> {
+    console::register_console(UART.assume_init_ref());
+    UART_READY.set();
+    super::reset::register_shutdown_hook(|| console::console().flush())
+}
+
+/// This must be called only after successful init of the memory subsystem.
+unsafe fn instantiate_gpio() -> Result<(), &'static str> {
+    let mmio_descriptor = MMIODescriptor::new(
+        crate::bsp::memory::map::mmio::GPIO_START,
+        crate::bsp::memory::map::mmio::GPIO_SIZE,
+    );
+    let virt_addr = kernel_map_mmio(
+        super::drivers::bcm2xxx_gpio::GPIO::COMPATIBLE,
+        &mmio_descriptor,
+    )?;
+
+    GPIO.write(super::drivers::bcm2xxx_gpio::GPIO::new(virt_addr));
+
+    Ok(())
+}
+
+/// Round-trip `byte` through the console UART's internal loopback and return what was received.
+///
+/// The mini UART has no loopback mode, so this is only available with the PL011.
+#[cfg(not(feature = "mini_uart"))]
+pub fn console_uart_loopback(byte: u8) -> Result<u8, &'static str> {
+    if !UART_READY.is_ready() {
+        return Err("Console UART not initialized");
+    }
+
+    unsafe { UART.assume_init_ref() }.loopback(byte)
+}
+
+/// This must be called only after successful init of the GPIO driver.
+unsafe fn post_init_gpio() -> Result<(), &'static str> {
+    console_uart::map_pins(GPIO.assume_init_ref());
+    GPIO_READY.set();
+    Ok(())
+}
+
+/// Return the GPIO driver if it has been initialized.
+pub fn gpio() -> Option<&'static super::drivers::bcm2xxx_gpio::GPIO> {
+    if !GPIO_READY.is_ready() {
+        return None;
+    }
+
+    unsafe { Some(GPIO.assume_init_ref()) }
+}
+
+/// This must be called only after successful init of the memory subsystem.
+unsafe fn instantiate_pm_watchdog() -> Result<(), &'static str> {
+    let mmio_descriptor = MMIODescriptor::new(
+        crate::bsp::memory::map::mmio::PM_START,
+        crate::bsp::memory::map::mmio::PM_SIZE,
+    );
+    let virt_addr = kernel_map_mmio(
+        super::drivers::bcm2711_pm_wdog::PMWatchdog::COMPATIBLE,
+        &mmio_descriptor,
+    )?;
+
+    PM_WATCHDOG.write(super::drivers::bcm2711_pm_wdog::PMWatchdog::new(virt_addr));
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the watchdog driver.
+unsafe fn post_init_pm_watchdog() -> Result<(), &'static str> {
+    PM_WATCHDOG_READY.set();
+    super::reset::register_shutdown_hook(super::watchdog::disable)
+}
+
+/// Return the watchdog driver if it has been initialized.
+pub fn pm_watchdog() -> Option<&'static super::drivers::bcm2711_pm_wdog::PMWatchdog> {
+    if !PM_WATCHDOG_READY.is_ready() {
+        return None;
+    }
+
+    unsafe { Some(PM_WATCHDOG.assume_init_ref()) }
+}
+
+/// This must be called only after successful init of the memory subsystem.
+unsafe fn instantiate_mailbox() -> Result<(), &'static str> {
+    let mmio_descriptor = MMIODescriptor::new(
+        crate::bsp::memory::map::mmio::MAILBOX_START,
+        crate::bsp::memory::map::mmio::MAILBOX_SIZE,
+    );
+    let virt_addr = kernel_map_mmio(
+        super::drivers::bcm2711_mailbox::Mailbox::COMPATIBLE,
+        &mmio_descriptor,
+    )?;
+
+    MAILBOX.write(super::drivers::bcm2711_mailbox::Mailbox::new(virt_addr));
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the mailbox driver.
+unsafe fn post_init_mailbox() -> Result<(), &'static str> {
+    MAILBOX_READY.set();
+    Ok(())
+}
+
+/// Return the mailbox driver if it has been initialized.
+pub fn mailbox() -> Option<&'static super::drivers::bcm2711_mailbox::Mailbox> {
+    if !MAILBOX_READY.is_ready() {
+        return None;
+    }
+
+    unsafe { Some(MAILBOX.assume_init_ref()) }
+}
+
+/// This must be called only after successful init of the memory subsystem.
+unsafe fn instantiate_interrupt_controller() -> Result<(), &'static str> {
+    let gicd_mmio_descriptor = MMIODescriptor::new(
+        crate::bsp::memory::map::mmio::GICD_START,
+        crate::bsp::memory::map::mmio::GICD_SIZE,
+    );
+    let gicd_virt_addr = kernel_map_mmio("GICv2 GICD", &gicd_mmio_descriptor)?;
+
+    let gicc_mmio_descriptor = MMIODescriptor::new(
+        crate::bsp::memory::map::mmio::GICC_START,
+        crate::bsp::memory::map::mmio::GICC_SIZE,
+    );
+    let gicc_virt_addr = kernel_map_mmio("GICV2 GICC", &gicc_mmio_descriptor)?;
+
+    INTERRUPT_CONTROLLER.write(super::drivers::gicv2::GICv2::new(
+        gicd_virt_addr,
+        gicc_virt_addr,
+    ));
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the interrupt controller driver.
+unsafe fn post_init_interrupt_controller() -> Result<(), &'static str> {
+    set_irq_manager(INTERRUPT_CONTROLLER.assume_init_ref());
+    INTERRUPT_CONTROLLER_READY.set();
+    Ok(())
+}
+
+/// Return the interrupt controller driver if it has been initialized.
+///
+/// Gives access to GIC specifics that the generic IRQ manager interface does not cover.
+pub fn interrupt_controller() -> Option<&'static super::drivers::gicv2::GICv2> {
+    if !INTERRUPT_CONTROLLER_READY.is_ready() {
+        return None;
+    }
+
+    unsafe { Some(INTERRUPT_CONTROLLER.assume_init_ref()) }
+}
+
+/// Return the reason the interrupt controller failed to come up, if it did.
+///
+/// In that case, the kernel runs in polled mode: the null IRQ manager stays registered, no driver
+/// gets an IRQ and IRQs must stay masked.
+pub fn interrupt_controller_error() -> Option<&'static str> {
+    INTERRUPT_CONTROLLER_ERROR.read(|error| *error)
+}
+
+/// Initialize the driver subsystem.
+///
+/// Completes [`InitPhase::BspDriverInit`].
+///
+/// # Safety
+///
+/// See child function calls.
+pub unsafe fn init() -> Result<(), &'static str> {
+    // Acquire pairs with the Release store at the end, so that a core which sees the flag set also
+    // sees all driver instances.
+    static INIT_DONE: AtomicBool = AtomicBool::new(false);
+    if INIT_DONE.load(Ordering::Acquire) {
+        return Err("Init already done");
+    }
+    init_phase_tracker().assert_ready_for(InitPhase::BspDriverInit);
+
+    // Try the interrupt controller first. If it fails, the kernel falls back to polled operation
+    // instead of losing the console as well, so drivers must not get IRQs in that case.
+    if let Err(e) = instantiate_interrupt_controller() {
+        INTERRUPT_CONTROLLER_ERROR.write(|error| *error = Some(e));
+    }
+    let irqs_available = interrupt_controller_error().is_none();
+
+    instantiate_uart()?;
+    let uart_descriptor = DeviceDriverDescriptor {
+        device_driver: UART.assume_init_ref(),
+        post_init_callback: Some(post_init_uart),
+        irq_number: irqs_available.then_some(console_uart::IRQ),
+        best_effort: false,
+    };
+    DRIVER_MANAGER.register_driver(uart_descriptor);
+
+    instantiate_gpio()?;
+    let gpio_descriptor = DeviceDriverDescriptor {
+        device_driver: GPIO.assume_init_ref(),
+        post_init_callback: Some(post_init_gpio),
+        irq_number: irqs_available.then_some(GPIO_IRQ),
+        best_effort: false,
+    };
+    DRIVER_MANAGER.register_driver(gpio_descriptor);
+
+    instantiate_pm_watchdog()?;
+    let pm_watchdog_descriptor = DeviceDriverDescriptor {
+        device_driver: PM_WATCHDOG.assume_init_ref(),
+        post_init_callback: Some(post_init_pm_watchdog),
+        irq_number: None,
+        best_effort: true,
+    };
+    DRIVER_MANAGER.register_driver(pm_watchdog_descriptor);
+
+    instantiate_mailbox()?;
+    let mailbox_descriptor = DeviceDriverDescriptor {
+        device_driver: MAILBOX.assume_init_ref(),
+        post_init_callback: Some(post_init_mailbox),
+        irq_number: None,
+        best_effort: true,
+    };
+    DRIVER_MANAGER.register_driver(mailbox_descriptor);
+
+    if irqs_available {
+        let interrupt_controller_descriptor = DeviceDriverDescriptor {
+            device_driver: INTERRUPT_CONTROLLER.assume_init_ref(),
+            post_init_callback: Some(post_init_interrupt_controller),
+            irq_number: None,
+            best_effort: false,
+        };
+        DRIVER_MANAGER.register_driver(interrupt_controller_descriptor);
+    }
+
+    init_phase_tracker().complete(InitPhase::BspDriverInit);
+    INIT_DONE.store(true, Ordering::Release);
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use crate::{
+    bsp::drivers::common::MMIODerefWrapper,
+    cpu, driver,
+    memory::{Address, Virtual},
+    synchronization::{IRQSafeNullLock, Mutex},
+};
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, WriteOnly},
+};
+
+use super::gicv2::IRQNumber;
+
+// VideoCore mailbox registers.
+//
+// Mailbox 0 is read by the ARM, mailbox 1 is written by the ARM. Both are described relative to
+// the start of mailbox 0.
+register_bitfields! {
+    u32,
+
+    /// Mailbox Status
+    STATUS [
+        /// The mailbox can not take another message.
+        FULL OFFSET(31) NUMBITS(1) [],
+
+        /// The mailbox holds no message.
+        EMPTY OFFSET(30) NUMBITS(1) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => READ: ReadOnly<u32>),
+        (0x04 => _reserved1),
+        (0x18 => STATUS0: ReadOnly<u32, STATUS::Register>),
+        (0x1C => _reserved2),
+        (0x20 => WRITE: WriteOnly<u32>),
+        (0x24 => _reserved3),
+        (0x38 => STATUS1: ReadOnly<u32, STATUS::Register>),
+        (0x3C => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// Channel of the property tags interface, ARM to VideoCore.
+const CHANNEL_PROPERTY: u32 = 8;
+
+/// Mask of the channel in a mailbox message. The rest is the buffer address.
+const CHANNEL_MASK: u32 = 0xF;
+
+/// Buffer code of a request.
+const CODE_REQUEST: u32 = 0;
+
+/// Buffer code of a successfully processed request.
+const CODE_RESPONSE_SUCCESS: u32 = 0x8000_0000;
+
+/// Set in a tag's request/response code once the firmware processed it. The rest is the length of
+/// the response in bytes.
+const TAG_RESPONSE: u32 = 1 << 31;
+
+/// Marks the end of the tags in a buffer.
+const TAG_END: u32 = 0;
+
+/// Words of the buffer header (size and code) plus the tag header (id, value size, code) plus the
+/// end tag.
+const OVERHEAD_WORDS: usize = 6;
+
+/// Number of words in the property buffer.
+const BUFFER_WORDS: usize = 36;
+
+/// Largest number of value words a single tag can carry.
+pub const MAX_VALUE_WORDS: usize = BUFFER_WORDS - OVERHEAD_WORDS;
+
+/// Number of status polls before a transfer is given up.
+const MAX_POLLS: usize = 1_000_000;
+
+/// Buffer shared with the firmware. The low four bits of its address carry the channel, so it
+/// must be 16 byte aligned.
+#[repr(C, align(16))]
+struct PropertyBuffer([u32; BUFFER_WORDS]);
+
+struct MailboxInner {
+    registers: Registers,
+    buffer: PropertyBuffer,
+}
+
+impl MailboxInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            buffer: PropertyBuffer([0; BUFFER_WORDS]),
+        }
+    }
+
+    /// Poll until `ready` returns true or the poll budget is used up.
+    fn poll(&self, ready: impl Fn(&Registers) -> bool) -> Result<(), &'static str> {
+        for _ in 0..MAX_POLLS {
+            if ready(&self.registers) {
+                return Ok(());
+            }
+            cpu::nop();
+        }
+
+        Err("Mailbox timeout")
+    }
+
+    /// Hand the buffer to the firmware and wait for it to be processed.
+    fn transfer(&mut self) -> Result<(), &'static str> {
+        let buffer_addr = Address::<Virtual>::new(self.buffer.0.as_ptr() as usize);
+
+        // The kernel is identity mapped, so the virtual address of the buffer is also the one the
+        // firmware sees. It lives in cacheable memory, while the firmware reads it from DRAM.
+        let message = buffer_addr.as_usize() as u32 | CHANNEL_PROPERTY;
+        cpu::cache::clean_invalidate_dcache_range(
+            buffer_addr,
+            core::mem::size_of::<PropertyBuffer>(),
+        );
+
+        self.poll(|regs| !regs.STATUS1.is_set(STATUS::FULL))?;
+        self.registers.WRITE.set(message);
+
+        // Replies to other channels are not expected, but would be dropped here.
+        loop {
+            self.poll(|regs| !regs.STATUS0.is_set(STATUS::EMPTY))?;
+
+            let reply = self.registers.READ.get();
+            if reply == message {
+                break;
+            }
+            if reply & CHANNEL_MASK == CHANNEL_PROPERTY {
+                return Err("Mailbox reply for a different buffer");
+            }
+        }
+
+        cpu::cache::clean_invalidate_dcache_range(
+            buffer_addr,
+            core::mem::size_of::<PropertyBuffer>(),
+        );
+
+        Ok(())
+    }
+
+    /// Send a single property tag. `values` holds the request values and receives the response.
+    fn call(&mut self, tag: u32, values: &mut [u32]) -> Result<(), &'static str> {
+        if values.len() > MAX_VALUE_WORDS {
+            return Err("Mailbox request too large");
+        }
+
+        let num_words = values.len() + OVERHEAD_WORDS;
+        let buf = &mut self.buffer.0;
+
+        buf[0] = (num_words * 4) as u32;
+        buf[1] = CODE_REQUEST;
+        buf[2] = tag;
+        buf[3] = (values.len() * 4) as u32;
+        buf[4] = 0;
+        buf[5..5 + values.len()].copy_from_slice(values);
+        buf[5 + values.len()] = TAG_END;
+
+        self.transfer()?;
+
+        let buf = &self.buffer.0;
+        if buf[1] != CODE_RESPONSE_SUCCESS {
+            return Err("Mailbox request failed");
+        }
+        if buf[4] & TAG_RESPONSE == 0 {
+            return Err("Mailbox tag not processed");
+        }
+
+        values.copy_from_slice(&buf[5..5 + values.len()]);
+
+        Ok(())
+    }
+}
+
+/// Representation of the VideoCore mailbox HW, used to talk to the firmware.
+pub struct Mailbox {
+    inner: IRQSafeNullLock<MailboxInner>,
+}
+
+impl Mailbox {
+    pub const COMPATIBLE: &'static str = "BCM VideoCore Mailbox";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(MailboxInner::new(mmio_start_addr)),
+        }
+    }
+
+    /// Send a single property tag to the firmware and wait for the response.
+    ///
+    /// `values` holds the request values of the tag and is overwritten with the response values.
+    /// It must be large enough for both.
+    pub fn call(&self, tag: u32, values: &mut [u32]) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.call(tag, values))
+    }
+}
+
+impl driver::interface::DeviceDriver for Mailbox {
+    type IRQNumberType = IRQNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{
+    bsp::drivers::common::MMIODerefWrapper,
+    driver,
+    memory::{Address, Virtual},
+    synchronization::{IRQSafeNullLock, Mutex},
+};
+use core::time::Duration;
+use tock_registers::{
+    interfaces::{ReadWriteable, Writeable},
+    register_bitfields, register_structs,
+    registers::ReadWrite,
+};
+
+use super::gicv2::IRQNumber;
+
+// Power management watchdog registers.
+//
+// The BCM2711 peripherals datasheet does not document this block. The layout follows the Linux
+// bcm2835_wdt driver.
+register_bitfields! {
+    u32,
+
+    /// Reset Control
+    RSTC [
+        /// Every write must carry the password, otherwise it is ignored.
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Magic = 0x5A
+        ],
+
+        /// What to do when the watchdog expires.
+        WRCFG OFFSET(4) NUMBITS(2) [
+            Clear = 0b00,
+            FullReset = 0b10
+        ],
+
+        /// Writing the stop pattern cancels a running watchdog. Overlaps with WRCFG, which is
+        /// cleared by it.
+        RESET OFFSET(0) NUMBITS(12) [
+            Stop = 0x102
+        ]
+    ],
+
+    /// Watchdog Timer
+    WDOG [
+        /// Every write must carry the password, otherwise it is ignored.
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Magic = 0x5A
+        ],
+
+        /// Remaining time in ticks of 1/65536 seconds.
+        TIME OFFSET(0) NUMBITS(20) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x1C => RSTC: ReadWrite<u32, RSTC::Register>),
+        (0x20 => _reserved2),
+        (0x24 => WDOG: ReadWrite<u32, WDOG::Register>),
+        (0x28 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// Number of watchdog ticks before the board resets on `reset()`.
+const RESET_TICKS: u32 = 10;
+
+/// Watchdog ticks per second.
+const TICKS_PER_SEC: u64 = 65536;
+
+/// Largest value of the 20 bit timer field.
+const MAX_TICKS: u64 = (1 << 20) - 1;
+
+struct PMWatchdogInner {
+    registers: Registers,
+    /// Ticks the watchdog is reloaded with on every pet. Zero while disarmed.
+    timeout_ticks: u32,
+}
+
+impl PMWatchdogInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            timeout_ticks: 0,
+        }
+    }
+
+    /// Start the watchdog, resetting the board if it is not petted within `timeout_ticks`.
+    fn arm(&mut self, timeout_ticks: u32) {
+        self.timeout_ticks = timeout_ticks;
+        self.pet();
+        self.registers
+            .RSTC
+            .modify(RSTC::PASSWD::Magic + RSTC::WRCFG::FullReset);
+    }
+
+    /// Reload the watchdog timer. Does nothing while disarmed.
+    fn pet(&mut self) {
+        if self.timeout_ticks == 0 {
+            return;
+        }
+
+        self.registers
+            .WDOG
+            .write(WDOG::PASSWD::Magic + WDOG::TIME.val(self.timeout_ticks));
+    }
+
+    /// Stop the watchdog.
+    fn disable(&mut self) {
+        self.timeout_ticks = 0;
+        self.registers
+            .RSTC
+            .write(RSTC::PASSWD::Magic + RSTC::RESET::Stop);
+    }
+
+    /// Start the watchdog with a short timeout and configure it to do a full reset on expiry.
+    fn reset(&mut self) {
+        self.registers
+            .WDOG
+            .write(WDOG::PASSWD::Magic + WDOG::TIME.val(RESET_TICKS));
+        self.registers
+            .RSTC
+            .modify(RSTC::PASSWD::Magic + RSTC::WRCFG::FullReset);
+    }
+}
+
+/// Representation of the power management watchdog HW.
+pub struct PMWatchdog {
+    inner: IRQSafeNullLock<PMWatchdogInner>,
+}
+
+impl PMWatchdog {
+    pub const COMPATIBLE: &'static str = "BCM PM Watchdog";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(PMWatchdogInner::new(mmio_start_addr)),
+        }
+    }
+
+    /// Start the watchdog. The board resets if it is not petted within `timeout`.
+    pub fn arm(&self, timeout: Duration) -> Result<(), &'static str> {
+        let ticks = timeout.as_secs().saturating_mul(TICKS_PER_SEC)
+            + u64::from(timeout.subsec_nanos()) * TICKS_PER_SEC / 1_000_000_000;
+
+        if ticks == 0 || ticks > MAX_TICKS {
+            return Err("Watchdog timeout out of range");
+        }
+
+        self.inner.lock(|inner| inner.arm(ticks as u32));
+        Ok(())
+    }
+
+    /// Return true if the watchdog is armed.
+    pub fn is_armed(&self) -> bool {
+        self.inner.lock(|inner| inner.timeout_ticks != 0)
+    }
+
+    /// Reload the watchdog timer.
+    pub fn pet(&self) {
+        self.inner.lock(|inner| inner.pet())
+    }
+
+    /// Stop the watchdog.
+    pub fn disable(&self) {
+        self.inner.lock(|inner| inner.disable())
+    }
+
+    /// Reset the whole board. The reset happens a few watchdog ticks after the call.
+    pub fn reset(&self) -> ! {
+        self.inner.lock(|inner| inner.reset());
+
+        crate::cpu::wait_forever()
+    }
+}
+
+impl driver::interface::DeviceDriver for PMWatchdog {
+    type IRQNumberType = IRQNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+}
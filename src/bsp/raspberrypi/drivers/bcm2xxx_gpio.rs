@@ -0,0 +1,342 @@
+use crate::{
+    bsp::drivers::common::{BoundedUsize, MMIODerefWrapper},
+    driver,
+    exception::asynchronous::{irq_manager, IRQHandler, IRQHandlerDescriptor},
+    memory::{Address, Virtual},
+    synchronization::{IRQSafeNullLock, Mutex},
+};
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_structs,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
+};
+
+use super::gicv2::IRQNumber;
+
+// GPIO registers.
+//
+// Descriptions taken from
+// - https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf
+// - https://datasheets.raspberrypi.org/bcm2711/bcm2711-peripherals.pdf
+//
+// All registers are banks with a field of the same width for every pin:
+// - GPFSEL: Function Select, 3 bits per pin.
+// - GPSET, GPCLR: Pin Output Set and Clear, 1 bit per pin. Writing 0 has no effect.
+// - GPLEV: Pin Level, 1 bit per pin.
+// - GPEDS: Event Detect Status, 1 bit per pin. Latched, cleared by writing 1.
+// - GPREN, GPFEN: Rising and Falling Edge Detect Enable, 1 bit per pin.
+// - GPIO_PUP_PDN_CNTRL_REG: Pull-up / Pull-down, 2 bits per pin. BCM2711 only, which replaces the
+//   clocked GPPUD sequence of older SoCs.
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => GPFSEL: [ReadWrite<u32>; 6]),
+        (0x18 => _reserved1),
+        (0x1C => GPSET: [WriteOnly<u32>; 2]),
+        (0x24 => _reserved2),
+        (0x28 => GPCLR: [WriteOnly<u32>; 2]),
+        (0x30 => _reserved3),
+        (0x34 => GPLEV: [ReadOnly<u32>; 2]),
+        (0x3C => _reserved4),
+        (0x40 => GPEDS: [ReadWrite<u32>; 2]),
+        (0x48 => _reserved5),
+        (0x4C => GPREN: [ReadWrite<u32>; 2]),
+        (0x54 => _reserved6),
+        (0x58 => GPFEN: [ReadWrite<u32>; 2]),
+        (0x60 => _reserved7),
+        (0xE4 => GPIO_PUP_PDN_CNTRL_REG: [ReadWrite<u32>; 4]),
+        (0xF4 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// A GPIO pin number.
+pub type GPIOPin = BoundedUsize<{ GPIO::MAX_PIN }>;
+
+/// Function of a pin, as selected in the GPFSEL registers.
+///
+/// What the alternate functions are is listed per pin in the BCM2711 peripherals datasheet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GPIOFunction {
+    Input = 0b000,
+    Output = 0b001,
+    AltFunc0 = 0b100,
+    AltFunc1 = 0b101,
+    AltFunc2 = 0b110,
+    AltFunc3 = 0b111,
+    AltFunc4 = 0b011,
+    AltFunc5 = 0b010,
+}
+
+/// Resistor of a pin, as selected in the `GPIO_PUP_PDN_CNTRL_REG` registers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pull {
+    None = 0b00,
+    Up = 0b01,
+    Down = 0b10,
+}
+
+/// Edges of a pin's level that raise an interrupt.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// Called from the GPIO IRQ handler with the pin whose edge was detected.
+pub type EdgeHandler = fn(GPIOPin);
+
+struct GPIOInner {
+    registers: Registers,
+    edge_handlers: [Option<EdgeHandler>; GPIO::MAX_PIN + 1],
+}
+
+impl GPIOInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            edge_handlers: [None; GPIO::MAX_PIN + 1],
+        }
+    }
+
+    /// Replace the `width` bits of `pin` in a bank of registers that holds `32 / width` pins each.
+    fn modify_field(registers: &[ReadWrite<u32>], pin: GPIOPin, width: usize, value: u32) {
+        let pins_per_reg = 32 / width;
+        let reg = &registers[pin.get() / pins_per_reg];
+        let shift = (pin.get() % pins_per_reg) * width;
+        let mask = ((1 << width) - 1) << shift;
+
+        reg.set((reg.get() & !mask) | (value << shift));
+    }
+
+    pub fn set_function(&mut self, pin: GPIOPin, function: GPIOFunction) {
+        Self::modify_field(&self.registers.GPFSEL, pin, 3, function as u32);
+    }
+
+    pub fn set_pull(&mut self, pin: GPIOPin, pull: Pull) {
+        Self::modify_field(&self.registers.GPIO_PUP_PDN_CNTRL_REG, pin, 2, pull as u32);
+    }
+
+    pub fn read(&self, pin: GPIOPin) -> bool {
+        self.registers.GPLEV[pin.get() / 32].get() & (1 << (pin.get() % 32)) != 0
+    }
+
+    pub fn write(&mut self, pin: GPIOPin, level: bool) {
+        // Writing zeros to the set and clear registers has no effect, so no read-modify-write.
+        let regs = if level {
+            &self.registers.GPSET
+        } else {
+            &self.registers.GPCLR
+        };
+
+        regs[pin.get() / 32].set(1 << (pin.get() % 32));
+    }
+
+    pub fn enable_interrupt(&mut self, pin: GPIOPin, edge: Edge, handler: EdgeHandler) {
+        self.edge_handlers[pin.get()] = Some(handler);
+
+        // Drop an event latched before, so that the handler only sees new edges.
+        self.registers.GPEDS[pin.get() / 32].set(1 << (pin.get() % 32));
+
+        let rising = matches!(edge, Edge::Rising | Edge::Both);
+        let falling = matches!(edge, Edge::Falling | Edge::Both);
+        Self::modify_field(&self.registers.GPREN, pin, 1, rising as u32);
+        Self::modify_field(&self.registers.GPFEN, pin, 1, falling as u32);
+    }
+
+    pub fn disable_interrupt(&mut self, pin: GPIOPin) {
+        Self::modify_field(&self.registers.GPREN, pin, 1, 0);
+        Self::modify_field(&self.registers.GPFEN, pin, 1, 0);
+
+        self.edge_handlers[pin.get()] = None;
+    }
+
+    /// Clear the latched events and return them as a mask with one bit per pin.
+    fn take_events(&mut self) -> u64 {
+        let mut events = 0;
+
+        for (i, reg) in self.registers.GPEDS.iter().enumerate() {
+            let bits = reg.get();
+            reg.set(bits);
+            events |= (bits as u64) << (32 * i);
+        }
+
+        events
+    }
+
+    /// Select `function` on the UART pins and pull them up.
+    ///
+    /// TX to pin 14
+    /// RX to pin 15
+    fn map_uart(&mut self, function: GPIOFunction) {
+        for pin in [GPIOPin::new(14), GPIOPin::new(15)] {
+            self.set_function(pin, function);
+            self.set_pull(pin, Pull::Up);
+        }
+    }
+
+    /// Map PL011 UART as standard output.
+    #[cfg(not(feature = "mini_uart"))]
+    pub fn map_pl011_uart(&mut self) {
+        self.map_uart(GPIOFunction::AltFunc0);
+    }
+
+    /// Map the mini UART as standard output.
+    #[cfg(feature = "mini_uart")]
+    pub fn map_mini_uart(&mut self) {
+        self.map_uart(GPIOFunction::AltFunc5);
+    }
+}
+
+/// Representation of the GPIO HW.
+pub struct GPIO {
+    inner: IRQSafeNullLock<GPIOInner>,
+}
+
+impl GPIO {
+    pub const COMPATIBLE: &'static str = "BCM GPIO";
+
+    /// Highest pin number of the BCM2711.
+    pub const MAX_PIN: usize = 57;
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(GPIOInner::new(mmio_start_addr)),
+        }
+    }
+
+    /// Select the function of a pin.
+    pub fn set_function(&self, pin: GPIOPin, function: GPIOFunction) {
+        self.inner.lock(|inner| inner.set_function(pin, function))
+    }
+
+    /// Select the pull-up/down resistor of a pin.
+    pub fn set_pull(&self, pin: GPIOPin, pull: Pull) {
+        self.inner.lock(|inner| inner.set_pull(pin, pull))
+    }
+
+    /// Return the level of a pin. True is high.
+    ///
+    /// Works for output pins as well, which read back the level they drive.
+    pub fn read(&self, pin: GPIOPin) -> bool {
+        self.inner.lock(|inner| inner.read(pin))
+    }
+
+    /// Drive a pin high or low. Only has an effect on the pin while it is an output.
+    pub fn write(&self, pin: GPIOPin, level: bool) {
+        self.inner.lock(|inner| inner.write(pin, level))
+    }
+
+    /// Call `handler` from the IRQ handler whenever `edge` is detected on `pin`.
+    ///
+    /// Replaces a handler registered for the pin before. The pin should be an input. There is no
+    /// debouncing, so a mechanical button usually raises a few edges per press.
+    pub fn enable_interrupt(&self, pin: GPIOPin, edge: Edge, handler: EdgeHandler) {
+        self.inner
+            .lock(|inner| inner.enable_interrupt(pin, edge, handler))
+    }
+
+    /// Stop detecting edges on a pin and remove its handler.
+    pub fn disable_interrupt(&self, pin: GPIOPin) {
+        self.inner.lock(|inner| inner.disable_interrupt(pin))
+    }
+
+    /// Concurrency safe version of `GPIOInner.map_pl011_uart()`
+    #[cfg(not(feature = "mini_uart"))]
+    pub fn map_pl011_uart(&self) {
+        self.inner.lock(|inner| inner.map_pl011_uart())
+    }
+
+    /// Concurrency safe version of `GPIOInner.map_mini_uart()`
+    #[cfg(feature = "mini_uart")]
+    pub fn map_mini_uart(&self) {
+        self.inner.lock(|inner| inner.map_mini_uart())
+    }
+}
+
+impl driver::interface::DeviceDriver for GPIO {
+    type IRQNumberType = IRQNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &Self::IRQNumberType,
+    ) -> Result<(), &'static str> {
+        let descriptor = IRQHandlerDescriptor {
+            number: *irq_number,
+            name: Self::COMPATIBLE,
+            handler: self,
+            affinity: None,
+            priority: None,
+        };
+
+        irq_manager().register_handler(descriptor)?;
+        irq_manager().enable(irq_number);
+
+        Ok(())
+    }
+}
+
+impl IRQHandler for GPIO {
+    fn handle(&self) -> Result<(), &'static str> {
+        // Clear the latches first, so that an edge while the handlers run raises the IRQ again.
+        let events = self.inner.lock(|inner| inner.take_events());
+
+        for pin in (0..=GPIO::MAX_PIN).filter(|pin| events & (1 << pin) != 0) {
+            let pin = GPIOPin::new(pin);
+
+            // Handlers may use the GPIO, so they are called without the lock held.
+            if let Some(handler) = self.inner.lock(|inner| inner.edge_handlers[pin.get()]) {
+                handler(pin);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) mod selftests {
+    use super::{GPIOFunction, GPIOPin};
+    use crate::{bsp, selftest::SelfTest};
+
+    pub(crate) static TESTS: &[SelfTest] = &[SelfTest {
+        name: "GPIO output reads back",
+        run: test_gpio_loopback,
+        destructive: true,
+        needs_irqs: false,
+    }];
+
+    fn test_gpio_loopback() -> Result<(), &'static str> {
+        // Drives the activity LED of the Pi 4, so that nothing on the header is touched.
+        let pin = GPIOPin::new(42);
+        let gpio = bsp::driver::gpio().ok_or("GPIO driver not initialized")?;
+
+        gpio.set_function(pin, GPIOFunction::Output);
+
+        // End low, which leaves the LED off.
+        for level in [true, false] {
+            gpio.write(pin, level);
+            if gpio.read(pin) != level {
+                return Err("Pin level differs from the driven level");
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+//! Mini UART of the AUX peripheral block.
+//!
+//! A 16550-like UART with 8 byte FIFOs whose baud rate is derived from the VPU core clock. The core
+//! clock must therefore be fixed, e.g. with `enable_uart=1` or `core_freq_min=500` in config.txt.
+
+use core::fmt;
+
+use crate::{
+    bsp::drivers::common::MMIODerefWrapper,
+    console::{
+        input_router,
+        interface::{Console, Read, Statistics, Write},
+    },
+    cpu,
+    driver::interface::DeviceDriver,
+    exception::asynchronous::{irq_manager, IRQHandler, IRQHandlerDescriptor},
+    memory::{Address, Virtual},
+    synchronization::{IRQSafeNullLock, Mutex},
+    util::RingBuffer,
+};
+use tock_registers::{
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
+};
+
+use super::gicv2::IRQNumber;
+
+// AUX and mini UART registers.
+//
+// Descriptions taken from "BCM2711 ARM Peripherals", chapter 2.
+register_bitfields! {
+    u32,
+
+    /// Auxiliary Interrupt status.
+    AUX_IRQ [
+        /// If set the mini UART has an interrupt pending.
+        MINI_UART OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Auxiliary enables. The register also enables the two SPI masters, so it must only be
+    /// modified.
+    AUX_ENABLES [
+        /// If set the mini UART is enabled. The UART will immediately start receiving data,
+        /// especially if the UART1_RX line is low. If clear the mini UART is disabled and its
+        /// registers can not be accessed.
+        MINI_UART OFFSET(0) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+
+    /// Mini UART Interrupt Enable.
+    AUX_MU_IER [
+        /// If set the interrupt line is asserted whenever the receive FIFO holds at least 1 byte.
+        RX OFFSET(0) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+
+    /// Mini UART Interrupt Identify.
+    AUX_MU_IIR [
+        /// On write, clear the receive and transmit FIFOs.
+        FIFO_CLEAR OFFSET(1) NUMBITS(2) [
+            All = 0b11
+        ]
+    ],
+
+    /// Mini UART Line Control.
+    AUX_MU_LCR [
+        /// Data size. Both bits must be set for 8 bit mode, contrary to older datasheets.
+        DATA_SIZE OFFSET(0) NUMBITS(2) [
+            SevenBit = 0b00,
+            EightBit = 0b11
+        ]
+    ],
+
+    /// Mini UART Line Status.
+    AUX_MU_LSR [
+        /// Set if the transmit FIFO is empty and the transmitter is idle.
+        TX_IDLE OFFSET(6) NUMBITS(1) [],
+
+        /// Set if the transmit FIFO can accept at least one byte.
+        TX_EMPTY OFFSET(5) NUMBITS(1) [],
+
+        /// Set if the receive FIFO holds at least one byte.
+        DATA_READY OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Mini UART Extra Control.
+    AUX_MU_CNTL [
+        /// If clear the mini UART transmitter is disabled.
+        TX_ENABLE OFFSET(1) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
+        /// If clear the mini UART receiver is disabled.
+        RX_ENABLE OFFSET(0) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+
+    /// Mini UART Baudrate.
+    AUX_MU_BAUD [
+        /// `baud = core_clock / (8 * (BAUDRATE + 1))`.
+        BAUDRATE OFFSET(0) NUMBITS(16) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    pub RegisterBlock {
+        (0x00 => AUX_IRQ: ReadOnly<u32, AUX_IRQ::Register>),
+        (0x04 => AUX_ENABLES: ReadWrite<u32, AUX_ENABLES::Register>),
+        (0x08 => _reserved1),
+        (0x40 => AUX_MU_IO: ReadWrite<u32>),
+        (0x44 => AUX_MU_IER: ReadWrite<u32, AUX_MU_IER::Register>),
+        (0x48 => AUX_MU_IIR: WriteOnly<u32, AUX_MU_IIR::Register>),
+        (0x4C => AUX_MU_LCR: WriteOnly<u32, AUX_MU_LCR::Register>),
+        (0x50 => AUX_MU_MCR: WriteOnly<u32>),
+        (0x54 => AUX_MU_LSR: ReadOnly<u32, AUX_MU_LSR::Register>),
+        (0x58 => _reserved2),
+        (0x60 => AUX_MU_CNTL: WriteOnly<u32, AUX_MU_CNTL::Register>),
+        (0x64 => _reserved3),
+        (0x68 => AUX_MU_BAUD: WriteOnly<u32, AUX_MU_BAUD::Register>),
+        (0x6C => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+/// Maximum number of characters taken from the RX FIFO per IRQ. Anything left raises the IRQ again.
+const RX_BATCH_SIZE: usize = 32;
+
+/// Number of received characters that are buffered for the read functions.
+const RX_BUFFER_SIZE: usize = 256;
+
+/// The VPU core clock the baud rate is derived from.
+const CORE_CLOCK_HZ: u32 = 500_000_000;
+
+/// The baud rate set up by `init()`.
+const DEFAULT_BAUD: u32 = 115_200;
+
+#[derive(PartialEq)]
+enum BlockingMode {
+    Blocking,
+    NonBlocking,
+}
+
+struct MiniUartInner {
+    registers: Registers,
+    chars_written: usize,
+    chars_read: usize,
+    /// Characters received by the IRQ handler that no input consumer took.
+    rx_buffer: RingBuffer<RX_BUFFER_SIZE>,
+    /// Characters dropped because `rx_buffer` was full.
+    rx_overruns: usize,
+}
+
+impl MiniUartInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            chars_written: 0,
+            chars_read: 0,
+            rx_buffer: RingBuffer::new(),
+            rx_overruns: 0,
+        }
+    }
+
+    /// Set up baud rate and characteristics.
+    ///
+    /// This results in 8N1 and 115_200 baud: `500_000_000 / (8 * 115_200) - 1 = 541.5`, so the
+    /// divisor is `541`, which gives `115_314` baud and an error of 0.1%.
+    pub fn init(&mut self) {
+        // The mini UART registers can only be accessed once it is enabled in the AUX block.
+        self.registers
+            .AUX_ENABLES
+            .modify(AUX_ENABLES::MINI_UART::Enabled);
+
+        // Like for the PL011, send out queued characters before turning the UART off.
+        self.flush();
+
+        // Turn the transmitter and receiver off and mask IRQs while configuring.
+        self.registers.AUX_MU_CNTL.set(0);
+        self.registers.AUX_MU_IER.set(0);
+
+        // 8N1, RTS inactive and empty FIFOs.
+        self.registers
+            .AUX_MU_LCR
+            .write(AUX_MU_LCR::DATA_SIZE::EightBit);
+        self.registers.AUX_MU_MCR.set(0);
+        self.registers.AUX_MU_IIR.write(AUX_MU_IIR::FIFO_CLEAR::All);
+
+        let divisor = CORE_CLOCK_HZ / (8 * DEFAULT_BAUD) - 1;
+        self.registers
+            .AUX_MU_BAUD
+            .write(AUX_MU_BAUD::BAUDRATE.val(divisor));
+
+        // Enable the RX IRQ.
+        self.registers.AUX_MU_IER.write(AUX_MU_IER::RX::Enabled);
+
+        // Turn the UART on.
+        self.registers
+            .AUX_MU_CNTL
+            .write(AUX_MU_CNTL::TX_ENABLE::Enabled + AUX_MU_CNTL::RX_ENABLE::Enabled);
+    }
+
+    /// Send a character.
+    fn write_char(&mut self, c: char) {
+        // Spin until the TX FIFO can take a character.
+        while !self.registers.AUX_MU_LSR.is_set(AUX_MU_LSR::TX_EMPTY) {
+            cpu::nop();
+        }
+
+        self.registers.AUX_MU_IO.set(c as u32);
+
+        self.chars_written += 1;
+    }
+
+    /// Block execution until the last buffered character has been physically put on the TX wire.
+    fn flush(&self) {
+        while !self.registers.AUX_MU_LSR.is_set(AUX_MU_LSR::TX_IDLE) {
+            cpu::nop();
+        }
+    }
+
+    /// Take a character from the RX FIFO, if there is one.
+    fn read_fifo(&mut self) -> Option<u8> {
+        if !self.registers.AUX_MU_LSR.is_set(AUX_MU_LSR::DATA_READY) {
+            return None;
+        }
+
+        let ret = self.registers.AUX_MU_IO.get() as u8;
+
+        self.chars_read += 1;
+
+        Some(ret)
+    }
+
+    /// Keep a character that was received by the IRQ handler for the read functions.
+    fn buffer_rx(&mut self, byte: u8) {
+        if !self.rx_buffer.push(byte) {
+            self.rx_overruns += 1;
+        }
+    }
+
+    /// Retrieve a character, from the buffer first.
+    fn read_char(&mut self, blocking_mode: BlockingMode) -> Option<char> {
+        if let Some(byte) = self.rx_buffer.pop() {
+            return Some(byte as char);
+        }
+
+        loop {
+            if let Some(byte) = self.read_fifo() {
+                return Some(byte as char);
+            }
+
+            if blocking_mode == BlockingMode::NonBlocking {
+                return None;
+            }
+
+            cpu::nop();
+        }
+    }
+}
+
+impl fmt::Write for MiniUartInner {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if c == '\n' {
+                self.write_char('\r');
+            }
+            self.write_char(c);
+        }
+
+        Ok(())
+    }
+}
+
+/// Representation of the mini UART.
+pub struct MiniUart {
+    inner: IRQSafeNullLock<MiniUartInner>,
+}
+
+impl MiniUart {
+    pub const COMPATIBLE: &'static str = "BCM Mini UART";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(MiniUartInner::new(mmio_start_addr)),
+        }
+    }
+}
+
+impl DeviceDriver for MiniUart {
+    type IRQNumberType = IRQNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.init());
+
+        Ok(())
+    }
+
+    fn register_and_enable_irq_handler(
+        &'static self,
+        irq_number: &Self::IRQNumberType,
+    ) -> Result<(), &'static str> {
+        let descriptor = IRQHandlerDescriptor {
+            number: *irq_number,
+            name: Self::COMPATIBLE,
+            handler: self,
+            affinity: None,
+            priority: None,
+        };
+
+        irq_manager().register_handler(descriptor)?;
+        irq_manager().enable(irq_number);
+
+        Ok(())
+    }
+}
+
+impl Write for MiniUart {
+    fn write_char(&self, c: char) {
+        self.inner.lock(|inner| inner.write_char(c));
+    }
+
+    fn write_fmt(&self, args: core::fmt::Arguments) -> fmt::Result {
+        self.inner.lock(|inner| fmt::Write::write_fmt(inner, args))
+    }
+
+    fn flush(&self) {
+        self.inner.lock(|inner| inner.flush());
+    }
+}
+
+impl Read for MiniUart {
+    fn read_char(&self) -> char {
+        self.inner
+            .lock(|inner| inner.read_char(BlockingMode::Blocking).unwrap())
+    }
+
+    fn try_read_char(&self) -> Option<char> {
+        self.inner
+            .lock(|inner| inner.read_char(BlockingMode::NonBlocking))
+    }
+
+    fn clear_rx(&self) {
+        // Read from the buffer and the RX FIFO until both are empty.
+        while self
+            .inner
+            .lock(|inner| inner.read_char(BlockingMode::NonBlocking))
+            .is_some()
+        {}
+    }
+}
+
+impl Statistics for MiniUart {
+    fn chars_written(&self) -> usize {
+        self.inner.lock(|inner| inner.chars_written)
+    }
+
+    fn chars_read(&self) -> usize {
+        self.inner.lock(|inner| inner.chars_read)
+    }
+
+    fn rx_overruns(&self) -> usize {
+        self.inner.lock(|inner| inner.rx_overruns)
+    }
+}
+
+impl Console for MiniUart {}
+
+impl IRQHandler for MiniUart {
+    fn handle(&self) -> Result<(), &'static str> {
+        let mut received = [0u8; RX_BATCH_SIZE];
+
+        // The RX IRQ is level triggered and deasserts once the RX FIFO is empty.
+        let num_received = self.inner.lock(|inner| {
+            if !inner.registers.AUX_IRQ.is_set(AUX_IRQ::MINI_UART) {
+                return 0;
+            }
+
+            let mut num_received = 0;
+            while num_received < received.len() {
+                match inner.read_fifo() {
+                    None => break,
+                    Some(byte) => received[num_received] = byte,
+                }
+                num_received += 1;
+            }
+
+            num_received
+        });
+
+        // Consumers may write to the console, so they are called without the lock held.
+        for byte in &received[..num_received] {
+            input_router::route(*byte, |byte| self.inner.lock(|inner| inner.buffer_rx(byte)));
+        }
+
+        Ok(())
+    }
+}
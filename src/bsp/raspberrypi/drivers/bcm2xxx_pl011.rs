@@ -1,13 +1,18 @@
-use core::fmt;
+use core::{fmt, time::Duration};
 
 use crate::{
     bsp::drivers::common::MMIODerefWrapper,
-    console::interface::{Console, Read, Statistics, Write},
+    console::{
+        input_router,
+        interface::{Console, Read, Statistics, Write},
+    },
     cpu,
     driver::interface::DeviceDriver,
     exception::asynchronous::{irq_manager, IRQHandler, IRQHandlerDescriptor},
     memory::{Address, Virtual},
     synchronization::{IRQSafeNullLock, Mutex},
+    time,
+    util::RingBuffer,
 };
 use tock_registers::{
     interfaces::{Readable, Writeable},
@@ -23,6 +28,16 @@ use super::gicv2::IRQNumber;
 register_bitfields! {
     u32,
 
+    /// Data Register.
+    DR [
+        /// Overrun error. Set if data is received and the receive FIFO is already full. The FIFO
+        /// contents remain valid, the new character is lost.
+        OE OFFSET(11) NUMBITS(1) [],
+
+        /// Received data character on reads, data character to transmit on writes.
+        DATA OFFSET(0) NUMBITS(8) []
+    ],
+
     /// Flag Register.
     FR [
         /// Transmit FIFO empty. The meaning of this bit depends on the state of the FEN bit in the
@@ -112,6 +127,14 @@ register_bitfields! {
             Enabled = 1
         ],
 
+        /// Loopback enable. If this bit is set to 1 and the SIREN bit is set to 0, the UARTTXD path
+        /// is fed through to the UARTRXD path. This bit is cleared to 0 on reset, to disable
+        /// loopback.
+        LBE OFFSET(7) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
         /// UART enable:
         ///
         /// 0 = UART is disabled. If the UART is disabled in the middle of transmission or
@@ -183,7 +206,7 @@ register_bitfields! {
 register_structs! {
     #[allow(non_snake_case)]
     pub RegisterBlock {
-        (0x00 => DR: ReadWrite<u32>),
+        (0x00 => DR: ReadWrite<u32, DR::Register>),
         (0x04 => _reserved1),
         (0x18 => FR: ReadOnly<u32, FR::Register>),
         (0x1c => _reserved2),
@@ -203,6 +226,46 @@ register_structs! {
 /// Abstraction for the associated MMIO registers.
 type Registers = MMIODerefWrapper<RegisterBlock>;
 
+/// Maximum number of characters taken from the RX FIFO per IRQ. Anything left raises the IRQ again.
+const RX_BATCH_SIZE: usize = 32;
+
+/// Number of received characters that are buffered for the read functions.
+const RX_BUFFER_SIZE: usize = 256;
+
+/// The UART reference clock, set in config.txt.
+const UART_CLOCK_HZ: u64 = 48_000_000;
+
+/// The baud rate set up by `init()`.
+const DEFAULT_BAUD: u32 = 921_600;
+
+/// How long a looped back character may take. Far longer than one character at any usable rate.
+const LOOPBACK_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Return the integer and fractional baud rate divisors for `baud`.
+///
+/// The divisor is `UART_CLOCK_HZ / (16 * baud)`, with the fraction in 64ths. Computing
+/// `4 * UART_CLOCK_HZ / baud` gives it in 64ths directly, rounded to the nearest.
+fn baud_divisors(baud: u32) -> Result<(u32, u32), &'static str> {
+    if baud == 0 {
+        return Err("Baud rate must not be zero");
+    }
+
+    let baud = u64::from(baud);
+    let divisor_64ths = (4 * UART_CLOCK_HZ + baud / 2) / baud;
+    let (integer, fraction) = (divisor_64ths >> 6, divisor_64ths & 0x3f);
+
+    // The TRM requires 1 <= divisor <= 65535, with no fraction for the maximum.
+    if integer == 0 {
+        return Err("Baud rate too high for the UART clock");
+    }
+
+    if integer > 0xffff || (integer == 0xffff && fraction != 0) {
+        return Err("Baud rate too low for the UART clock");
+    }
+
+    Ok((integer as u32, fraction as u32))
+}
+
 #[derive(PartialEq)]
 enum BlockingMode {
     Blocking,
@@ -213,6 +276,10 @@ struct PL011UartInner {
     registers: Registers,
     chars_written: usize,
     chars_read: usize,
+    /// Characters received by the IRQ handler that no input consumer took.
+    rx_buffer: RingBuffer<RX_BUFFER_SIZE>,
+    /// Characters dropped because `rx_buffer` or the RX FIFO was full.
+    rx_overruns: usize,
 }
 
 impl PL011UartInner {
@@ -226,12 +293,14 @@ impl PL011UartInner {
             registers: Registers::new(mmio_start_addr),
             chars_written: 0,
             chars_read: 0,
+            rx_buffer: RingBuffer::new(),
+            rx_overruns: 0,
         }
     }
 
     /// Set up baud rate and characteristics.
     ///
-    /// This results in 8N1 and 921_600 baud.
+    /// This results in 8N1 and `DEFAULT_BAUD`, which is 921_600 baud.
     ///
     /// The calculation for the BRD is (we set the clock to 48 MHz in config.txt):
     /// `(48_000_000 / 16) / 921_600 = 3.2552083`.
@@ -263,31 +332,85 @@ impl PL011UartInner {
         // Clear all pending interrupts.
         self.registers.ICR.write(ICR::ALL::CLEAR);
 
+        // Set the baud rate, 8N1 and FIFO enabled. The default rate is always achievable.
+        let (integer, fraction) = baud_divisors(DEFAULT_BAUD).unwrap();
+        self.write_line_control(integer, fraction);
+
+        // Set RX FIFO fill level at 1/8.
+        self.registers.IFLS.write(IFLS::RXIFLSEL::OneEigth);
+
+        // Enable RX IRQ + RX timeout IRQ.
+        self.registers
+            .IMSC
+            .write(IMSC::RXIM::Enabled + IMSC::RTIM::Enabled);
+
+        self.enable();
+    }
+
+    /// Program the baud rate divisors, 8N1 and enabled FIFOs. The UART must be disabled.
+    fn write_line_control(&mut self, integer: u32, fraction: u32) {
         // From the PL011 Technical Reference Manual:
         //
         // The LCR_H, IBRD, and FBRD registers form the single 30-bit wide LCR Register that is
         // updated on a single write strobe generated by a LCR_H write. So, to internally update the
         // contents of IBRD or FBRD, a LCR_H write must always be performed at the end.
-        //
-        // Set the baud rate, 8N1 and FIFO enabled.
-        self.registers.IBRD.write(IBRD::BAUD_DIVINT.val(3));
-        self.registers.FBRD.write(FBRD::BAUD_DIVFRAC.val(16));
+        self.registers.IBRD.write(IBRD::BAUD_DIVINT.val(integer));
+        self.registers.FBRD.write(FBRD::BAUD_DIVFRAC.val(fraction));
         self.registers
             .LCR_H
             .write(LCR_H::WLEN::EightBit + LCR_H::FEN::FifosEnabled);
+    }
 
-        // Set RX FIFO fill level at 1/8.
-        self.registers.IFLS.write(IFLS::RXIFLSEL::OneEigth);
-
-        // Enable RX IRQ + RX timeout IRQ.
+    /// Turn the UART on.
+    fn enable(&mut self) {
         self.registers
-            .IMSC
-            .write(IMSC::RXIM::Enabled + IMSC::RTIM::Enabled);
+            .CR
+            .write(CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled);
+    }
+
+    /// Switch to another baud rate.
+    fn set_baud(&mut self, baud: u32) -> Result<(), &'static str> {
+        let (integer, fraction) = baud_divisors(baud)?;
+
+        // Like in `init()`, let queued characters go out at the old rate before turning the UART
+        // off.
+        self.flush();
+        self.registers.CR.set(0);
+
+        self.write_line_control(integer, fraction);
+        self.enable();
+
+        Ok(())
+    }
+
+    /// Send `byte` straight to the own receiver and return what arrived there.
+    fn loopback(&mut self, byte: u8) -> Result<u8, &'static str> {
+        // Let queued output go out and keep pending input, so that neither ends up in the loop.
+        self.flush();
+        self.drain_rx_fifo();
 
-        // Turn the UART on.
         self.registers
             .CR
-            .write(CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled);
+            .write(CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled + CR::LBE::Enabled);
+        self.registers.DR.set(byte as u32);
+
+        let deadline = time::uptime() + LOOPBACK_TIMEOUT;
+        let result = loop {
+            if !self.registers.FR.matches_all(FR::RXFE::SET) {
+                break Ok(self.registers.DR.read(DR::DATA) as u8);
+            }
+
+            if time::uptime() > deadline {
+                break Err("Looped back character did not arrive");
+            }
+
+            cpu::nop();
+        };
+
+        self.flush();
+        self.enable();
+
+        result
     }
 
     /// Send a character.
@@ -311,28 +434,58 @@ impl PL011UartInner {
         }
     }
 
-    /// Retrieve a character.
-    fn read_char(&mut self, blocking_mode: BlockingMode) -> Option<char> {
-        // If RX FIFO is empty,
+    /// Take a character from the RX FIFO, if there is one.
+    fn read_fifo(&mut self) -> Option<u8> {
         if self.registers.FR.matches_all(FR::RXFE::SET) {
-            // immediately return in non-blocking mode.
-            if blocking_mode == BlockingMode::NonBlocking {
-                return None;
-            }
-
-            // Otherwise, wait until a char was received.
-            while self.registers.FR.matches_all(FR::RXFE::SET) {
-                cpu::nop();
-            }
+            return None;
         }
 
-        // Read one character.
-        let ret = self.registers.DR.get() as u8 as char;
+        // Read one character. An overrun flagged with it means that a character after it was lost.
+        let data = self.registers.DR.extract();
+        if data.is_set(DR::OE) {
+            self.rx_overruns += 1;
+        }
 
         // Update statistics.
         self.chars_read += 1;
 
-        Some(ret)
+        Some(data.read(DR::DATA) as u8)
+    }
+
+    /// Move everything the RX FIFO holds into `rx_buffer`.
+    fn drain_rx_fifo(&mut self) {
+        while let Some(byte) = self.read_fifo() {
+            self.buffer_rx(byte);
+        }
+    }
+
+    /// Keep a received character for the read functions.
+    fn buffer_rx(&mut self, byte: u8) {
+        if !self.rx_buffer.push(byte) {
+            self.rx_overruns += 1;
+        }
+    }
+
+    /// Retrieve a character.
+    ///
+    /// Without IRQs, e.g. in the kernelloader, the callers poll. Every call empties the whole RX
+    /// FIFO into the buffer, so that a caller that does some work per character still keeps up with
+    /// the line. Characters buffered before were received first, so they are returned first.
+    fn read_char(&mut self, blocking_mode: BlockingMode) -> Option<char> {
+        loop {
+            self.drain_rx_fifo();
+
+            if let Some(byte) = self.rx_buffer.pop() {
+                return Some(byte as char);
+            }
+
+            // Immediately return in non-blocking mode, otherwise wait until a char was received.
+            if blocking_mode == BlockingMode::NonBlocking {
+                return None;
+            }
+
+            cpu::nop();
+        }
     }
 }
 
@@ -376,6 +529,23 @@ impl PL011Uart {
             inner: IRQSafeNullLock::new(PL011UartInner::new(mmio_start_addr)),
         }
     }
+
+    /// Switch the line to `baud`, derived from the 48 MHz UART clock.
+    ///
+    /// Waits until all queued characters are sent, so nothing is garbled by the switch. The other
+    /// side must switch as well. Returns an error, and keeps the current rate, if the divisors for
+    /// `baud` are out of range.
+    pub fn set_baud(&self, baud: u32) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.set_baud(baud))
+    }
+
+    /// Round-trip `byte` through the UART's internal loopback and return what was received.
+    ///
+    /// Nothing is sent on the line, so this works without a cable. Neither the byte nor the
+    /// received one are counted in the statistics.
+    pub fn loopback(&self, byte: u8) -> Result<u8, &'static str> {
+        self.inner.lock(|inner| inner.loopback(byte))
+    }
 }
 
 impl DeviceDriver for PL011Uart {
@@ -399,6 +569,8 @@ impl DeviceDriver for PL011Uart {
             number: *irq_number,
             name: Self::COMPATIBLE,
             handler: self,
+            affinity: None,
+            priority: None,
         };
 
         irq_manager().register_handler(descriptor)?;
@@ -433,8 +605,13 @@ impl Read for PL011Uart {
             .lock(|inner| inner.read_char(BlockingMode::Blocking).unwrap())
     }
 
+    fn try_read_char(&self) -> Option<char> {
+        self.inner
+            .lock(|inner| inner.read_char(BlockingMode::NonBlocking))
+    }
+
     fn clear_rx(&self) {
-        // Read from the RX FIFO until it is indicating empty.
+        // Read from the buffer and the RX FIFO until both are empty.
         while self
             .inner
             .lock(|inner| inner.read_char(BlockingMode::NonBlocking))
@@ -451,27 +628,67 @@ impl Statistics for PL011Uart {
     fn chars_read(&self) -> usize {
         self.inner.lock(|inner| inner.chars_read)
     }
+
+    fn rx_overruns(&self) -> usize {
+        self.inner.lock(|inner| inner.rx_overruns)
+    }
 }
 
 impl Console for PL011Uart {}
 
 impl IRQHandler for PL011Uart {
     fn handle(&self) -> Result<(), &'static str> {
-        self.inner.lock(|inner| {
+        let mut received = [0u8; RX_BATCH_SIZE];
+
+        let num_received = self.inner.lock(|inner| {
             let pending = inner.registers.MIS.extract();
 
             // Clear all pending IRQs.
             inner.registers.ICR.write(ICR::ALL::CLEAR);
 
             // Check for any kind of RX interrupt.
-            if pending.matches_any(MIS::RXMIS::SET + MIS::RTMIS::SET) {
-                // Echo any received characters.
-                while let Some(c) = inner.read_char(BlockingMode::NonBlocking) {
-                    inner.write_char(c)
+            if !pending.matches_any(MIS::RXMIS::SET + MIS::RTMIS::SET) {
+                return 0;
+            }
+
+            let mut num_received = 0;
+            while num_received < received.len() {
+                match inner.read_fifo() {
+                    None => break,
+                    Some(byte) => received[num_received] = byte,
                 }
+                num_received += 1;
             }
+
+            num_received
         });
 
+        // Consumers may write to the console, so they are called without the lock held.
+        for byte in &received[..num_received] {
+            input_router::route(*byte, |byte| self.inner.lock(|inner| inner.buffer_rx(byte)));
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) mod selftests {
+    use crate::{bsp, selftest::SelfTest};
+
+    pub(crate) static TESTS: &[SelfTest] = &[SelfTest {
+        name: "UART loopback round trip",
+        run: test_uart_loopback,
+        destructive: true,
+        needs_irqs: false,
+    }];
+
+    fn test_uart_loopback() -> Result<(), &'static str> {
+        for byte in [0x00, 0x55, 0xAA, 0xFF] {
+            if bsp::driver::console_uart_loopback(byte)? != byte {
+                return Err("Received a different byte");
+            }
+        }
+
         Ok(())
     }
 }
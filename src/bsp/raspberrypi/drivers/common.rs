@@ -0,0 +1,107 @@
+use core::{marker::PhantomData, ops::Deref};
+
+use crate::memory::{Address, Virtual};
+
+pub struct MMIODerefWrapper<T> {
+    start_addr: Address<Virtual>,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> MMIODerefWrapper<T> {
+    /// Create an instance.
+    pub const unsafe fn new(start_addr: Address<Virtual>) -> Self {
+        Self {
+            start_addr,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for MMIODerefWrapper<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*(self.start_addr.as_usize() as *const _) }
+    }
+}
+
+/// A wrapper for an array of `N` consecutive MMIO registers of type `T`.
+///
+/// Unlike indexing into a `register_structs!` array, the array does not need to be part of a
+/// register block, so sparse per-IRQ or per-pin register banks can be described by their start
+/// address alone. Element access is always bounds-checked.
+pub struct MMIOArray<T, const N: usize> {
+    start_addr: Address<Virtual>,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T, const N: usize> MMIOArray<T, { N }> {
+    pub const LEN: usize = N;
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure that `N` registers of type `T` are mapped at `start_addr`.
+    pub const unsafe fn new(start_addr: Address<Virtual>) -> Self {
+        Self {
+            start_addr,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Return the register at `index`, or `None` if the index is out of bounds.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= N {
+            return None;
+        }
+
+        let ptr = self.start_addr.as_usize() as *const T;
+        Some(unsafe { &*ptr.add(index) })
+    }
+
+    /// Return the register at `index`.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn reg(&self, index: usize) -> &T {
+        match self.get(index) {
+            None => panic!("MMIO array index out of bounds: {} >= {}", index, N),
+            Some(x) => x,
+        }
+    }
+
+    /// Iterate over the first `n` registers.
+    ///
+    /// Panics if `n` exceeds the length of the array.
+    pub fn iter_first(&self, n: usize) -> impl Iterator<Item = &T> {
+        assert!(n <= N, "MMIO array range out of bounds: {} > {}", n, N);
+
+        (0..n).map(|i| self.reg(i))
+    }
+}
+
+/// A wrapper type for usize with integrated range bound check.
+#[derive(Copy, Clone)]
+pub struct BoundedUsize<const MAX_INCLUSIVE: usize>(usize);
+
+impl<const MAX_INCLUSIVE: usize> BoundedUsize<{ MAX_INCLUSIVE }> {
+    pub const MAX_INCLUSIVE: usize = MAX_INCLUSIVE;
+
+    /// Creates a new instance if number <= MAX_INCLUSIVE.
+    pub const fn new(number: usize) -> Self {
+        assert!(number <= MAX_INCLUSIVE);
+
+        Self(number)
+    }
+
+    /// Return the wrapped number.
+    pub const fn get(self) -> usize {
+        self.0
+    }
+}
+
+impl<const MAX_INCLUSIVE: usize> core::fmt::Display for BoundedUsize<{ MAX_INCLUSIVE }> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
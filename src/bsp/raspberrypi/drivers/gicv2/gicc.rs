@@ -1,12 +1,13 @@
 use crate::{
     bsp::drivers::common::MMIODerefWrapper,
     exception::asynchronous::IRQContext,
+    info,
     memory::{Address, Virtual},
 };
 use tock_registers::{
     interfaces::{Readable, Writeable},
     register_bitfields, register_structs,
-    registers::ReadWrite,
+    registers::{ReadOnly, ReadWrite},
 };
 
 register_bitfields! {
@@ -22,14 +23,31 @@ register_bitfields! {
         Priority OFFSET(0) NUMBITS(8) []
     ],
 
+    /// Binary Point Register
+    BPR [
+        BinaryPoint OFFSET(0) NUMBITS(3) []
+    ],
+
     /// Interrupt Acknowledge Register
     IAR [
+        CPUID OFFSET(10) NUMBITS(3) [],
         InterruptID OFFSET(0) NUMBITS(10) []
     ],
 
     /// End of Interrupt Register
     EOIR [
+        CPUID OFFSET(10) NUMBITS(3) [],
         EOIINTID OFFSET(0) NUMBITS(10) []
+    ],
+
+    /// Running Priority Register
+    RPR [
+        Priority OFFSET(0) NUMBITS(8) []
+    ],
+
+    /// Highest Priority Pending Interrupt Register
+    HPPIR [
+        PENDINTID OFFSET(0) NUMBITS(10) []
     ]
 }
 
@@ -38,10 +56,12 @@ register_structs! {
     pub RegisterBlock {
         (0x000 => CTLR: ReadWrite<u32, CTLR::Register>),
         (0x004 => PMR: ReadWrite<u32, PMR::Register>),
-        (0x008 => _reserved1),
+        (0x008 => BPR: ReadWrite<u32, BPR::Register>),
         (0x00C => IAR: ReadWrite<u32, IAR::Register>),
         (0x010 => EOIR: ReadWrite<u32, EOIR::Register>),
-        (0x014  => @END),
+        (0x014 => RPR: ReadOnly<u32, RPR::Register>),
+        (0x018 => HPPIR: ReadOnly<u32, HPPIR::Register>),
+        (0x01C => @END),
     }
 }
 
@@ -80,6 +100,22 @@ impl GICC {
         self.registers.PMR.write(PMR::Priority.val(255)); // Comment in arch spec.
     }
 
+    /// Use as many priority bits as possible for the group priority, which allows the finest
+    /// grained preemption between IRQs.
+    ///
+    /// Quoting the GICv2 Architecture Specification:
+    ///
+    ///   "If the value written to the register is lower than the minimum value supported, the
+    ///    register is set to the minimum value."
+    ///
+    /// # Safety
+    ///
+    /// - GICC MMIO registers are banked per CPU core. It is therefore safe to have `&self` instead
+    ///   of `&mut self`.
+    pub fn binary_point_min(&self) {
+        self.registers.BPR.write(BPR::BinaryPoint.val(0));
+    }
+
     /// Enable the interface - start accepting IRQs.
     ///
     /// # Safety
@@ -90,7 +126,18 @@ impl GICC {
         self.registers.CTLR.write(CTLR::Enable::SET);
     }
 
-    /// Extract the number of the highest-priority pending IRQ.
+    /// Return true if the executing core's interface accepts IRQs.
+    ///
+    /// # Safety
+    ///
+    /// - GICC MMIO registers are banked per CPU core. It is therefore safe to have `&self` instead
+    ///   of `&mut self`.
+    pub fn is_enabled(&self) -> bool {
+        self.registers.CTLR.is_set(CTLR::Enable)
+    }
+
+    /// Extract the number of the highest-priority pending IRQ, and for SGIs the number of the
+    /// core that requested it. Zero for other IRQs.
     ///
     /// Can only be called from IRQ context, which is ensured by taking an `IRQContext` token.
     ///
@@ -99,22 +146,52 @@ impl GICC {
     /// - GICC MMIO registers are banked per CPU core. It is therefore safe to have `&self` instead
     ///   of `&mut self`.
     #[allow(clippy::trivially_copy_pass_by_ref)]
-    pub fn pending_irq_number<'irq_context>(&self, _ic: &IRQContext<'irq_context>) -> usize {
-        self.registers.IAR.read(IAR::InterruptID) as usize
+    pub fn pending_irq_number<'irq_context>(&self, _ic: &IRQContext<'irq_context>) -> (usize, u32) {
+        let iar = self.registers.IAR.extract();
+
+        (iar.read(IAR::InterruptID) as usize, iar.read(IAR::CPUID))
     }
 
     /// Complete handling of the currently active IRQ.
     ///
     /// Can only be called from IRQ context, which is ensured by taking an `IRQContext` token.
     ///
-    /// To be called after `pending_irq_number()`.
+    /// To be called after `pending_irq_number()`, with both values it returned. An SGI is only
+    /// completed if the requesting core matches.
     ///
     /// # Safety
     ///
     /// - GICC MMIO registers are banked per CPU core. It is therefore safe to have `&self` instead
     ///   of `&mut self`.
     #[allow(clippy::trivially_copy_pass_by_ref)]
-    pub fn mark_comleted<'irq_context>(&self, irq_number: u32, _ic: &IRQContext<'irq_context>) {
-        self.registers.EOIR.write(EOIR::EOIINTID.val(irq_number));
+    pub fn mark_comleted<'irq_context>(
+        &self,
+        irq_number: u32,
+        source_core: u32,
+        _ic: &IRQContext<'irq_context>,
+    ) {
+        self.registers
+            .EOIR
+            .write(EOIR::EOIINTID.val(irq_number) + EOIR::CPUID.val(source_core));
+    }
+
+    /// Print the state of the executing core's CPU interface.
+    ///
+    /// # Safety
+    ///
+    /// - GICC MMIO registers are banked per CPU core. It is therefore safe to have `&self` instead
+    ///   of `&mut self`.
+    pub fn dump_state(&self) {
+        info!(
+            "      CPU interface: {}, priority mask {:#04x}, running priority {:#04x}, highest pending IRQ {}",
+            if self.registers.CTLR.is_set(CTLR::Enable) {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            self.registers.PMR.read(PMR::Priority),
+            self.registers.RPR.read(RPR::Priority),
+            self.registers.HPPIR.read(HPPIR::PENDINTID)
+        );
     }
 }
@@ -0,0 +1,599 @@
+use super::{IRQNumber, SGITarget, DEFAULT_PRIORITY};
+use crate::{
+    bsp::{
+        cpu::NUM_CORES,
+        drivers::common::{MMIOArray, MMIODerefWrapper},
+    },
+    cpu::smp::CoreMask,
+    info,
+    memory::{Address, Virtual},
+    state, synchronization,
+    synchronization::IRQSafeNullLock,
+};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use synchronization::Mutex;
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
+};
+
+register_bitfields! {
+    u32,
+
+    /// Distributor Control Register
+    CTLR [
+        Enable OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Interrupt Controller Type Register
+    TYPER [
+        ITLinesNumber OFFSET(0)  NUMBITS(5) []
+    ],
+
+    /// Interrupt Priority Registers
+    IPRIORITYR [
+        Offset3 OFFSET(24) NUMBITS(8) [],
+        Offset2 OFFSET(16) NUMBITS(8) [],
+        Offset1 OFFSET(8)  NUMBITS(8) [],
+        Offset0 OFFSET(0)  NUMBITS(8) []
+    ],
+
+    /// Interrupt Processor Targets Registers
+    ITARGETSR [
+        Offset3 OFFSET(24) NUMBITS(8) [],
+        Offset2 OFFSET(16) NUMBITS(8) [],
+        Offset1 OFFSET(8)  NUMBITS(8) [],
+        Offset0 OFFSET(0)  NUMBITS(8) []
+    ],
+
+    /// Software Generated Interrupt Register
+    SGIR [
+        TargetListFilter OFFSET(24) NUMBITS(2) [
+            TargetList = 0b00,
+            AllButSelf = 0b01,
+            OnlySelf = 0b10
+        ],
+        CPUTargetList OFFSET(16) NUMBITS(8) [],
+        SGIINTID OFFSET(0) NUMBITS(4) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    SharedRegisterBlock {
+        (0x000 => CTLR: ReadWrite<u32, CTLR::Register>),
+        (0x004 => TYPER: ReadOnly<u32, TYPER::Register>),
+        (0x008 => _reserved1),
+        (0xF00 => SGIR: WriteOnly<u32, SGIR::Register>),
+        (0xF04 => @END),
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    BankedRegisterBlock {
+        (0x000 => _reserved1),
+        (0x100 => ISENABLER: ReadWrite<u32>),
+        (0x104 => _reserved2),
+        (0x200 => ISPENDR: ReadWrite<u32>),
+        (0x204 => _reserved3),
+        (0x300 => ISACTIVER: ReadWrite<u32>),
+        (0x304 => @END),
+    }
+}
+
+/// Offsets of the per-IRQ register arrays, relative to the distributor base.
+const ISENABLER_OFFSET: usize = 0x100;
+const ISPENDR_OFFSET: usize = 0x200;
+const ISACTIVER_OFFSET: usize = 0x300;
+const IPRIORITYR_OFFSET: usize = 0x400;
+const ITARGETSR_OFFSET: usize = 0x800;
+const ICFGR_OFFSET: usize = 0xC00;
+
+/// Number of registers covering the 32 private IRQs, for one bit, one byte and two bits per IRQ
+/// respectively.
+const PRIVATE_BIT_REG_COUNT: usize = 1;
+const PRIVATE_BYTE_REG_COUNT: usize = 8;
+const PRIVATE_CONFIG_REG_COUNT: usize = 2;
+
+/// The upper bit of an IRQ's two configuration bits. Set for edge-triggered IRQs, clear for
+/// level-sensitive ones.
+const ICFGR_EDGE_TRIGGERED: u32 = 0b10;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_PRIVATE_PRIORITY: AtomicU8 = AtomicU8::new(DEFAULT_PRIORITY);
+
+/// Abstraction for the non-banked parts of the associated MMIO registers.
+#[allow(non_snake_case)]
+struct SharedRegisters {
+    block: MMIODerefWrapper<SharedRegisterBlock>,
+
+    /// Set-enable registers for the SPIs, one bit per IRQ.
+    ISENABLER: MMIOArray<ReadWrite<u32>, 31>,
+
+    /// Set-pending registers for the SPIs, one bit per IRQ.
+    ISPENDR: MMIOArray<ReadWrite<u32>, 31>,
+
+    /// Set-active registers for the SPIs, one bit per IRQ.
+    ISACTIVER: MMIOArray<ReadWrite<u32>, 31>,
+
+    /// Priority registers for the SPIs, one byte per IRQ.
+    IPRIORITYR: MMIOArray<ReadWrite<u32, IPRIORITYR::Register>, 248>,
+
+    /// Target registers for the SPIs, one byte per IRQ.
+    ITARGETSR: MMIOArray<ReadWrite<u32, ITARGETSR::Register>, 248>,
+
+    /// Configuration registers for the SPIs, two bits per IRQ.
+    ICFGR: MMIOArray<ReadWrite<u32>, 62>,
+}
+
+impl SharedRegisters {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        let base = mmio_start_addr.as_usize();
+
+        Self {
+            block: MMIODerefWrapper::new(mmio_start_addr),
+            ISENABLER: MMIOArray::new(Address::new(
+                base + ISENABLER_OFFSET + PRIVATE_BIT_REG_COUNT * 4,
+            )),
+            ISPENDR: MMIOArray::new(Address::new(
+                base + ISPENDR_OFFSET + PRIVATE_BIT_REG_COUNT * 4,
+            )),
+            ISACTIVER: MMIOArray::new(Address::new(
+                base + ISACTIVER_OFFSET + PRIVATE_BIT_REG_COUNT * 4,
+            )),
+            IPRIORITYR: MMIOArray::new(Address::new(
+                base + IPRIORITYR_OFFSET + PRIVATE_BYTE_REG_COUNT * 4,
+            )),
+            ITARGETSR: MMIOArray::new(Address::new(
+                base + ITARGETSR_OFFSET + PRIVATE_BYTE_REG_COUNT * 4,
+            )),
+            ICFGR: MMIOArray::new(Address::new(
+                base + ICFGR_OFFSET + PRIVATE_CONFIG_REG_COUNT * 4,
+            )),
+        }
+    }
+
+    /// Return the number of IRQs that this HW implements.
+    #[inline(always)]
+    fn num_irqs(&self) -> usize {
+        // Query number of implemented IRQs.
+        //
+        // Refer to GICv2 Architecture Specification, Section 4.3.2.
+        ((self.block.TYPER.read(TYPER::ITLinesNumber) as usize) + 1) * 32
+    }
+
+    /// Return the number of implemented shared registers that hold one byte per IRQ.
+    #[inline(always)]
+    fn num_implemented_spi_byte_regs(&self) -> usize {
+        assert!(self.num_irqs() >= 36);
+
+        // The first 32 IRQs are private, so not included in the shared arrays. Each register has
+        // four entries, so shift right by two.
+        (self.num_irqs() - 32) >> 2
+    }
+}
+
+/// Abstraction for the banked parts of the associated MMIO registers.
+#[allow(non_snake_case)]
+struct BankedRegisters {
+    block: MMIODerefWrapper<BankedRegisterBlock>,
+
+    /// Priority registers for the SGIs and PPIs, one byte per IRQ.
+    IPRIORITYR: MMIOArray<ReadWrite<u32, IPRIORITYR::Register>, PRIVATE_BYTE_REG_COUNT>,
+
+    /// Target registers for the SGIs and PPIs, one byte per IRQ.
+    ITARGETSR: MMIOArray<ReadOnly<u32, ITARGETSR::Register>, PRIVATE_BYTE_REG_COUNT>,
+
+    /// Configuration registers for the SGIs and PPIs, two bits per IRQ.
+    ICFGR: MMIOArray<ReadOnly<u32>, PRIVATE_CONFIG_REG_COUNT>,
+}
+
+impl BankedRegisters {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        let base = mmio_start_addr.as_usize();
+
+        Self {
+            block: MMIODerefWrapper::new(mmio_start_addr),
+            IPRIORITYR: MMIOArray::new(Address::new(base + IPRIORITYR_OFFSET)),
+            ITARGETSR: MMIOArray::new(Address::new(base + ITARGETSR_OFFSET)),
+            ICFGR: MMIOArray::new(Address::new(base + ICFGR_OFFSET)),
+        }
+    }
+}
+
+/// Representation of the GIC Distributor.
+pub struct GICD {
+    /// Access to shared registers is guarded with a lock.
+    shared_registers: IRQSafeNullLock<SharedRegisters>,
+
+    /// Access to banked registers is unguarded.
+    banked_registers: BankedRegisters,
+
+    /// Private IRQs that were enabled so far. Each core has its own copy of the banked enable
+    /// register, so cores that come up later replay this mask in `init_this_core()`.
+    private_irqs_enabled: AtomicU32,
+
+    /// Priorities of the private IRQs, replayed in `init_this_core()` like the enable mask.
+    private_priorities: [AtomicU8; 32],
+}
+
+impl GICD {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: Address<Virtual>) -> Self {
+        Self {
+            shared_registers: IRQSafeNullLock::new(SharedRegisters::new(mmio_start_addr)),
+            banked_registers: BankedRegisters::new(mmio_start_addr),
+            private_irqs_enabled: AtomicU32::new(0),
+            private_priorities: [DEFAULT_PRIVATE_PRIORITY; 32],
+        }
+    }
+
+    /// Use a banked ITARGETSR to retrieve the executing core's GIC target mask.
+    ///
+    /// Quoting the GICv2 Architecture Specification:
+    ///
+    ///   "GICD_ITARGETSR0 to GICD_ITARGETSR7 are read-only, and each field returns a value that
+    ///    corresponds only to the processor reading the register."
+    fn local_gic_target_mask(&self) -> u32 {
+        self.banked_registers
+            .ITARGETSR
+            .reg(0)
+            .read(ITARGETSR::Offset0)
+    }
+
+    /// Route all SPIs to the boot core, give them the default priority and enable the
+    /// distributor.
+    pub fn boot_core_init(&self) {
+        assert!(
+            state::state_manager().is_init(),
+            "Only allowed during kernel init phase"
+        );
+
+        // Target all SPIs to the boot core only.
+        let mask = self.local_gic_target_mask();
+
+        self.shared_registers.lock(|regs| {
+            let num_regs = regs.num_implemented_spi_byte_regs();
+
+            for i in regs.ITARGETSR.iter_first(num_regs) {
+                i.write(
+                    ITARGETSR::Offset3.val(mask)
+                        + ITARGETSR::Offset2.val(mask)
+                        + ITARGETSR::Offset1.val(mask)
+                        + ITARGETSR::Offset0.val(mask),
+                );
+            }
+
+            let priority = DEFAULT_PRIORITY as u32;
+            for i in regs.IPRIORITYR.iter_first(num_regs) {
+                i.write(
+                    IPRIORITYR::Offset3.val(priority)
+                        + IPRIORITYR::Offset2.val(priority)
+                        + IPRIORITYR::Offset1.val(priority)
+                        + IPRIORITYR::Offset0.val(priority),
+                );
+            }
+
+            regs.block.CTLR.write(CTLR::Enable::SET);
+        });
+    }
+
+    /// Apply the priorities of the private IRQs and enable all private IRQs that were enabled so
+    /// far on the executing core.
+    ///
+    /// SGIs and PPIs are banked per core, so every core must call this once during its own
+    /// bring-up. SPIs are shared and need no per-core setup.
+    pub fn init_this_core(&self) {
+        for (i, reg) in self
+            .banked_registers
+            .IPRIORITYR
+            .iter_first(PRIVATE_BYTE_REG_COUNT)
+            .enumerate()
+        {
+            let value = (0..4).fold(0, |value, byte| {
+                let priority = self.private_priorities[i * 4 + byte].load(Ordering::Acquire);
+                value | ((priority as u32) << (byte * 8))
+            });
+            reg.set(value);
+        }
+
+        let enable_reg = &self.banked_registers.block.ISENABLER;
+        enable_reg.set(enable_reg.get() | self.private_irqs_enabled.load(Ordering::Acquire));
+    }
+
+    /// Check that the executing core's banked registers hold the enables and priorities that
+    /// `init_this_core()` replays.
+    ///
+    /// Only the implemented upper four priority bits are compared, see the module documentation.
+    pub fn check_this_core(&self) -> Result<(), &'static str> {
+        let enabled = self.private_irqs_enabled.load(Ordering::Acquire);
+        if self.banked_registers.block.ISENABLER.get() & enabled != enabled {
+            return Err("Private IRQ is not enabled on this core");
+        }
+
+        for (irq_num, expected) in self.private_priorities.iter().enumerate() {
+            let reg = self.banked_registers.IPRIORITYR.reg(irq_num >> 2).get();
+            let priority = (reg >> ((irq_num % 4) * 8)) as u8;
+
+            if priority & 0xf0 != expected.load(Ordering::Acquire) & 0xf0 {
+                return Err("Private IRQ has a different priority on this core");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Enable an interrupt.
+    ///
+    /// Private IRQs are enabled on the executing core right away and on every other core once it
+    /// calls `init_this_core()`.
+    pub fn enable(&self, irq_num: &IRQNumber) {
+        let irq_num = irq_num.get();
+
+        // Each bit in the u32 enable register corresponds to one IRQ number. Shift right by 5
+        // (division by 32) and arrive at the index for the respective ISENABLER[i].
+        let enable_reg_index = irq_num >> 5;
+        let enable_bit: u32 = 1u32 << (irq_num % 32);
+
+        // Check if we are handling a private or shared IRQ.
+        match irq_num {
+            // Private.
+            0..=31 => {
+                self.private_irqs_enabled
+                    .fetch_or(enable_bit, Ordering::AcqRel);
+
+                let enable_reg = &self.banked_registers.block.ISENABLER;
+                enable_reg.set(enable_reg.get() | enable_bit);
+            }
+            // Shared.
+            _ => {
+                let enable_reg_index_shared = enable_reg_index - 1;
+
+                self.shared_registers.lock(|regs| {
+                    let enable_reg = regs.ISENABLER.reg(enable_reg_index_shared);
+                    enable_reg.set(enable_reg.get() | enable_bit);
+                });
+            }
+        }
+    }
+
+    /// Route a shared IRQ to the given set of cores.
+    ///
+    /// SGIs and PPIs are private to each core and have no target register, so routing them is
+    /// rejected.
+    pub fn set_affinity(&self, irq_num: &IRQNumber, mask: CoreMask) -> Result<(), &'static str> {
+        let irq_num = irq_num.get();
+
+        if irq_num < 32 {
+            return Err("Private IRQs cannot be routed to other cores");
+        }
+
+        if mask.is_empty() {
+            return Err("IRQ must be routed to at least one core");
+        }
+
+        // Each ITARGETSR register holds the targets of four IRQs, one byte each.
+        let target_reg_index_shared = (irq_num >> 2) - PRIVATE_BYTE_REG_COUNT;
+        let target_shift = (irq_num % 4) * 8;
+
+        self.shared_registers.lock(|regs| {
+            if target_reg_index_shared >= regs.num_implemented_spi_byte_regs() {
+                return Err("IRQ is not implemented by the distributor");
+            }
+
+            let target_reg = regs.ITARGETSR.reg(target_reg_index_shared);
+            let value = (target_reg.get() & !(0xff << target_shift))
+                | ((mask.bits() as u32) << target_shift);
+            target_reg.set(value);
+
+            Ok(())
+        })
+    }
+
+    /// Mark a shared IRQ pending, as if its peripheral had raised it.
+    ///
+    /// The IRQ is delivered to one of the cores it is routed to, like one raised by hardware.
+    pub fn set_pending(&self, irq_num: &IRQNumber) -> Result<(), &'static str> {
+        let irq_num = irq_num.get();
+
+        if irq_num < 32 {
+            return Err("Only shared IRQs can be made pending");
+        }
+
+        self.shared_registers.lock(|regs| {
+            // Writing zeroes has no effect, so no read-modify-write is needed.
+            regs.ISPENDR
+                .reg((irq_num >> 5) - PRIVATE_BIT_REG_COUNT)
+                .set(1 << (irq_num % 32));
+        });
+
+        Ok(())
+    }
+
+    /// Return true if a shared IRQ is pending, i.e. no core has acknowledged it yet.
+    pub fn is_pending(&self, irq_num: &IRQNumber) -> Result<bool, &'static str> {
+        let irq_num = irq_num.get();
+
+        if irq_num < 32 {
+            return Err("Only shared IRQs can be queried");
+        }
+
+        Ok(self.shared_registers.lock(|regs| {
+            regs.ISPENDR
+                .reg((irq_num >> 5) - PRIVATE_BIT_REG_COUNT)
+                .get()
+                & (1 << (irq_num % 32))
+                != 0
+        }))
+    }
+
+    /// Set the priority of an IRQ.
+    ///
+    /// Like enabling, the priority of a private IRQ applies to the executing core right away and
+    /// to every other core once it calls `init_this_core()`.
+    pub fn set_priority(&self, irq_num: &IRQNumber, priority: u8) -> Result<(), &'static str> {
+        let irq_num = irq_num.get();
+
+        // Each IPRIORITYR register holds the priorities of four IRQs, one byte each.
+        let priority_reg_index = irq_num >> 2;
+        let priority_shift = (irq_num % 4) * 8;
+        let set_byte =
+            |reg: u32| (reg & !(0xff << priority_shift)) | ((priority as u32) << priority_shift);
+
+        match irq_num {
+            // Private.
+            0..=31 => {
+                self.private_priorities[irq_num].store(priority, Ordering::Release);
+
+                let priority_reg = self.banked_registers.IPRIORITYR.reg(priority_reg_index);
+                priority_reg.set(set_byte(priority_reg.get()));
+
+                Ok(())
+            }
+            // Shared.
+            _ => {
+                let priority_reg_index_shared = priority_reg_index - PRIVATE_BYTE_REG_COUNT;
+
+                self.shared_registers.lock(|regs| {
+                    if priority_reg_index_shared >= regs.num_implemented_spi_byte_regs() {
+                        return Err("IRQ is not implemented by the distributor");
+                    }
+
+                    let priority_reg = regs.IPRIORITYR.reg(priority_reg_index_shared);
+                    priority_reg.set(set_byte(priority_reg.get()));
+
+                    Ok(())
+                })
+            }
+        }
+    }
+
+    /// Send the SGI `sgi_num` to the cores selected by `target`.
+    ///
+    /// The SGI is pending on each target core until that core acknowledges it.
+    pub fn send_sgi(&self, sgi_num: &IRQNumber, target: SGITarget) -> Result<(), &'static str> {
+        let sgi_num = sgi_num.get();
+
+        if sgi_num > 15 {
+            return Err("IRQ is not an SGI");
+        }
+
+        let filter_and_list = match target {
+            SGITarget::AllButSelf => SGIR::TargetListFilter::AllButSelf,
+            SGITarget::Only(core_id) => {
+                if core_id >= NUM_CORES {
+                    return Err("SGI target core does not exist");
+                }
+
+                SGIR::TargetListFilter::TargetList + SGIR::CPUTargetList.val(1 << core_id)
+            }
+            SGITarget::List(mask) => {
+                if mask.is_empty() {
+                    return Err("SGI must target at least one core");
+                }
+
+                SGIR::TargetListFilter::TargetList + SGIR::CPUTargetList.val(mask.bits() as u32)
+            }
+        };
+
+        self.shared_registers.lock(|regs| {
+            regs.block
+                .SGIR
+                .write(filter_and_list + SGIR::SGIINTID.val(sgi_num as u32));
+        });
+
+        Ok(())
+    }
+
+    /// Print the distributor state of every implemented IRQ that is enabled, pending or active.
+    ///
+    /// Private IRQs are reported as seen by the executing core.
+    pub fn dump_state(&self) {
+        self.shared_registers.lock(|regs| {
+            let num_irqs = regs.num_irqs();
+
+            info!(
+                "      Distributor: {}, {} IRQs implemented",
+                if regs.block.CTLR.is_set(CTLR::Enable) {
+                    "enabled"
+                } else {
+                    "disabled"
+                },
+                num_irqs
+            );
+            info!("      IRQ  Enabled Pending Active Priority Targets Trigger");
+
+            for irq_num in 0..num_irqs {
+                let bit_index = irq_num >> 5;
+                let byte_index = irq_num >> 2;
+                let config_index = irq_num >> 4;
+
+                let (enabled, pending, active, priority, targets, config) = match irq_num {
+                    // Private.
+                    0..=31 => (
+                        self.banked_registers.block.ISENABLER.get(),
+                        self.banked_registers.block.ISPENDR.get(),
+                        self.banked_registers.block.ISACTIVER.get(),
+                        self.banked_registers.IPRIORITYR.reg(byte_index).get(),
+                        self.banked_registers.ITARGETSR.reg(byte_index).get(),
+                        self.banked_registers.ICFGR.reg(config_index).get(),
+                    ),
+                    // Shared.
+                    _ => (
+                        regs.ISENABLER.reg(bit_index - PRIVATE_BIT_REG_COUNT).get(),
+                        regs.ISPENDR.reg(bit_index - PRIVATE_BIT_REG_COUNT).get(),
+                        regs.ISACTIVER.reg(bit_index - PRIVATE_BIT_REG_COUNT).get(),
+                        regs.IPRIORITYR
+                            .reg(byte_index - PRIVATE_BYTE_REG_COUNT)
+                            .get(),
+                        regs.ITARGETSR
+                            .reg(byte_index - PRIVATE_BYTE_REG_COUNT)
+                            .get(),
+                        regs.ICFGR
+                            .reg(config_index - PRIVATE_CONFIG_REG_COUNT)
+                            .get(),
+                    ),
+                };
+
+                let bit = |reg: u32| reg & (1 << (irq_num % 32)) != 0;
+                let byte = |reg: u32| (reg >> ((irq_num % 4) * 8)) & 0xff;
+                let trigger = if (config >> ((irq_num % 16) * 2)) & ICFGR_EDGE_TRIGGERED != 0 {
+                    "edge"
+                } else {
+                    "level"
+                };
+
+                if !(bit(enabled) || bit(pending) || bit(active)) {
+                    continue;
+                }
+
+                info!(
+                    "      {: >3}  {: <7} {: <7} {: <6} {:#04x}     {:#04x}    {}",
+                    irq_num,
+                    bit(enabled),
+                    bit(pending),
+                    bit(active),
+                    byte(priority),
+                    byte(targets),
+                    trigger
+                );
+            }
+        });
+    }
+}
@@ -0,0 +1,402 @@
+//! GICv2 Driver - ARM Generic Interrupt Controller v2.
+//!
+//! The following is a collection of excerpts with useful information from
+//!   - `Programmer's Guide for ARMv8-A`
+//!   - `ARM Generic Interrupt Controller Architecture Specification`
+//!
+//! # Programmer's Guide - 10.6.1 Configuration
+//!
+//! The GIC is accessed as a memory-mapped peripheral.
+//!
+//! All cores can access the common Distributor, but the CPU interface is banked, that is, each core
+//! uses the same address to access its own private CPU interface.
+//!
+//! It is not possible for a core to access the CPU interface of another core.
+//!
+//! # Architecture Specification - 10.6.2 Initialization
+//!
+//! Both the Distributor and the CPU interfaces are disabled at reset. The GIC must be initialized
+//! after reset before it can deliver interrupts to the core.
+//!
+//! In the Distributor, software must configure the priority, target, security and enable individual
+//! interrupts. The Distributor must subsequently be enabled through its control register
+//! (GICD_CTLR). For each CPU interface, software must program the priority mask and preemption
+//! settings.
+//!
+//! Each CPU interface block itself must be enabled through its control register (GICD_CTLR). This
+//! prepares the GIC to deliver interrupts to the core.
+//!
+//! Before interrupts are expected in the core, software prepares the core to take interrupts by
+//! setting a valid interrupt vector in the vector table, and clearing interrupt mask bits in
+//! PSTATE, and setting the routing controls.
+//!
+//! The entire interrupt mechanism in the system can be disabled by disabling the Distributor.
+//! Interrupt delivery to an individual core can be disabled by disabling its CPU interface.
+//! Individual interrupts can also be disabled (or enabled) in the distributor.
+//!
+//! For an interrupt to reach the core, the individual interrupt, Distributor and CPU interface must
+//! all be enabled. The interrupt also needs to be of sufficient priority, that is, higher than the
+//! core's priority mask.
+//!
+//! # Architecture Specification - 1.4.2 Interrupt types
+//!
+//! - Peripheral interrupt
+//!     - Private Peripheral Interrupt (PPI)
+//!         - This is a peripheral interrupt that is specific to a single processor.
+//!     - Shared Peripheral Interrupt (SPI)
+//!         - This is a peripheral interrupt that the Distributor can route to any of a specified
+//!           combination of processors.
+//!
+//! - Software-generated interrupt (SGI)
+//!     - This is an interrupt generated by software writing to a GICD_SGIR register in the GIC. The
+//!       system uses SGIs for interprocessor communication.
+//!     - An SGI has edge-triggered properties. The software triggering of the interrupt is
+//!       equivalent to the edge transition of the interrupt request signal.
+//!     - When an SGI occurs in a multiprocessor implementation, the CPUID field in the Interrupt
+//!       Acknowledge Register, GICC_IAR, or the Aliased Interrupt Acknowledge Register, GICC_AIAR,
+//!       identifies the processor that requested the interrupt.
+//!
+//! # Priorities
+//!
+//! Each IRQ has an 8-bit priority, lower values being more urgent. The GIC-400 of the BCM2711 only
+//! implements the upper four bits for the non-secure world the kernel runs in, so there are 16
+//! effective levels, `0x00`, `0x10`, ..., `0xF0`. The lower bits read as zero.
+//!
+//! The CPU interface only signals an IRQ whose priority is higher than both the priority mask
+//! (GICC_PMR) and the running priority, which is the priority of the IRQ being handled. The binary
+//! point (GICC_BPR) is programmed so that all implemented bits count for preemption. As handlers
+//! run with IRQs unmasked, any IRQ of a more urgent level preempts a running handler, while IRQs of
+//! the same or a less urgent level wait for it to complete.
+//!
+//! IRQs whose handler is registered without a priority get [`DEFAULT_PRIORITY`], in the middle of
+//! the range.
+//!
+//! # Architecture Specification - 2.2.1 Interrupt IDs
+//!
+//! Interrupts from sources are identified using ID numbers. Each CPU interface can see up to 1020
+//! interrupts. The banking of SPIs and PPIs increases the total number of interrupts supported by
+//! the Distributor.
+//!
+//! The GIC assigns interrupt ID numbers ID0-ID1019 as follows:
+//!   - Interrupt numbers 32..1019 are used for SPIs.
+//!   - Interrupt numbers 0..31 are used for interrupts that are private to a CPU interface. These
+//!     interrupts are banked in the Distributor.
+//!       - A banked interrupt is one where the Distributor can have multiple interrupts with the
+//!         same ID. A banked interrupt is identified uniquely by its ID number and its associated
+//!         CPU interface number. Of the banked interrupt IDs:
+//!           - 00..15 SGIs
+//!           - 16..31 PPIs
+
+mod gicc;
+mod gicd;
+
+use gicc::GICC;
+use gicd::GICD;
+
+use super::common::BoundedUsize;
+use crate::{
+    bsp::cpu::BOOT_CORE_ID,
+    cpu::smp::{core_id, CoreMask},
+    driver::interface::DeviceDriver,
+    exception::{
+        self,
+        asynchronous::{IRQContext, IRQHandlerDescriptor, IRQManager},
+    },
+    memory::{Address, Virtual},
+    synchronization,
+    synchronization::InitStateLock,
+};
+use synchronization::ReadWriteExclusive;
+
+/// Used for the associated type of trait [`exception::asynchronous::interface::IRQManager`].
+pub type IRQNumber = BoundedUsize<{ GICv2::MAX_IRQ_NUMBER }>;
+
+/// Priority of IRQs that were not given one, in the middle of the 16 effective levels.
+pub const DEFAULT_PRIORITY: u8 = 0x80;
+
+type HandlerTable = [Option<IRQHandlerDescriptor<IRQNumber>>; IRQNumber::MAX_INCLUSIVE + 1];
+
+/// The cores an SGI is sent to.
+#[derive(Copy, Clone, Debug)]
+pub enum SGITarget {
+    /// Every core except the sending one.
+    AllButSelf,
+    /// A single core, which may be the sending one.
+    Only(usize),
+    /// The cores in the mask.
+    List(CoreMask),
+}
+
+/// Representation of the GIC.
+pub struct GICv2 {
+    /// The Distributor.
+    gicd: GICD,
+
+    /// The CPU Interface.
+    gicc: GICC,
+
+    /// Stores registered IRQ handlers. Writable only during kernel init. RO afterwards.
+    handler_table: InitStateLock<HandlerTable>,
+}
+
+impl GICv2 {
+    const MAX_IRQ_NUMBER: usize = 300; // Normally 1019, but keep it lower to save some space.
+
+    pub const COMPATIBLE: &'static str = "GICv2 (ARM Generic Interrupt Controller v2)";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(
+        gicd_mmio_start_addr: Address<Virtual>,
+        gicc_mmio_start_addr: Address<Virtual>,
+    ) -> Self {
+        Self {
+            gicd: GICD::new(gicd_mmio_start_addr),
+            gicc: GICC::new(gicc_mmio_start_addr),
+            handler_table: InitStateLock::new([None; IRQNumber::MAX_INCLUSIVE + 1]),
+        }
+    }
+
+    /// Highest SGI number. IRQs 0 to 15 are SGIs, whose handlers are registered like those of
+    /// other IRQs.
+    pub const MAX_SGI_NUMBER: usize = 15;
+
+    /// Per-core part of the GIC initialization.
+    ///
+    /// Enables the banked SGIs and PPIs that were enabled so far and sets up the executing core's
+    /// CPU interface to accept IRQs of all priorities, preempting by all priority bits. The boot
+    /// core calls this from `init()`. Secondary cores must call it during their own bring-up, after
+    /// the boot core has initialized the distributor.
+    ///
+    /// The kernel does not start the secondary cores yet. The stock armstub parks them in its spin
+    /// table, which lies in the unmapped first page, PSCI is not available, and there is no
+    /// secondary entry path that drops to EL1, sets up a stack and enables the MMU. Until there is,
+    /// the per-core state can only be checked on the boot core, see `check_this_core()`.
+    pub fn init_this_core(&self) {
+        self.gicd.init_this_core();
+
+        self.gicc.priority_accept_all();
+        self.gicc.binary_point_min();
+        self.gicc.enable();
+    }
+
+    /// Check that the executing core's banked SGIs and PPIs are set up like `init_this_core()`
+    /// does it, and that its CPU interface is enabled.
+    pub fn check_this_core(&self) -> Result<(), &'static str> {
+        if !self.gicc.is_enabled() {
+            return Err("CPU interface is not enabled on this core");
+        }
+
+        self.gicd.check_this_core()
+    }
+
+    /// Mark a shared IRQ pending, as if its peripheral had raised it. Used to test the routing.
+    pub fn set_pending(&self, irq_number: &IRQNumber) -> Result<(), &'static str> {
+        self.gicd.set_pending(irq_number)
+    }
+
+    /// Return true if a shared IRQ is pending, i.e. no core has acknowledged it yet.
+    pub fn is_pending(&self, irq_number: &IRQNumber) -> Result<bool, &'static str> {
+        self.gicd.is_pending(irq_number)
+    }
+
+    /// Send a software-generated interrupt, e.g. to signal other cores.
+    ///
+    /// Each target core runs the handler registered for `sgi_num` once it takes the IRQ.
+    pub fn send_sgi(&self, sgi_num: &IRQNumber, target: SGITarget) -> Result<(), &'static str> {
+        self.gicd.send_sgi(sgi_num, target)
+    }
+}
+
+impl DeviceDriver for GICv2 {
+    type IRQNumberType = IRQNumber;
+
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        if BOOT_CORE_ID == core_id() {
+            self.gicd.boot_core_init();
+        }
+
+        self.init_this_core();
+
+        Ok(())
+    }
+}
+
+impl IRQManager for GICv2 {
+    type IRQNumberType = IRQNumber;
+
+    fn register_handler(
+        &self,
+        irq_handler_descriptor: IRQHandlerDescriptor<Self::IRQNumberType>,
+    ) -> Result<(), &'static str> {
+        self.handler_table.write(|table| {
+            let irq_number = irq_handler_descriptor.number.get();
+
+            if table[irq_number].is_some() {
+                return Err("IRQ handler already registered");
+            }
+
+            if let Some(mask) = irq_handler_descriptor.affinity {
+                self.gicd
+                    .set_affinity(&irq_handler_descriptor.number, mask)?;
+            }
+
+            let priority = irq_handler_descriptor.priority.unwrap_or(DEFAULT_PRIORITY);
+            self.gicd
+                .set_priority(&irq_handler_descriptor.number, priority)?;
+
+            table[irq_number] = Some(irq_handler_descriptor);
+
+            Ok(())
+        })
+    }
+
+    fn enable(&self, irq_number: &Self::IRQNumberType) {
+        self.gicd.enable(irq_number);
+    }
+
+    fn set_affinity(
+        &self,
+        irq_number: &Self::IRQNumberType,
+        mask: CoreMask,
+    ) -> Result<(), &'static str> {
+        self.gicd.set_affinity(irq_number, mask)
+    }
+
+    fn set_priority(
+        &self,
+        irq_number: &Self::IRQNumberType,
+        priority: u8,
+    ) -> Result<(), &'static str> {
+        self.gicd.set_priority(irq_number, priority)
+    }
+
+    fn handle_pending_irqs<'irq_context>(&'irq_context self, ic: &IRQContext<'irq_context>) {
+        // Extract the highest priority pending IRQ number from the Interrupt Acknowledge Register
+        // (IAR).
+        let (irq_number, source_core) = self.gicc.pending_irq_number(ic);
+
+        // Guard against spurious interrupts.
+        if irq_number > GICv2::MAX_IRQ_NUMBER {
+            return;
+        }
+
+        // Call the IRQ handler. Panic if there is none.
+        self.handler_table.read(|table| {
+            match table[irq_number] {
+                None => panic!("No handler registered for IRQ {}", irq_number),
+                Some(descriptor) => {
+                    // Acknowledging raised the running priority to the one of this IRQ, so only
+                    // more urgent IRQs can preempt the handler.
+                    exception::local_irq_unmask();
+                    let result = descriptor.handler.handle();
+                    exception::local_irq_mask();
+
+                    // Panics on failure.
+                    result.expect("Error handling IRQ");
+                }
+            }
+        });
+
+        // Signal completion of handling.
+        self.gicc.mark_comleted(irq_number as u32, source_core, ic);
+    }
+
+    fn dump_state(&self) {
+        use crate::info;
+
+        info!("GICv2 state as seen by core {}:", core_id::<usize>());
+        self.gicd.dump_state();
+        self.gicc.dump_state();
+    }
+
+    fn print_handler(&self) {
+        use crate::info;
+
+        self.handler_table.read(|table| {
+            info!("Software-generated handler:");
+            for (i, opt) in table.iter().take(GICv2::MAX_SGI_NUMBER + 1).enumerate() {
+                if let Some(handler) = opt {
+                    info!("{: >3}. {}", i, handler.name);
+                }
+            }
+
+            info!("Peripheral handler:");
+            for (i, opt) in table.iter().skip(32).enumerate() {
+                if let Some(handler) = opt {
+                    info!("{: >3}. {}", i + 32, handler.name);
+                }
+            }
+        });
+    }
+}
+
+pub(crate) mod selftests {
+    use crate::{
+        bsp, cpu::smp::CoreMask, exception::asynchronous::irq_manager, selftest::SelfTest, time,
+    };
+    use core::time::Duration;
+
+    pub(crate) static TESTS: &[SelfTest] = &[
+        SelfTest {
+            name: "GIC banked IRQs set up on this core",
+            run: test_gic_this_core,
+            destructive: false,
+            needs_irqs: true,
+        },
+        SelfTest {
+            name: "GIC routes the UART IRQ away from the boot core",
+            run: test_gic_route_to_other_core,
+            destructive: true,
+            needs_irqs: true,
+        },
+    ];
+
+    /// Secondary cores are not started yet, so the banked GIC state can only be checked on the boot
+    /// core. The timer tests above cover the timer PPI firing there.
+    fn test_gic_this_core() -> Result<(), &'static str> {
+        match bsp::driver::interrupt_controller() {
+            None => Err("No interrupt controller"),
+            Some(gic) => gic.check_this_core(),
+        }
+    }
+
+    /// Route the console UART IRQ to core 1 and raise it by software. Core 1 is parked by the
+    /// firmware with its CPU interface disabled, so it never takes the IRQ, and core 0 must not
+    /// take it either. Once routed back, core 0 must take it. Core 1 running the handler can only
+    /// be checked once the secondary cores are started, see `GICv2::init_this_core()`.
+    fn test_gic_route_to_other_core() -> Result<(), &'static str> {
+        let gic = match bsp::driver::interrupt_controller() {
+            None => return Err("No interrupt controller"),
+            Some(gic) => gic,
+        };
+        let irq = bsp::driver::CONSOLE_UART_IRQ;
+
+        irq_manager().set_affinity(&irq, CoreMask::from_core(1))?;
+        gic.set_pending(&irq)?;
+        time::spin_for(Duration::from_micros(200))?;
+        let stayed_pending = gic.is_pending(&irq)?;
+
+        // Restore the routing before reporting, so that the console keeps its RX IRQ.
+        irq_manager().set_affinity(&irq, CoreMask::from_core(bsp::cpu::BOOT_CORE_ID as usize))?;
+        if !stayed_pending {
+            return Err("IRQ routed to core 1 was taken by the boot core");
+        }
+
+        let deadline = time::uptime() + Duration::from_millis(1);
+        while gic.is_pending(&irq)? {
+            if time::uptime() > deadline {
+                return Err("IRQ routed back was not taken by the boot core");
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+pub mod bcm2711_mailbox;
+pub mod bcm2711_pm_wdog;
+pub mod bcm2xxx_gpio;
+#[cfg(feature = "mini_uart")]
+pub mod bcm2xxx_mini_uart;
+#[cfg(not(feature = "mini_uart"))]
+pub mod bcm2xxx_pl011;
+pub mod common;
+pub mod gicv2;
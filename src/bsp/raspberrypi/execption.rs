@@ -0,0 +1,12 @@
+use crate::bsp::drivers::gicv2::IRQNumber;
+
+/// Non-secure physical timer PPI.
+pub const ARCH_TIMER_IRQ: IRQNumber = IRQNumber::new(30);
+
+pub const PL011_UART_IRQ: IRQNumber = IRQNumber::new(153);
+
+/// GPIO events of all banks, VideoCore IRQ 52.
+pub const GPIO_IRQ: IRQNumber = IRQNumber::new(148);
+
+/// AUX peripherals, VideoCore IRQ 29. Shared by the mini UART and the two SPI masters.
+pub const MINI_UART_IRQ: IRQNumber = IRQNumber::new(125);
@@ -0,0 +1,394 @@
+//! BSP Memory Management Unit.
+
+#[cfg(feature = "gdbstub")]
+use crate::memory::mmu::kernel_map_alias;
+use crate::memory::mmu::translation_table::TranslationTable;
+use crate::memory::mmu::{
+    kernel_map_at, AssociatedTranslationTable, MemoryRegion, MemorySize, PageAddress,
+};
+use crate::memory::mmu::{AccessPermissions, AddressSpace, AttributeFields, MemAttributes};
+use crate::memory::{Address, Physical, Virtual};
+use crate::synchronization::InitStateLock;
+use crate::synchronization::ReadWriteExclusive;
+
+/// The translation granule chosen by this BSP. This will be used everywhere else in the kernel to
+/// derive respective data structures and their sizes.
+#[cfg(not(feature = "granule_4k"))]
+pub type MSKernel = MemorySize<{ 64 * 1024 }>;
+
+/// The translation granule chosen by this BSP. This will be used everywhere else in the kernel to
+/// derive respective data structures and their sizes.
+#[cfg(feature = "granule_4k")]
+pub type MSKernel = MemorySize<{ 4 * 1024 }>;
+
+/// Size of the kernel's virtual address space. This is the only place to change it.
+///
+/// It must be a power of two and a multiple of the memory covered by a level 3 table, which is
+/// checked at compile time. With the 64 KiB granule, every 512 MiB cost a 64 KiB level 3 table in
+/// `.bss`, so a 4 GiB space adds 512 KiB to the kernel. The 4 KiB granule limits the space to
+/// 1 GiB. The whole kernel binary and all of its mappings must lie below this size.
+pub const KERNEL_VIRT_ADDR_SPACE_SIZE: usize = 1024 * 1024 * 1024;
+
+/// The kernel's virtual address space defined by this BSP.
+pub type KernelVirtAddrSpace = AddressSpace<KERNEL_VIRT_ADDR_SPACE_SIZE>;
+
+type KernelTranslationTable = <KernelVirtAddrSpace as AssociatedTranslationTable>::Table;
+
+/// The kernel translation tables.
+///
+/// It is mandatory that InitStateLock is transparent.
+///
+/// That is, `size_of(InitStateLock<KernelTranslationTable>) == size_of(KernelTranslationTable)`.
+pub static KERNEL_TRANSLATION_TABLES: InitStateLock<KernelTranslationTable> =
+    InitStateLock::new(KernelTranslationTable::new());
+
+/// The heap pages.
+pub fn virt_heap_region() -> MemoryRegion<Virtual> {
+    super::linker_layout().heap_region()
+}
+
+/// The heap guard pages. They are intentionally left unmapped.
+pub fn virt_heap_guard_region() -> MemoryRegion<Virtual> {
+    super::linker_layout().heap_guard_region()
+}
+
+/// The heap reserve pages.
+pub fn virt_heap_reserve_region() -> MemoryRegion<Virtual> {
+    super::linker_layout().heap_reserve_region()
+}
+
+/// The heap reserve guard pages. They are intentionally left unmapped.
+pub fn virt_heap_reserve_guard_region() -> MemoryRegion<Virtual> {
+    super::linker_layout().heap_reserve_guard_region()
+}
+
+/// The guard pages below the boot core stack. They are intentionally left unmapped.
+pub fn virt_boot_core_stack_guard_region() -> MemoryRegion<Virtual> {
+    super::linker_layout().boot_core_stack_guard_region()
+}
+
+/// Return a description of the fault if the address lies in one of the guard regions.
+pub fn guard_region_fault(addr: Address<Virtual>) -> Option<&'static str> {
+    if virt_heap_guard_region().contains(addr) {
+        return Some("Heap overflow: access to the guard page after the kernel heap");
+    }
+
+    if virt_heap_reserve_guard_region().contains(addr) {
+        return Some("Heap overflow: access to the guard page after the kernel heap reserve");
+    }
+
+    if virt_boot_core_stack_guard_region().contains(addr) {
+        return Some("Stack overflow: access to the guard page below the boot core stack");
+    }
+
+    None
+}
+
+/// The code pages of the kernel binary.
+pub fn virt_code_region() -> MemoryRegion<Virtual> {
+    super::linker_layout().code_region()
+}
+
+/// The data pages of the kernel binary.
+fn virt_data_region() -> MemoryRegion<Virtual> {
+    super::linker_layout().data_region()
+}
+
+/// The data pages that become read-only at the end of kernel init.
+pub fn virt_ro_after_init_region() -> MemoryRegion<Virtual> {
+    super::linker_layout().ro_after_init_region()
+}
+
+/// The boot core stack pages.
+fn virt_boot_core_stack_region() -> MemoryRegion<Virtual> {
+    super::linker_layout().boot_core_stack_region()
+}
+
+/// Try to get the attributes of a kernel page.
+///
+/// Will only succeed if there exists a valid mapping for the input page.
+pub fn kernel_page_attributes(
+    virt_page_addr: PageAddress<Virtual>,
+) -> Result<AttributeFields, &'static str> {
+    KERNEL_TRANSLATION_TABLES.read(|tables| tables.try_page_attributes(virt_page_addr))
+}
+
+/// Try to get the physical address a kernel virtual address is mapped to.
+pub fn try_kernel_virt_to_phys(
+    virt_addr: Address<Virtual>,
+) -> Result<Address<Physical>, &'static str> {
+    KERNEL_TRANSLATION_TABLES.read(|tables| tables.try_virt_to_phys(virt_addr))
+}
+
+// The binary is still identity mapped, so use this trivial conversion function for mapping below.
+
+fn kernel_virt_to_phys_region(virt_region: MemoryRegion<Virtual>) -> MemoryRegion<Physical> {
+    MemoryRegion::new(
+        PageAddress::from(virt_region.start_page.address().as_usize()),
+        PageAddress::from(virt_region.end_page_exclusive.address().as_usize()),
+    )
+}
+
+/// The MMIO remap pages.
+pub fn virt_mmio_remap_region() -> MemoryRegion<Virtual> {
+    super::linker_layout().mmio_remap_region()
+}
+
+/// A region of the kernel binary and the attributes it is mapped with.
+struct KernelRegion {
+    name: &'static str,
+    virt_region: MemoryRegion<Virtual>,
+    attr: AttributeFields,
+}
+
+fn kernel_binary_regions() -> [KernelRegion; 5] {
+    [
+        KernelRegion {
+            name: "Kernel boot-core stack",
+            virt_region: virt_boot_core_stack_region(),
+            attr: AttributeFields {
+                mem_attributes: MemAttributes::CacheableDRAM,
+                acc_perms: AccessPermissions::ReadWrite,
+                executable: false,
+            },
+        },
+        KernelRegion {
+            name: "Kernel heap",
+            virt_region: virt_heap_region(),
+            attr: AttributeFields {
+                mem_attributes: MemAttributes::CacheableDRAM,
+                acc_perms: AccessPermissions::ReadWrite,
+                executable: false,
+            },
+        },
+        KernelRegion {
+            name: "Kernel heap reserve",
+            virt_region: virt_heap_reserve_region(),
+            attr: AttributeFields {
+                mem_attributes: MemAttributes::CacheableDRAM,
+                acc_perms: AccessPermissions::ReadWrite,
+                executable: false,
+            },
+        },
+        KernelRegion {
+            name: "Kernel code and RO data",
+            virt_region: virt_code_region(),
+            attr: AttributeFields {
+                mem_attributes: MemAttributes::CacheableDRAM,
+                acc_perms: AccessPermissions::ReadOnly,
+                executable: true,
+            },
+        },
+        KernelRegion {
+            name: "Kernel data and bss",
+            virt_region: virt_data_region(),
+            attr: AttributeFields {
+                mem_attributes: MemAttributes::CacheableDRAM,
+                acc_perms: AccessPermissions::ReadWrite,
+                executable: false,
+            },
+        },
+    ]
+}
+
+/// Map the kernel binary.
+///
+/// # Safety
+///
+/// - Any miscalculation or attribute error will likely be fatal. Needs careful manual checking.
+pub unsafe fn kernel_map_binary() -> Result<(), &'static str> {
+    super::linker_layout().validate()?;
+
+    for region in kernel_binary_regions() {
+        kernel_map_at(
+            region.name,
+            &region.virt_region,
+            &kernel_virt_to_phys_region(region.virt_region),
+            &region.attr,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Map the kernel code a second time, writable and not executable, so that it can be patched at
+/// runtime. Returns the virtual address of the alias of the first code page.
+///
+/// # Safety
+///
+/// - The alias circumvents the write protection of the code. Only the debugger may use it.
+#[cfg(feature = "gdbstub")]
+pub unsafe fn kernel_map_code_alias() -> Result<Address<Virtual>, &'static str> {
+    kernel_map_alias(
+        "Kernel code (debugger alias)",
+        &kernel_virt_to_phys_region(virt_code_region()),
+        &AttributeFields {
+            mem_attributes: MemAttributes::CacheableDRAM,
+            acc_perms: AccessPermissions::ReadWrite,
+            executable: false,
+        },
+    )
+}
+
+/// Check that the first page of every region of the kernel binary is mapped with the attributes
+/// it was intended to get, and that the guard pages are not mapped.
+///
+/// # Panics
+///
+/// - If a region is not mapped, or mapped with other attributes.
+/// - If a guard page is mapped.
+pub fn verify_kernel_mappings() {
+    for (name, guard) in [
+        ("Boot core stack guard", virt_boot_core_stack_guard_region()),
+        ("Heap guard", virt_heap_guard_region()),
+        ("Heap reserve guard", virt_heap_reserve_guard_region()),
+    ] {
+        if kernel_page_attributes(guard.start_page).is_ok() {
+            panic!("{} page is mapped", name);
+        }
+    }
+
+    for region in kernel_binary_regions() {
+        match kernel_page_attributes(region.virt_region.start_page) {
+            Ok(attr) if attr == region.attr => (),
+            Ok(attr) => panic!(
+                "{} is mapped with {:?}, expected {:?}",
+                region.name, attr, region.attr
+            ),
+            Err(e) => panic!("{} is not mapped: {}", region.name, e),
+        }
+    }
+}
+
+pub(crate) mod selftests {
+    use crate::{
+        bsp,
+        memory::{
+            self,
+            mmu::{
+                self, AccessPermissions, AttributeFields, MemAttributes, MemoryRegion, PageAddress,
+            },
+        },
+        selftest::SelfTest,
+    };
+
+    pub(crate) static TESTS: &[SelfTest] = &[
+        SelfTest {
+            name: "heap is mapped",
+            run: test_heap_mapped,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "heap guard faults safely",
+            run: test_heap_guard_faults,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "boot stack guard faults safely",
+            run: test_boot_stack_guard_faults,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "MMIO remap region is protected",
+            run: test_mmio_remap_guard,
+            destructive: false,
+            needs_irqs: false,
+        },
+    ];
+
+    fn test_heap_mapped() -> Result<(), &'static str> {
+        let heap = bsp::memory::mmu::virt_heap_region();
+
+        if !mmu::is_mapped(heap.start_page.address()) {
+            return Err("Heap start reported unmapped");
+        }
+
+        Ok(())
+    }
+
+    fn test_heap_guard_faults() -> Result<(), &'static str> {
+        let guard = bsp::memory::mmu::virt_heap_guard_region()
+            .start_page
+            .address();
+
+        if mmu::is_mapped(guard) {
+            return Err("Heap guard page reported mapped");
+        }
+
+        if memory::safe_read32(guard).is_ok() {
+            return Err("Read from heap guard page did not fault");
+        }
+
+        Ok(())
+    }
+
+    fn test_boot_stack_guard_faults() -> Result<(), &'static str> {
+        // The page directly below the stack is the one an overflow runs into.
+        let guard = bsp::memory::mmu::virt_boot_core_stack_guard_region()
+            .end_inclusive_page_addr()
+            .address();
+
+        if mmu::is_mapped(guard) {
+            return Err("Boot stack guard page reported mapped");
+        }
+
+        if memory::safe_read32(guard).is_ok() {
+            return Err("Read from boot stack guard page did not fault");
+        }
+
+        Ok(())
+    }
+
+    fn test_mmio_remap_guard() -> Result<(), &'static str> {
+        let mmio = bsp::memory::mmu::virt_mmio_remap_region();
+        let before = mmio
+            .start_page
+            .checked_offset(-1)
+            .ok_or("No page before MMIO")?;
+        let after = mmio
+            .end_page_exclusive
+            .checked_offset(1)
+            .ok_or("No page after MMIO")?;
+        let second = mmio
+            .start_page
+            .checked_offset(1)
+            .ok_or("MMIO region too small")?;
+
+        let enclosing = MemoryRegion::new(before, after);
+        let partial = MemoryRegion::new(before, second);
+        let adjacent = MemoryRegion::new(before, mmio.start_page);
+
+        if !mmio.overlaps(&enclosing) || !mmio.overlaps(&partial) {
+            return Err("Overlap not detected");
+        }
+
+        if mmio.overlaps(&adjacent) {
+            return Err("Adjacent region reported as overlapping");
+        }
+
+        let attr = AttributeFields {
+            mem_attributes: MemAttributes::Device,
+            acc_perms: AccessPermissions::ReadWrite,
+            executable: false,
+        };
+
+        // Only the rejected cases are tried. The tables can not be changed after kernel init, so
+        // anything that gets past the check would panic.
+        for virt_region in [enclosing, partial] {
+            let phys_region = MemoryRegion::new(
+                PageAddress::from(virt_region.start_page.address().as_usize()),
+                PageAddress::from(virt_region.end_page_exclusive.address().as_usize()),
+            );
+
+            if unsafe { mmu::kernel_map_at("selftest", &virt_region, &phys_region, &attr) }.is_ok()
+            {
+                return Err("Mapping over the MMIO remap region was accepted");
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,417 @@
+//! BSP Memory Management.
+//!
+//! The physical memory layout.
+//!
+//! The Raspberry's firmware copies the kernel binary to 0x8_0000. The preceding region will be used
+//! as the boot core's stack, except for its first page. That one stays unmapped, so that a stack
+//! overflow faults.
+//!
+//! +---------------------------------------+
+//! |                                       | boot_core_stack_guard_start @ 0x0
+//! | Unmapped guard page                   |
+//! +---------------------------------------+
+//! |                                       | boot_core_stack_start @ PAGE_SIZE
+//! |                                       |                                ^
+//! | Boot-core Stack                       |                                | stack
+//! |                                       |                                | growth
+//! |                                       |                                | direction
+//! +---------------------------------------+
+//! |                                       | code_start @ 0x8_0000 == boot_core_stack_end_exclusive
+//! | .text                                 |
+//! | .rodata                               |
+//! | .got                                  |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       | data_start == code_end_exclusive
+//! | .data.ro_after_init                   |
+//! | .data                                 |
+//! | .bss                                  |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       | heap_start == data_end_exclusive
+//! | .heap                                 |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       | heap_end_exclusive
+//! |                                       |
+//!
+//!
+//! The virtual memory layout is as follows:
+//!
+//! +---------------------------------------+
+//! |                                       | boot_core_stack_guard_start @ 0x0
+//! | Unmapped guard page                   |
+//! +---------------------------------------+
+//! |                                       | boot_core_stack_start @ PAGE_SIZE
+//! |                                       |                                ^
+//! | Boot-core Stack                       |                                | stack
+//! |                                       |                                | growth
+//! |                                       |                                | direction
+//! +---------------------------------------+
+//! |                                       | code_start @ 0x8_0000 == boot_core_stack_end_exclusive
+//! | .text                                 |
+//! | .rodata                               |
+//! | .got                                  |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       | data_start == code_end_exclusive
+//! | .data.ro_after_init                   |
+//! | .data                                 |
+//! | .bss                                  |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       |  heap_start == data_end_exclusive
+//! | .heap                                 |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       |  heap_guard_start == heap_end_exclusive
+//! | Unmapped guard page                   |
+//! +---------------------------------------+
+//! |                                       |  mmio_remap_start == heap_guard_end_exclusive
+//! | VA region for MMIO remapping          |
+//! |                                       |
+//! +---------------------------------------+
+//! |                                       |  mmio_remap_end_exclusive
+//! |                                       |                  
+
+pub mod mmu;
+
+use crate::memory::{
+    mmu::{MemoryRegion, PageAddress},
+    Address, Physical, Virtual,
+};
+use core::cell::UnsafeCell;
+
+// Symbols from the linker script.
+extern "Rust" {
+    static __code_start: UnsafeCell<()>;
+    static __code_end_exclusive: UnsafeCell<()>;
+
+    static __data_start: UnsafeCell<()>;
+    static __data_end_exclusive: UnsafeCell<()>;
+
+    static __ro_after_init_start: UnsafeCell<()>;
+    static __ro_after_init_end_exclusive: UnsafeCell<()>;
+
+    static __bss_start: UnsafeCell<()>;
+    static __bss_end_exclusive: UnsafeCell<()>;
+
+    static __heap_start: UnsafeCell<()>;
+    static __heap_end_exclusive: UnsafeCell<()>;
+
+    static __heap_guard_start: UnsafeCell<()>;
+    static __heap_guard_end_exclusive: UnsafeCell<()>;
+
+    static __heap_reserve_start: UnsafeCell<()>;
+    static __heap_reserve_end_exclusive: UnsafeCell<()>;
+
+    static __heap_reserve_guard_start: UnsafeCell<()>;
+    static __heap_reserve_guard_end_exclusive: UnsafeCell<()>;
+
+    static __mmio_remap_start: UnsafeCell<()>;
+    static __mmio_remap_end_exclusive: UnsafeCell<()>;
+
+    static __boot_core_stack_guard_start: UnsafeCell<()>;
+    static __boot_core_stack_guard_end_exclusive: UnsafeCell<()>;
+
+    static __boot_core_stack_start: UnsafeCell<()>;
+    static __boot_core_stack_paint_start: UnsafeCell<()>;
+    static __boot_core_stack_end_exclusive: UnsafeCell<()>;
+}
+
+/// The board's physical memory map.
+#[rustfmt::skip]
+pub mod map {
+    #[cfg(feature = "kernelloader")]
+    pub const BOARD_DEFAULT_LOAD_ADDRESS: usize =        0x8_0000;
+
+    /// Entry of the kernelloader after it relocated itself. It stays resident while the kernel
+    /// runs, since nothing of the kernel lives that high in physical memory.
+    pub const LOADER_ENTRY_ADDRESS:       usize =    0x208_0000;
+
+    use super::*;
+
+    /// Physical devices.
+    pub mod mmio {
+
+
+        use super::*;
+
+        /// Start of the peripherals. Everything below is DRAM.
+        pub const START:            Address<Physical> = Address::new(0xFE00_0000);
+
+        pub const MAILBOX_START:    Address<Physical> = Address::new(0xFE00_B880);
+        pub const MAILBOX_SIZE:     usize             =              0x3C;
+
+        pub const PM_START:         Address<Physical> = Address::new(0xFE10_0000);
+        pub const PM_SIZE:          usize             =              0x28;
+
+        pub const GPIO_START:       Address<Physical> = Address::new(0xFE20_0000);
+        pub const GPIO_SIZE:        usize             =              0xF4;
+
+        pub const PL011_UART_START: Address<Physical> = Address::new(0xFE20_1000);
+        pub const PL011_UART_SIZE:  usize             =              0x48;
+
+        pub const MINI_UART_START:  Address<Physical> = Address::new(0xFE21_5000);
+        pub const MINI_UART_SIZE:   usize             =              0x6C;
+
+        pub const GICD_START:       Address<Physical> = Address::new(0xFF84_1000);
+        pub const GICD_SIZE:        usize             =              0x1000;
+
+        pub const GICC_START:       Address<Physical> = Address::new(0xFF84_2000);
+        pub const GICC_SIZE:        usize             =              0x1C;
+
+        pub const END:              Address<Physical> = Address::new(0xFF85_0000);
+    }
+
+    pub const END: Address<Physical> = mmio::END;
+}
+
+// The address on which the Raspberry firmware loads every binary by default.
+#[cfg(feature = "kernelloader")]
+#[inline(always)]
+pub fn board_default_load_addr() -> *const u64 {
+    map::BOARD_DEFAULT_LOAD_ADDRESS as _
+}
+
+/// Check that a binary of `size` bytes loaded at the default load address stays in DRAM and does
+/// not overwrite the resident kernelloader.
+///
+/// The loader's boot core stack sits right below its code, so the lowest address of that stack is
+/// the real upper bound, not the loader's entry address.
+#[cfg(feature = "kernelloader")]
+pub fn load_range_valid(size: usize) -> bool {
+    let loader_start = unsafe { __boot_core_stack_start.get() as usize };
+
+    match map::BOARD_DEFAULT_LOAD_ADDRESS.checked_add(size) {
+        None => false,
+        Some(end) => end <= map::mmio::START.as_usize() && end <= loader_start,
+    }
+}
+
+/// The memory layout of the kernel binary as defined by the linker script.
+///
+/// All linker symbols are read in one place, so that the rest of the kernel only deals with typed
+/// regions.
+#[derive(Copy, Clone)]
+pub struct LinkerLayout {
+    boot_core_stack_guard_start: usize,
+    boot_core_stack_guard_end_exclusive: usize,
+    boot_core_stack_start: usize,
+    boot_core_stack_paint_start: usize,
+    boot_core_stack_end_exclusive: usize,
+    code_start: usize,
+    code_end_exclusive: usize,
+    data_start: usize,
+    ro_after_init_start: usize,
+    ro_after_init_end_exclusive: usize,
+    bss_start: usize,
+    bss_end_exclusive: usize,
+    data_end_exclusive: usize,
+    heap_start: usize,
+    heap_end_exclusive: usize,
+    heap_guard_start: usize,
+    heap_guard_end_exclusive: usize,
+    heap_reserve_start: usize,
+    heap_reserve_end_exclusive: usize,
+    heap_reserve_guard_start: usize,
+    heap_reserve_guard_end_exclusive: usize,
+    mmio_remap_start: usize,
+    mmio_remap_end_exclusive: usize,
+}
+
+/// Return the memory layout defined by the linker script.
+///
+/// # Safety
+///
+/// - Values are provided by the linker script and must be trusted as-is.
+#[inline(always)]
+pub fn linker_layout() -> LinkerLayout {
+    unsafe {
+        LinkerLayout {
+            boot_core_stack_guard_start: __boot_core_stack_guard_start.get() as usize,
+            boot_core_stack_guard_end_exclusive: __boot_core_stack_guard_end_exclusive.get()
+                as usize,
+            boot_core_stack_start: __boot_core_stack_start.get() as usize,
+            boot_core_stack_paint_start: __boot_core_stack_paint_start.get() as usize,
+            boot_core_stack_end_exclusive: __boot_core_stack_end_exclusive.get() as usize,
+            code_start: __code_start.get() as usize,
+            code_end_exclusive: __code_end_exclusive.get() as usize,
+            data_start: __data_start.get() as usize,
+            ro_after_init_start: __ro_after_init_start.get() as usize,
+            ro_after_init_end_exclusive: __ro_after_init_end_exclusive.get() as usize,
+            bss_start: __bss_start.get() as usize,
+            bss_end_exclusive: __bss_end_exclusive.get() as usize,
+            data_end_exclusive: __data_end_exclusive.get() as usize,
+            heap_start: __heap_start.get() as usize,
+            heap_end_exclusive: __heap_end_exclusive.get() as usize,
+            heap_guard_start: __heap_guard_start.get() as usize,
+            heap_guard_end_exclusive: __heap_guard_end_exclusive.get() as usize,
+            heap_reserve_start: __heap_reserve_start.get() as usize,
+            heap_reserve_end_exclusive: __heap_reserve_end_exclusive.get() as usize,
+            heap_reserve_guard_start: __heap_reserve_guard_start.get() as usize,
+            heap_reserve_guard_end_exclusive: __heap_reserve_guard_end_exclusive.get() as usize,
+            mmio_remap_start: __mmio_remap_start.get() as usize,
+            mmio_remap_end_exclusive: __mmio_remap_end_exclusive.get() as usize,
+        }
+    }
+}
+
+impl LinkerLayout {
+    /// Check the properties of the layout that the kernel relies on.
+    ///
+    /// The sections must appear in the order stack guard, stack, code, data, heap, heap guard, heap
+    /// reserve, heap reserve guard, MMIO remap, must not overlap, must start and end on page
+    /// boundaries, and the bss and the read-only after init pages must lie within the data segment.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let boundaries = [
+            self.boot_core_stack_guard_start,
+            self.boot_core_stack_guard_end_exclusive,
+            self.boot_core_stack_start,
+            self.boot_core_stack_end_exclusive,
+            self.code_start,
+            self.code_end_exclusive,
+            self.data_start,
+            self.data_end_exclusive,
+            self.heap_start,
+            self.heap_end_exclusive,
+            self.heap_guard_start,
+            self.heap_guard_end_exclusive,
+            self.heap_reserve_start,
+            self.heap_reserve_end_exclusive,
+            self.heap_reserve_guard_start,
+            self.heap_reserve_guard_end_exclusive,
+            self.mmio_remap_start,
+            self.mmio_remap_end_exclusive,
+        ];
+
+        if !boundaries.is_sorted() {
+            return Err("Linker sections are out of order or overlap");
+        }
+
+        if !boundaries
+            .iter()
+            .all(|addr| crate::is_aligned(*addr, mmu::MSKernel::SIZE))
+        {
+            return Err("Linker section boundary is not page aligned");
+        }
+
+        if !(self.data_start <= self.bss_start
+            && self.bss_start <= self.bss_end_exclusive
+            && self.bss_end_exclusive <= self.data_end_exclusive)
+        {
+            return Err("BSS is not contained in the data segment");
+        }
+
+        if !(self.data_start <= self.ro_after_init_start
+            && self.ro_after_init_start <= self.ro_after_init_end_exclusive
+            && self.ro_after_init_end_exclusive <= self.data_end_exclusive)
+        {
+            return Err("Read-only after init data is not contained in the data segment");
+        }
+
+        if !crate::is_aligned(self.ro_after_init_start, mmu::MSKernel::SIZE)
+            || !crate::is_aligned(self.ro_after_init_end_exclusive, mmu::MSKernel::SIZE)
+        {
+            return Err("Read-only after init data is not page aligned");
+        }
+
+        if !(self.boot_core_stack_start..self.boot_core_stack_end_exclusive)
+            .contains(&self.boot_core_stack_paint_start)
+        {
+            return Err("Stack paint start is not within the boot core stack");
+        }
+
+        if self.boot_core_stack_guard_start == self.boot_core_stack_guard_end_exclusive
+            || self.boot_core_stack_start == self.boot_core_stack_end_exclusive
+            || self.code_start == self.code_end_exclusive
+            || self.heap_start == self.heap_end_exclusive
+            || self.heap_guard_start == self.heap_guard_end_exclusive
+            || self.heap_reserve_start == self.heap_reserve_end_exclusive
+            || self.heap_reserve_guard_start == self.heap_reserve_guard_end_exclusive
+            || self.mmio_remap_start == self.mmio_remap_end_exclusive
+        {
+            return Err("Linker section is empty");
+        }
+
+        Ok(())
+    }
+
+    /// Build a region from page aligned start and exclusive end addresses.
+    fn region(start: usize, end_exclusive: usize) -> MemoryRegion<Virtual> {
+        MemoryRegion::new(PageAddress::from(start), PageAddress::from(end_exclusive))
+    }
+
+    /// The guard pages below the boot core stack, which are never mapped.
+    pub fn boot_core_stack_guard_region(&self) -> MemoryRegion<Virtual> {
+        Self::region(
+            self.boot_core_stack_guard_start,
+            self.boot_core_stack_guard_end_exclusive,
+        )
+    }
+
+    /// The boot core stack pages.
+    pub fn boot_core_stack_region(&self) -> MemoryRegion<Virtual> {
+        Self::region(
+            self.boot_core_stack_start,
+            self.boot_core_stack_end_exclusive,
+        )
+    }
+
+    /// The part of the boot core stack that is painted at boot, as `(start, end_exclusive)`.
+    pub fn boot_core_stack_paint_range(&self) -> (Address<Virtual>, Address<Virtual>) {
+        (
+            Address::new(self.boot_core_stack_paint_start),
+            Address::new(self.boot_core_stack_end_exclusive),
+        )
+    }
+
+    /// The code pages of the kernel binary.
+    pub fn code_region(&self) -> MemoryRegion<Virtual> {
+        Self::region(self.code_start, self.code_end_exclusive)
+    }
+
+    /// The data pages of the kernel binary, including the bss.
+    pub fn data_region(&self) -> MemoryRegion<Virtual> {
+        Self::region(self.data_start, self.data_end_exclusive)
+    }
+
+    /// The data pages that become read-only at the end of kernel init. Might be empty.
+    pub fn ro_after_init_region(&self) -> MemoryRegion<Virtual> {
+        Self::region(self.ro_after_init_start, self.ro_after_init_end_exclusive)
+    }
+
+    /// The heap pages.
+    pub fn heap_region(&self) -> MemoryRegion<Virtual> {
+        Self::region(self.heap_start, self.heap_end_exclusive)
+    }
+
+    /// The heap guard pages, which are never mapped.
+    pub fn heap_guard_region(&self) -> MemoryRegion<Virtual> {
+        Self::region(self.heap_guard_start, self.heap_guard_end_exclusive)
+    }
+
+    /// The heap reserve pages, which back the heap once it is exhausted.
+    pub fn heap_reserve_region(&self) -> MemoryRegion<Virtual> {
+        Self::region(self.heap_reserve_start, self.heap_reserve_end_exclusive)
+    }
+
+    /// The heap reserve guard pages, which are never mapped.
+    pub fn heap_reserve_guard_region(&self) -> MemoryRegion<Virtual> {
+        Self::region(
+            self.heap_reserve_guard_start,
+            self.heap_reserve_guard_end_exclusive,
+        )
+    }
+
+    /// The MMIO remap pages.
+    pub fn mmio_remap_region(&self) -> MemoryRegion<Virtual> {
+        Self::region(self.mmio_remap_start, self.mmio_remap_end_exclusive)
+    }
+}
+
+/// Exclusive end address of the physical address space.
+#[inline(always)]
+pub fn phys_addr_space_end_exclusive_addr() -> PageAddress<Physical> {
+    PageAddress::from(map::END)
+}
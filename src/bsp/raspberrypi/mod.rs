@@ -0,0 +1,46 @@
+//! Raspberry Pi 4 board support.
+
+use crate::time::UnixTime;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+pub mod clock;
+pub mod cpu;
+pub mod driver;
+pub mod drivers;
+pub mod execption;
+pub mod memory;
+pub mod power;
+pub mod reset;
+pub mod thermal;
+pub mod watchdog;
+
+/// Unix time in seconds the kernel was booted at, as provided by the kernelloader. Written by the
+/// boot code before any Rust code runs. Zero if the kernel was not booted by the kernelloader or
+/// the host did not provide a time.
+#[no_mangle]
+static BOOT_TIMESTAMP: AtomicU64 = AtomicU64::new(0);
+
+/// Return the wall-clock time the kernel was booted at, if it is known.
+pub fn boot_time() -> Option<UnixTime> {
+    match BOOT_TIMESTAMP.load(Ordering::Relaxed) {
+        0 => None,
+        secs => Some(UnixTime::from_secs(secs)),
+    }
+}
+
+pub fn board_name() -> &'static str {
+    "Raspberry Pi 4"
+}
+
+/// Property tag to read the board revision code.
+const TAG_GET_BOARD_REVISION: u32 = 0x0001_0002;
+
+/// Return the board revision code, which encodes the model, memory size and manufacturer.
+pub fn board_revision() -> Result<u32, &'static str> {
+    let mailbox = driver::mailbox().ok_or("Mailbox driver not initialized")?;
+
+    let mut values = [0];
+    mailbox.call(TAG_GET_BOARD_REVISION, &mut values)?;
+
+    Ok(values[0])
+}
@@ -0,0 +1,89 @@
+//! BSP power domains.
+//!
+//! Some peripherals sit in power domains that are off after boot. Their registers only respond once
+//! the domain was powered on through the mailbox property interface.
+
+use crate::cpu;
+
+/// Property tag to read the power state of a device.
+const TAG_GET_POWER_STATE: u32 = 0x0002_0001;
+
+/// Property tag to set the power state of a device.
+const TAG_SET_POWER_STATE: u32 = 0x0002_8001;
+
+/// Power state bit: the device is on.
+const STATE_ON: u32 = 1 << 0;
+
+/// Request bit: the firmware waits for the transition before replying.
+const STATE_WAIT: u32 = 1 << 1;
+
+/// Response bit: the device does not exist.
+const STATE_NO_DEVICE: u32 = 1 << 1;
+
+/// Number of state queries before waiting for a transition is given up.
+const MAX_POLLS: usize = 1000;
+
+/// The devices with a power domain, as identified by the firmware.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum DeviceId {
+    SdCard = 0,
+    Uart0 = 1,
+    Uart1 = 2,
+    UsbHcd = 3,
+    I2c0 = 4,
+    I2c1 = 5,
+    I2c2 = 6,
+    Spi = 7,
+    Ccp2tx = 8,
+}
+
+/// Send a power state tag and return the state from the response.
+fn call(tag: u32, device: DeviceId, state: u32) -> Result<u32, &'static str> {
+    let mailbox = super::driver::mailbox().ok_or("Mailbox driver not initialized")?;
+
+    let mut values = [device as u32, state];
+    mailbox.call(tag, &mut values)?;
+
+    if values[1] & STATE_NO_DEVICE != 0 {
+        return Err("Power device does not exist");
+    }
+
+    Ok(values[1])
+}
+
+/// Return if the power domain of a device is on.
+pub fn device_power(device: DeviceId) -> Result<bool, &'static str> {
+    Ok(call(TAG_GET_POWER_STATE, device, 0)? & STATE_ON != 0)
+}
+
+/// Switch the power domain of a device on or off.
+///
+/// With `wait`, the call only returns once the firmware confirms the transition, so the device's
+/// registers can be used right away.
+pub fn set_device_power(device: DeviceId, on: bool, wait: bool) -> Result<(), &'static str> {
+    let mut state = if on { STATE_ON } else { 0 };
+    if wait {
+        state |= STATE_WAIT;
+    }
+
+    let new_state = call(TAG_SET_POWER_STATE, device, state)?;
+    if !wait {
+        return Ok(());
+    }
+
+    // The firmware should only have replied after the transition, but double check before the
+    // caller touches the device.
+    if (new_state & STATE_ON != 0) == on {
+        return Ok(());
+    }
+
+    for _ in 0..MAX_POLLS {
+        if device_power(device)? == on {
+            return Ok(());
+        }
+        cpu::nop();
+    }
+
+    Err("Timeout waiting for power state transition")
+}
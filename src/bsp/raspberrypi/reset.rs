@@ -0,0 +1,162 @@
+//! BSP reset handling.
+
+use crate::{
+    console,
+    console::interface::Write,
+    cpu, exception, memory,
+    synchronization::{IRQSafeNullLock, Mutex},
+};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Maximum number of shutdown hooks.
+const MAX_SHUTDOWN_HOOKS: usize = 8;
+
+/// No shutdown is in progress.
+const SHUTDOWN_NONE: u8 = 0;
+const SHUTDOWN_REBOOT: u8 = 1;
+const SHUTDOWN_POWER_OFF: u8 = 2;
+/// The shutdown was taken over by the panic handler.
+const SHUTDOWN_RESUMED: u8 = 3;
+
+/// The shutdown that is in progress, if any. Set before the hooks run.
+static SHUTDOWN_IN_PROGRESS: AtomicU8 = AtomicU8::new(SHUTDOWN_NONE);
+
+static SHUTDOWN_HOOKS: IRQSafeNullLock<ShutdownHooks> = IRQSafeNullLock::new(ShutdownHooks::new());
+
+/// Functions that quiesce a subsystem before the board is reset or powered off.
+struct ShutdownHooks {
+    hooks: [Option<fn()>; MAX_SHUTDOWN_HOOKS],
+    num_hooks: usize,
+}
+
+impl ShutdownHooks {
+    const fn new() -> Self {
+        Self {
+            hooks: [None; MAX_SHUTDOWN_HOOKS],
+            num_hooks: 0,
+        }
+    }
+}
+
+/// Register a function that is called before a reboot or power-off.
+///
+/// Hooks run in reverse registration order, so subsystems that were brought up early are torn
+/// down last.
+pub fn register_shutdown_hook(hook: fn()) -> Result<(), &'static str> {
+    SHUTDOWN_HOOKS.lock(|hooks| {
+        if hooks.num_hooks == MAX_SHUTDOWN_HOOKS {
+            return Err("Too many shutdown hooks");
+        }
+
+        hooks.hooks[hooks.num_hooks] = Some(hook);
+        hooks.num_hooks += 1;
+
+        Ok(())
+    })
+}
+
+/// Run the shutdown hooks for the given kind of shutdown.
+///
+/// A hook that panics must not cause the hooks to run again. The panic handler notices the
+/// shutdown in progress through [`resume_interrupted_shutdown()`] and goes straight to the reset.
+fn run_shutdown_hooks(kind: u8) {
+    if SHUTDOWN_IN_PROGRESS
+        .compare_exchange(SHUTDOWN_NONE, kind, Ordering::Relaxed, Ordering::Relaxed)
+        .is_err()
+    {
+        return;
+    }
+
+    // Copy the hooks out, so that none of them runs with the lock held.
+    let (hooks, num_hooks) = SHUTDOWN_HOOKS.lock(|hooks| (hooks.hooks, hooks.num_hooks));
+
+    for hook in hooks[..num_hooks].iter().rev().flatten() {
+        hook();
+    }
+}
+
+/// Finish a reboot or power-off that was interrupted by a panic in one of the shutdown hooks.
+///
+/// Returns if no shutdown is in progress, or if it was already resumed once. The latter stops a
+/// reset path that panics itself from recursing.
+pub fn resume_interrupted_shutdown() {
+    let kind = SHUTDOWN_IN_PROGRESS.load(Ordering::Relaxed);
+    if kind == SHUTDOWN_NONE || kind == SHUTDOWN_RESUMED {
+        return;
+    }
+
+    SHUTDOWN_IN_PROGRESS.store(SHUTDOWN_RESUMED, Ordering::Relaxed);
+
+    if kind == SHUTDOWN_POWER_OFF {
+        power_off_board()
+    }
+    reset_board()
+}
+
+/// Reset the whole board without running the shutdown hooks.
+///
+/// For the panic handler, where a hook could deadlock on a lock the panicking code holds.
+pub fn reset_board() -> ! {
+    console::console().flush();
+
+    if let Some(watchdog) = super::driver::pm_watchdog() {
+        watchdog.reset();
+    }
+
+    let _ = cpu::psci::system_reset();
+
+    cpu::wait_forever()
+}
+
+/// Power off the whole board without running the shutdown hooks.
+fn power_off_board() -> ! {
+    console::console().flush();
+
+    let _ = cpu::psci::system_off();
+
+    // The board has no way to cut its own power without PSCI.
+    exception::local_irq_mask();
+    cpu::wait_forever()
+}
+
+/// Reset the whole board.
+///
+/// Runs the shutdown hooks first. Uses the power management watchdog if its driver is up, and PSCI
+/// otherwise. If neither is available, the core is parked.
+pub fn reboot() -> ! {
+    run_shutdown_hooks(SHUTDOWN_REBOOT);
+    reset_board()
+}
+
+/// Power off the whole board.
+///
+/// Runs the shutdown hooks first. Uses PSCI, and parks the core if that is not available.
+pub fn power_off() -> ! {
+    run_shutdown_hooks(SHUTDOWN_POWER_OFF);
+    power_off_board()
+}
+
+/// Return control to the resident kernelloader, so that a new kernel can be uploaded without a
+/// power cycle.
+///
+/// The loader expects to run with the MMU and caches off, like after the firmware handed over.
+/// The console is flushed and IRQs are masked first, so nothing touches memory behind our back.
+/// Then `disable_mmu_and_caching()` tears down the MMU in the order the architecture requires:
+/// data caching off, whole data cache cleaned by set/way, TLB invalidated, MMU and instruction
+/// caching off, instruction cache invalidated. The kernel is identity mapped, so execution
+/// continues at the same addresses.
+pub fn return_to_loader() -> ! {
+    console::console().flush();
+    exception::local_irq_mask();
+
+    unsafe {
+        if let Err(e) = memory::mmu::disable_mmu_and_caching() {
+            // The MMU was never turned on, which is just as fine for the loader.
+            if !matches!(e, memory::mmu::MMUEnableError::AlreadyDisabled) {
+                cpu::wait_forever()
+            }
+        }
+
+        cpu::jump_to(super::memory::map::LOADER_ENTRY_ADDRESS)
+    }
+}
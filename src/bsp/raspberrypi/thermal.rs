@@ -0,0 +1,33 @@
+//! BSP thermal monitoring.
+//!
+//! The SoC temperature is measured by the firmware and read through the mailbox property
+//! interface.
+
+/// Property tag to read the current SoC temperature.
+const TAG_GET_TEMPERATURE: u32 = 0x0003_0006;
+
+/// Property tag to read the temperature at which the firmware starts to throttle.
+const TAG_GET_MAX_TEMPERATURE: u32 = 0x0003_000A;
+
+/// Id of the SoC temperature sensor. The only one there is.
+const TEMPERATURE_ID: u32 = 0;
+
+fn query(tag: u32) -> Result<u32, &'static str> {
+    let mailbox = super::driver::mailbox().ok_or("Mailbox driver not initialized")?;
+
+    let mut values = [TEMPERATURE_ID, 0];
+    mailbox.call(tag, &mut values)?;
+
+    Ok(values[1])
+}
+
+/// Return the current SoC temperature in thousandths of a degree Celsius.
+pub fn temperature_millidegrees() -> Result<u32, &'static str> {
+    query(TAG_GET_TEMPERATURE)
+}
+
+/// Return the temperature in thousandths of a degree Celsius above which the firmware throttles
+/// the clocks.
+pub fn max_temperature() -> Result<u32, &'static str> {
+    query(TAG_GET_MAX_TEMPERATURE)
+}
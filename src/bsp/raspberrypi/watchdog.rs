@@ -0,0 +1,66 @@
+//! BSP watchdog.
+//!
+//! Once armed, the board resets unless `pet()` is called at least once per timeout.
+//!
+//! The hardware allows timeouts of up to 16 seconds. [`DEFAULT_TIMEOUT`] uses nearly all of it and
+//! the heartbeat pets every [`HEARTBEAT_INTERVAL`], which leaves a margin of 15 missed heartbeats.
+//! That is enough for long legitimate operations that keep the heartbeat from running, e.g. long
+//! sections with IRQs masked, while a hung kernel still resets within seconds. Operations that can
+//! block for longer must pet the watchdog themselves.
+//!
+//! The heartbeat is started at the end of init. Arming is opt-in with the `watchdog` feature, since
+//! an armed watchdog also resets a board that is stopped in a debugger or hangs on purpose. The
+//! `hang` demo masks IRQs and spins, to check that the board really resets.
+
+use crate::{time::timer, warn};
+use core::time::Duration;
+
+/// Timeout used when arming the watchdog for unattended operation.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How often the heartbeat is expected to pet the watchdog.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Start the watchdog. Fails if the driver is not up or the timeout is out of range.
+pub fn arm(timeout: Duration) -> Result<(), &'static str> {
+    match super::driver::pm_watchdog() {
+        None => Err("Watchdog driver not initialized"),
+        Some(watchdog) => watchdog.arm(timeout),
+    }
+}
+
+/// Return true if the watchdog is armed.
+pub fn is_armed() -> bool {
+    super::driver::pm_watchdog().map_or(false, |watchdog| watchdog.is_armed())
+}
+
+/// Reload the watchdog timer. Does nothing if the watchdog is not armed.
+pub fn pet() {
+    if let Some(watchdog) = super::driver::pm_watchdog() {
+        watchdog.pet();
+    }
+}
+
+/// Stop the watchdog.
+pub fn disable() {
+    if let Some(watchdog) = super::driver::pm_watchdog() {
+        watchdog.disable();
+    }
+}
+
+/// Pet the watchdog and schedule the next heartbeat.
+fn heartbeat() {
+    pet();
+
+    if let Err(e) = timer::timer().arm(HEARTBEAT_INTERVAL, heartbeat) {
+        warn!("Watchdog heartbeat stopped: {}", e);
+    }
+}
+
+/// Start petting the watchdog every [`HEARTBEAT_INTERVAL`] from the timer IRQ.
+///
+/// Pets are no-ops while the watchdog is disarmed, so the heartbeat can run unconditionally.
+pub fn start_heartbeat() -> Result<(), &'static str> {
+    timer::timer().arm(HEARTBEAT_INTERVAL, heartbeat)?;
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! Routes received console bytes to their consumer.
+//!
+//! Bytes normally go to the normal consumer, e.g. a shell. Without one, they are left to the
+//! console driver, which buffers them for its read functions. Receiving
+//! [`ESCAPE_SEQUENCE`] switches to upload mode, in which all bytes go to the upload consumer, e.g.
+//! a loader protocol, until it reports that it is done. This way an upload can be started while
+//! something else owns the input.
+//!
+//! Bytes that might start the escape sequence are held back until it either completes or turns
+//! out to be ordinary input. The escape sequence itself is never passed on. Without an upload
+//! consumer, it is passed on like ordinary input.
+
+use crate::synchronization::{IRQSafeNullLock, Mutex};
+
+/// Switches to upload mode.
+pub const ESCAPE_SEQUENCE: &[u8] = b"\x1b\x1bupload";
+
+/// Consumer of bytes in normal mode.
+pub type NormalConsumer = fn(u8);
+
+/// Consumer of bytes in upload mode.
+pub type UploadConsumer = fn(u8) -> UploadState;
+
+/// Returned by the upload consumer for each byte.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UploadState {
+    /// More bytes belong to the upload.
+    Continue,
+    /// The upload is complete. Switch back to normal mode.
+    Done,
+}
+
+/// Where bytes currently go.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Upload,
+}
+
+/// What to do with the bytes of one `route()` call.
+enum Delivery {
+    /// Pass the first `usize` bytes of the buffer to the normal consumer, if any.
+    Normal([u8; ESCAPE_SEQUENCE.len() + 1], usize),
+    Upload(UploadConsumer, u8),
+    None,
+}
+
+struct Router {
+    mode: Mode,
+    /// Number of bytes of the escape sequence received so far.
+    matched: usize,
+    normal: Option<NormalConsumer>,
+    upload: Option<UploadConsumer>,
+}
+
+static ROUTER: IRQSafeNullLock<Router> = IRQSafeNullLock::new(Router {
+    mode: Mode::Normal,
+    matched: 0,
+    normal: None,
+    upload: None,
+});
+
+impl Router {
+    fn route(&mut self, byte: u8) -> Delivery {
+        if self.mode == Mode::Upload {
+            return match self.upload {
+                Some(upload) => Delivery::Upload(upload, byte),
+                None => Delivery::None,
+            };
+        }
+
+        if byte == ESCAPE_SEQUENCE[self.matched] {
+            self.matched += 1;
+
+            if self.matched < ESCAPE_SEQUENCE.len() {
+                return Delivery::None;
+            }
+
+            self.matched = 0;
+            if self.upload.is_some() {
+                self.mode = Mode::Upload;
+                return Delivery::None;
+            }
+
+            let mut bytes = [0; ESCAPE_SEQUENCE.len() + 1];
+            bytes[..ESCAPE_SEQUENCE.len()].copy_from_slice(ESCAPE_SEQUENCE);
+            return Delivery::Normal(bytes, ESCAPE_SEQUENCE.len());
+        }
+
+        // Some of the held back bytes were ordinary input. Keep holding back the longest tail that
+        // might still start the escape sequence.
+        let mut bytes = [0; ESCAPE_SEQUENCE.len() + 1];
+        let len = self.matched + 1;
+        bytes[..self.matched].copy_from_slice(&ESCAPE_SEQUENCE[..self.matched]);
+        bytes[self.matched] = byte;
+
+        let num_ordinary = (1..len)
+            .find(|start| ESCAPE_SEQUENCE.starts_with(&bytes[*start..len]))
+            .unwrap_or(len);
+        self.matched = len - num_ordinary;
+
+        Delivery::Normal(bytes, num_ordinary)
+    }
+}
+
+/// Pass a received byte to its consumer.
+///
+/// Called by console drivers for every received byte. Bytes for normal mode without a normal
+/// consumer are handed to `unclaimed`, so that the driver can buffer them. Must not be called with
+/// the driver's lock held, as consumers may write to the console.
+pub fn route(byte: u8, mut unclaimed: impl FnMut(u8)) {
+    let (delivery, normal) = ROUTER.lock(|router| (router.route(byte), router.normal));
+
+    match delivery {
+        Delivery::Normal(bytes, len) => {
+            for byte in &bytes[..len] {
+                match normal {
+                    Some(normal) => normal(*byte),
+                    None => unclaimed(*byte),
+                }
+            }
+        }
+        Delivery::Upload(upload, byte) => {
+            if upload(byte) == UploadState::Done {
+                ROUTER.lock(|router| router.mode = Mode::Normal);
+            }
+        }
+        Delivery::None => (),
+    }
+}
+
+/// Set the consumer of bytes in normal mode. Until then, the console driver keeps them.
+pub fn register_normal_consumer(consumer: NormalConsumer) {
+    ROUTER.lock(|router| router.normal = Some(consumer));
+}
+
+/// Set the consumer of bytes in upload mode, which enables the escape sequence.
+pub fn register_upload_consumer(consumer: UploadConsumer) {
+    ROUTER.lock(|router| router.upload = Some(consumer));
+}
+
+/// Return where bytes currently go.
+pub fn mode() -> Mode {
+    ROUTER.lock(|router| router.mode)
+}
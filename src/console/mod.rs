@@ -2,8 +2,10 @@ use crate::console::interface::Console;
 use crate::synchronization::InitStateLock;
 use crate::synchronization::ReadWriteExclusive;
 
+pub mod input_router;
 pub mod null_console;
 
+#[link_section = ".data.ro_after_init"]
 static CUR_CONSOLE: InitStateLock<&'static (dyn Console + Sync)> =
     InitStateLock::new(&null_console::NULL_CONSOLE);
 
@@ -30,6 +32,11 @@ pub mod interface {
             ' '
         }
 
+        /// Read a single character if one was received, without blocking.
+        fn try_read_char(&self) -> Option<char> {
+            None
+        }
+
         /// Clear RX buffers, if any.
         fn clear_rx(&self);
     }
@@ -45,6 +52,12 @@ pub mod interface {
         fn chars_read(&self) -> usize {
             0
         }
+
+        /// Return the number of received characters that were dropped because a receive buffer or
+        /// FIFO was full.
+        fn rx_overruns(&self) -> usize {
+            0
+        }
     }
 
     /// Trait alias for a full-fledged console.
@@ -62,3 +75,37 @@ pub fn register_console(new_console: &'static (impl Console + Sync)) {
 pub fn console() -> &'static (dyn Console + Sync) {
     CUR_CONSOLE.read(|con| *con)
 }
+
+pub(crate) mod selftests {
+    use crate::{
+        console::{
+            self,
+            interface::{Statistics, Write},
+        },
+        selftest::SelfTest,
+    };
+
+    pub(crate) static TESTS: &[SelfTest] = &[SelfTest {
+        name: "console counts written chars",
+        run: test_console_statistics,
+        destructive: false,
+        needs_irqs: false,
+    }];
+
+    fn test_console_statistics() -> Result<(), &'static str> {
+        const NUM_CHARS: usize = 4;
+        let console = console::console();
+
+        let before = console.chars_written();
+        // Carriage returns do not disturb the output.
+        for _ in 0..NUM_CHARS {
+            console.write_char('\r');
+        }
+
+        if console.chars_written() != before + NUM_CHARS {
+            return Err("Written chars not counted");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+//! Memory barriers.
+//!
+//! All barrier usage in the kernel goes through these wrappers, so that the intent of each barrier
+//! is explicit at the call site.
+
+#[path = "../arch/aarch64/cpu/barrier.rs"]
+mod arch_barrier;
+
+pub use arch_barrier::*;
+
+/// Shareability domain a barrier applies to.
+#[derive(Copy, Clone)]
+pub enum Domain {
+    /// Full system.
+    SY,
+    /// Inner shareable, e.g. all cores of the cluster.
+    ISH,
+    /// Outer shareable, e.g. cores plus coherent DMA masters.
+    OSH,
+    /// Non-shareable, the executing core only.
+    NSH,
+}
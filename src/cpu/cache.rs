@@ -0,0 +1,67 @@
+//! Cache maintenance.
+
+#[path = "../arch/aarch64/cpu/cache.rs"]
+mod arch_cache;
+
+use crate::{info, size_human_readable_ceil};
+
+pub use arch_cache::*;
+
+/// The kind of caches implemented at a level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CacheKind {
+    Instruction,
+    Data,
+    /// Separate instruction and data caches.
+    Separate,
+    Unified,
+}
+
+/// A cache level as reported by the hardware.
+#[derive(Copy, Clone)]
+pub struct CacheLevel {
+    /// One based level, 1 is closest to the core.
+    pub level: u8,
+    pub kind: CacheKind,
+}
+
+/// The geometry of a single cache.
+#[derive(Copy, Clone)]
+pub struct CacheGeometry {
+    pub line_size: usize,
+    pub associativity: usize,
+    pub num_sets: usize,
+}
+
+impl CacheGeometry {
+    /// Total size of the cache in bytes.
+    pub fn size(&self) -> usize {
+        self.line_size * self.associativity * self.num_sets
+    }
+}
+
+fn print_geometry(level: u8, name: &str, geometry: CacheGeometry) {
+    let (size, unit) = size_human_readable_ceil(geometry.size());
+
+    info!(
+        "      L{} {:<11}: {:>3} {}, {:>2}-way, {} sets, {} Byte lines",
+        level, name, size, unit, geometry.associativity, geometry.num_sets, geometry.line_size
+    );
+}
+
+/// Print the geometry of each cache level of the executing core.
+pub fn print_info() {
+    info!("      Minimum line size: {} Byte", line_size());
+
+    for CacheLevel { level, kind } in levels() {
+        match kind {
+            CacheKind::Instruction => print_geometry(level, "instruction", geometry(level, true)),
+            CacheKind::Data => print_geometry(level, "data", geometry(level, false)),
+            CacheKind::Separate => {
+                print_geometry(level, "instruction", geometry(level, true));
+                print_geometry(level, "data", geometry(level, false));
+            }
+            CacheKind::Unified => print_geometry(level, "unified", geometry(level, false)),
+        }
+    }
+}
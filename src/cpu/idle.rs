@@ -0,0 +1,67 @@
+use crate::{bsp::cpu::NUM_CORES, exception, info, time};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+/// Uptime in nanoseconds at which each core entered the idle loop. Zero if it never did.
+static IDLE_LOOP_ENTRY_NS: [AtomicU64; NUM_CORES] = [ZERO; NUM_CORES];
+
+/// Accumulated time in nanoseconds each core spent waiting for interrupts in the idle loop.
+static IDLE_NS: [AtomicU64; NUM_CORES] = [ZERO; NUM_CORES];
+
+/// Idle the executing core forever, keeping track of the time spent sleeping.
+///
+/// IRQs are masked while sleeping. A pending IRQ still wakes the core, but its handler only runs
+/// after the sleep period was accounted and IRQs are unmasked again. That way, all time between
+/// waking up and the next sleep, including IRQ handling, counts as busy time.
+pub fn idle_loop() -> ! {
+    let core = super::smp::core_id::<usize>();
+
+    IDLE_LOOP_ENTRY_NS[core].store(time::uptime().as_nanos() as u64, Ordering::Relaxed);
+
+    loop {
+        exception::asynchronous::exec_with_irq_masked(|| {
+            let sleep_start = time::uptime();
+            super::wait_for_interrupt();
+            let sleep_time = time::uptime() - sleep_start;
+
+            IDLE_NS[core].fetch_add(sleep_time.as_nanos() as u64, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Return the utilization of the given core since it entered the idle loop, in hundredths of a
+/// percent (0 to 10000).
+///
+/// Returns `None` if the core has not entered the idle loop yet.
+pub fn utilization(core: usize) -> Option<u32> {
+    let entry_ns = IDLE_LOOP_ENTRY_NS[core].load(Ordering::Relaxed);
+    if entry_ns == 0 {
+        return None;
+    }
+
+    let total_ns = (time::uptime().as_nanos() as u64).saturating_sub(entry_ns);
+    if total_ns == 0 {
+        return Some(0);
+    }
+
+    let idle_ns = IDLE_NS[core].load(Ordering::Relaxed).min(total_ns);
+    let busy_ns = total_ns - idle_ns;
+
+    Some(((busy_ns as u128 * 10_000) / total_ns as u128) as u32)
+}
+
+/// Print the utilization of all cores that entered the idle loop.
+pub fn print_utilization() {
+    for core in 0..NUM_CORES {
+        if let Some(utilization) = utilization(core) {
+            info!(
+                "      Core {}: {}.{:02}% busy",
+                core,
+                utilization / 100,
+                utilization % 100
+            );
+        }
+    }
+}
@@ -1,5 +1,10 @@
 #[path = "../arch/aarch64/cpu/mod.rs"]
 mod arch_cpu;
+pub mod barrier;
+pub mod cache;
+mod idle;
+pub mod psci;
 pub mod smp;
 
 pub use arch_cpu::*;
+pub use idle::*;
@@ -0,0 +1,147 @@
+//! Power State Coordination Interface.
+//!
+//! PSCI is implemented by the secure firmware and offers a standard way to power cores on and off
+//! and to reset the system. Not every firmware implements it: the stock Raspberry Pi armstub parks
+//! the secondary cores in a spin-table instead and disables the `smc` instruction. Availability is
+//! therefore decided by the BSP and then confirmed by querying the PSCI version. All functions
+//! fail gracefully if PSCI is not available.
+
+#[path = "../arch/aarch64/cpu/psci.rs"]
+mod arch_psci;
+
+use crate::memory::{Address, Physical};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+const PSCI_VERSION: u32 = 0x8400_0000;
+const CPU_OFF: u32 = 0x8400_0002;
+const CPU_ON: u32 = 0xC400_0003;
+const SYSTEM_OFF: u32 = 0x8400_0008;
+const SYSTEM_RESET: u32 = 0x8400_0009;
+
+const NOT_SUPPORTED: i64 = -1;
+
+const UNKNOWN: u8 = 0;
+const AVAILABLE: u8 = 1;
+const UNAVAILABLE: u8 = 2;
+
+/// Cached result of the availability check.
+///
+/// Only plain loads and stores are used, so this is safe even with MMU and caching deactivated,
+/// e.g. from an early panic.
+static AVAILABILITY: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// Translate a PSCI return code into an error string.
+fn error_str(code: i64) -> &'static str {
+    match code {
+        -1 => "PSCI: Not supported",
+        -2 => "PSCI: Invalid parameters",
+        -3 => "PSCI: Denied",
+        -4 => "PSCI: Already on",
+        -5 => "PSCI: On pending",
+        -6 => "PSCI: Internal failure",
+        -7 => "PSCI: Not present",
+        -8 => "PSCI: Disabled",
+        -9 => "PSCI: Invalid address",
+        _ => "PSCI: Unknown error",
+    }
+}
+
+/// Return the (major, minor) PSCI version implemented by the firmware, or `None` if PSCI is not
+/// available.
+pub fn version() -> Option<(u16, u16)> {
+    if !crate::bsp::cpu::PSCI_AVAILABLE {
+        return None;
+    }
+
+    let ret = unsafe { arch_psci::call(PSCI_VERSION, 0, 0, 0) };
+    if ret == NOT_SUPPORTED {
+        return None;
+    }
+
+    Some(((ret >> 16) as u16, ret as u16))
+}
+
+/// Return true if PSCI calls can be issued.
+pub fn is_available() -> bool {
+    match AVAILABILITY.load(Ordering::Relaxed) {
+        AVAILABLE => true,
+        UNAVAILABLE => false,
+        _ => {
+            let available = version().is_some();
+            AVAILABILITY.store(
+                if available { AVAILABLE } else { UNAVAILABLE },
+                Ordering::Relaxed,
+            );
+
+            available
+        }
+    }
+}
+
+/// Power on the given core and let it start executing at `entry_point`.
+///
+/// The core starts with MMU and caches off, so `entry_point` is a physical address. `context_id`
+/// is handed to the new core in x0.
+pub fn cpu_on(
+    target_core: usize,
+    entry_point: Address<Physical>,
+    context_id: u64,
+) -> Result<(), &'static str> {
+    if !is_available() {
+        return Err("PSCI not available");
+    }
+
+    // On the BCM2711, the core id is the affinity level 0 field of the MPIDR.
+    let ret = unsafe {
+        arch_psci::call(
+            CPU_ON,
+            target_core as u64,
+            entry_point.as_usize() as u64,
+            context_id,
+        )
+    };
+
+    match ret {
+        0 => Ok(()),
+        code => Err(error_str(code)),
+    }
+}
+
+/// Power off the executing core.
+///
+/// Only returns on failure, in which case the caller should fall back to parking the core.
+pub fn cpu_off() -> Result<(), &'static str> {
+    if !is_available() {
+        return Err("PSCI not available");
+    }
+
+    let ret = unsafe { arch_psci::call(CPU_OFF, 0, 0, 0) };
+
+    Err(error_str(ret))
+}
+
+/// Power off the whole system.
+///
+/// Only returns on failure, in which case the caller should fall back to parking the core.
+pub fn system_off() -> Result<(), &'static str> {
+    if !is_available() {
+        return Err("PSCI not available");
+    }
+
+    let ret = unsafe { arch_psci::call(SYSTEM_OFF, 0, 0, 0) };
+
+    Err(error_str(ret))
+}
+
+/// Reset the whole system.
+///
+/// Only returns on failure, in which case the caller should fall back to another reset method.
+pub fn system_reset() -> Result<(), &'static str> {
+    if !is_available() {
+        return Err("PSCI not available");
+    }
+
+    let ret = unsafe { arch_psci::call(SYSTEM_RESET, 0, 0, 0) };
+
+    Err(error_str(ret))
+}
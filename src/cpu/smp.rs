@@ -2,3 +2,37 @@
 mod arch_cpu_smp;
 
 pub use arch_cpu_smp::*;
+
+/// A set of cores, one bit per core id.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct CoreMask(u8);
+
+impl CoreMask {
+    /// Create a mask containing only the given core.
+    pub const fn from_core(core_id: usize) -> Self {
+        assert!(core_id < crate::bsp::cpu::NUM_CORES);
+
+        Self(1 << core_id)
+    }
+
+    /// Return a copy of the mask that additionally contains the given core.
+    pub const fn with_core(self, core_id: usize) -> Self {
+        Self(self.0 | Self::from_core(core_id).0)
+    }
+
+    /// Return the raw bit representation, bit `n` standing for core `n`.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Return true if the mask contains no core.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl core::fmt::Display for CoreMask {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{:#06b}", self.0)
+    }
+}
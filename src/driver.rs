@@ -1,7 +1,9 @@
 use crate::{
     exception::asynchronous::IRQNumber,
-    info,
+    fault, info,
+    state::{init_phase_tracker, InitPhase},
     synchronization::{InitStateLock, ReadWriteExclusive},
+    warn,
 };
 
 const NUM_DRIVERS: usize = 5;
@@ -55,6 +57,10 @@ where
     pub device_driver: &'static (dyn interface::DeviceDriver<IRQNumberType = T> + Sync),
     pub post_init_callback: Option<DeviceDriverPostInitCallback>,
     pub irq_number: Option<T>,
+    /// If set, a failed `init()` leaves the driver out instead of panicking: its post-init
+    /// callback is not called and its IRQ is not registered. For drivers the kernel can do
+    /// without.
+    pub best_effort: bool,
 }
 
 struct DriverManagerInner<T>
@@ -63,6 +69,8 @@ where
 {
     next_index: usize,
     descriptors: [Option<DeviceDriverDescriptor<T>>; NUM_DRIVERS],
+    /// Why the best-effort driver with the same index failed to initialize, if it did.
+    init_errors: [Option<&'static str>; NUM_DRIVERS],
 }
 
 impl<T> DriverManagerInner<T>
@@ -74,6 +82,7 @@ where
         Self {
             next_index: 0,
             descriptors: [None; NUM_DRIVERS],
+            init_errors: [None; NUM_DRIVERS],
         }
     }
 }
@@ -105,31 +114,62 @@ where
         })
     }
 
-    /// Helper for iterating over registered drivers.
-    fn for_each_descriptor<'a>(&'a self, f: impl FnMut(&'a DeviceDriverDescriptor<T>)) {
+    /// Helper for iterating over registered drivers, together with their index.
+    fn for_each_descriptor<'a>(&'a self, mut f: impl FnMut(usize, &'a DeviceDriverDescriptor<T>)) {
         self.inner.read(|inner| {
             inner
                 .descriptors
                 .iter()
-                .filter_map(|x| x.as_ref())
-                .for_each(f)
+                .enumerate()
+                .filter_map(|(i, x)| Some((i, x.as_ref()?)))
+                .for_each(|(i, x)| f(i, x))
+        })
+    }
+
+    /// Return why the driver with the given compatible string failed to initialize, if it was
+    /// left out.
+    pub fn init_error(&self, compatible: &str) -> Option<&'static str> {
+        self.inner.read(|inner| {
+            inner
+                .descriptors
+                .iter()
+                .zip(inner.init_errors)
+                .find_map(|(descriptor, error)| match descriptor {
+                    Some(x) if x.device_driver.compatible() == compatible => Some(error),
+                    _ => None,
+                })
+                .flatten()
         })
     }
 
     /// Fully initialize all drivers and their interrupts handlers.
     ///
+    /// Completes [`InitPhase::DriverInit`].
+    ///
     /// # Safety
     ///
     /// - During init, drivers might do stuff with system-wide impact.
     pub unsafe fn init_drivers_and_irqs(&self) {
-        self.for_each_descriptor(|descriptor| {
+        init_phase_tracker().assert_ready_for(InitPhase::DriverInit);
+
+        let mut init_errors = [None; NUM_DRIVERS];
+
+        self.for_each_descriptor(|i, descriptor| {
             // 1. Initialize driver.
-            if let Err(x) = descriptor.device_driver.init() {
-                panic!(
-                    "Error initializing driver: {}: {}",
-                    descriptor.device_driver.compatible(),
-                    x
-                );
+            let compatible = descriptor.device_driver.compatible();
+            let result = if fault::should_fail_for(fault::point::DRIVER_INIT, compatible) {
+                Err(fault::INJECTED_FAULT)
+            } else {
+                descriptor.device_driver.init()
+            };
+
+            if let Err(x) = result {
+                if !descriptor.best_effort {
+                    panic!("Error initializing driver: {}: {}", compatible, x);
+                }
+
+                init_errors[i] = Some(x);
+                return;
             }
 
             // 2. Call corresponding post init callback.
@@ -144,9 +184,15 @@ where
             }
         });
 
+        self.inner.write(|inner| inner.init_errors = init_errors);
+
         // 3. After all post-init callbacks were done, the interrupt controller should be
         //    registered and functional. So let drivers register with it now.
-        self.for_each_descriptor(|descriptor| {
+        self.for_each_descriptor(|i, descriptor| {
+            if init_errors[i].is_some() {
+                return;
+            }
+
             if let Some(irq_number) = &descriptor.irq_number {
                 if let Err(x) = descriptor
                     .device_driver
@@ -160,15 +206,33 @@ where
                 }
             }
         });
+
+        // The console is up now, so the drivers that were left out can be reported.
+        self.for_each_descriptor(|i, descriptor| {
+            if let Some(x) = init_errors[i] {
+                warn!(
+                    "Driver left out: {}: {}",
+                    descriptor.device_driver.compatible(),
+                    x
+                );
+            }
+        });
+
+        init_phase_tracker().complete(InitPhase::DriverInit);
     }
 
     /// Enumerate all registered device drivers.
     pub fn enumerate(&self) {
-        let mut i: usize = 1;
-        self.for_each_descriptor(|descriptor| {
-            info!("{}. {}", i, descriptor.device_driver.compatible());
-
-            i += 1;
+        let init_errors = self.inner.read(|inner| inner.init_errors);
+
+        self.for_each_descriptor(|i, descriptor| match init_errors[i] {
+            None => info!("{}. {}", i + 1, descriptor.device_driver.compatible()),
+            Some(x) => info!(
+                "{}. {} (failed: {})",
+                i + 1,
+                descriptor.device_driver.compatible(),
+                x
+            ),
         });
     }
 }
@@ -1,3 +1,4 @@
+use crate::cpu::smp::CoreMask;
 use crate::exception::null_irq_manager::NULL_IRQ_MANAGER;
 use crate::exception::{local_irq_mask_and_save, local_irq_restore};
 use crate::synchronization;
@@ -58,11 +59,27 @@ pub trait IRQManager {
     /// Enable an interrupt in the controller.
     fn enable(&self, irq_number: &Self::IRQNumberType);
 
+    /// Route an interrupt to the given set of cores.
+    ///
+    /// Fails for interrupts that are private to a core and therefore cannot be routed.
+    fn set_affinity(
+        &self,
+        irq_number: &Self::IRQNumberType,
+        mask: CoreMask,
+    ) -> Result<(), &'static str>;
+
+    /// Set the priority of an interrupt. Lower values are more urgent.
+    fn set_priority(
+        &self,
+        irq_number: &Self::IRQNumberType,
+        priority: u8,
+    ) -> Result<(), &'static str>;
+
     /// Handle pending interrupts.
     ///
     /// This function is called directly from the CPU's IRQ exception vector. On AArch64,
-    /// this means that the respective CPU core has disabled exception handling.
-    /// This function can therefore not be preempted and runs start to finish.
+    /// this means that the respective CPU core has disabled exception handling. An implementation
+    /// may unmask IRQs while a handler runs, so that interrupts of higher priority preempt it.
     ///
     /// Takes an IRQContext token to ensure it can only be called from IRQ context.
     #[allow(clippy::trivially_copy_pass_by_ref)]
@@ -70,6 +87,10 @@ pub trait IRQManager {
 
     /// Print list of registered handlers.
     fn print_handler(&self) {}
+
+    /// Print the current state of the interrupt controller, e.g. which IRQs are enabled, pending
+    /// or active. Useful for debugging lost IRQs or IRQ storms.
+    fn dump_state(&self) {}
 }
 
 /// IRQContext token.
@@ -101,6 +122,33 @@ impl<'irq_context> IRQContext<'irq_context> {
     }
 }
 
+/// ThreadContext token.
+///
+/// An instance of this type indicates that the local core is executing in thread context, aka
+/// outside of any interrupt vector, where it is allowed to block. APIs that may block, like
+/// [`crate::time::sleep()`], take it as proof.
+///
+/// IRQ handlers only ever receive an [`IRQContext`], so they have no way to call such APIs. The
+/// token is neither `Send` nor `Sync`, so it can not be smuggled into an IRQ handler through a
+/// static either.
+#[derive(Clone, Copy)]
+pub struct ThreadContext<'thread_context> {
+    _0: PhantomData<&'thread_context *const ()>,
+}
+
+impl<'thread_context> ThreadContext<'thread_context> {
+    /// Creates a ThreadContext token.
+    ///
+    /// # Safety
+    ///
+    /// - This must only be called in thread context, i.e. not from an interrupt vector or subcalls
+    ///   of it. The intended place is the entry of `kernel_main()`, which hands the token down.
+    #[inline(always)]
+    pub unsafe fn new() -> Self {
+        ThreadContext { _0: PhantomData }
+    }
+}
+
 /// Interrupt descriptor.
 #[derive(Copy, Clone)]
 pub struct IRQHandlerDescriptor<T>
@@ -113,4 +161,9 @@ where
     pub name: &'static str,
     /// Reference to handler trait object.
     pub handler: &'static (dyn IRQHandler + Sync),
+    /// Cores the IRQ is routed to. `None` keeps the routing set up by the IRQ manager.
+    pub affinity: Option<CoreMask>,
+    /// Priority of the IRQ, lower values being more urgent. `None` picks the IRQ manager's
+    /// default.
+    pub priority: Option<u8>,
 }
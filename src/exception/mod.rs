@@ -44,6 +44,11 @@ impl Debug for ExceptionLevel {
     }
 }
 
+/// Print the registers of the executing core, e.g. to give a panic some context.
+pub fn print_register_snapshot() {
+    arch_exception_vector::print_register_snapshot()
+}
+
 /// Prints exception status.
 #[inline(always)]
 pub fn print_exception_state() {
@@ -1,5 +1,6 @@
 use crate::{
     bsp::drivers::gicv2::IRQNumber,
+    cpu::smp::CoreMask,
     exception::asynchronous::{IRQContext, IRQHandlerDescriptor, IRQManager},
 };
 
@@ -22,6 +23,22 @@ impl IRQManager for NullIRQManager {
         panic!("No IRQ Manager registered yet");
     }
 
+    fn set_affinity(
+        &self,
+        _irq_number: &Self::IRQNumberType,
+        _mask: CoreMask,
+    ) -> Result<(), &'static str> {
+        panic!("No IRQ Manager registered yet");
+    }
+
+    fn set_priority(
+        &self,
+        _irq_number: &Self::IRQNumberType,
+        _priority: u8,
+    ) -> Result<(), &'static str> {
+        panic!("No IRQ Manager registered yet");
+    }
+
     fn handle_pending_irqs<'irq_context>(&'irq_context self, _ic: &IRQContext<'irq_context>) {
         panic!("No IRQ Manager registered yet");
     }
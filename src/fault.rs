@@ -0,0 +1,245 @@
+//! Fault injection for exercising error paths.
+//!
+//! Error arms that are hard to trigger on real hardware check a named fault point with
+//! [`should_fail()`] and return an error if it was armed. A test arms a point with [`arm()`], which
+//! makes exactly the next check of that point fail.
+//!
+//! Points that are checked for several instances, like driver init, are checked with
+//! [`should_fail_for()`] instead. [`arm_for()`] arms them for a single instance, so that only its
+//! next check fails.
+//!
+//! Without the `fault_injection` feature, [`should_fail()`] and [`should_fail_for()`] are a
+//! constant `false` and the checks compile away.
+
+/// Names of the fault points.
+pub mod point {
+    /// `memory::mmu::kernel_map_at()` and the other kernel mapping functions.
+    pub const MAP_AT: &str = "map_at";
+    /// `memory::mmu::page_alloc::PageAllocator::alloc()`.
+    pub const PAGE_ALLOC: &str = "page_alloc";
+    /// The `init()` of every device driver. Instances are the drivers' compatible strings.
+    pub const DRIVER_INIT: &str = "driver_init";
+}
+
+/// Error returned by an instrumented function whose fault point fired.
+pub const INJECTED_FAULT: &str = "Injected fault";
+
+#[cfg(feature = "fault_injection")]
+mod registry {
+    use super::point;
+    use crate::synchronization::{IRQSafeNullLock, Mutex};
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    const POINTS: [&str; 3] = [point::MAP_AT, point::PAGE_ALLOC, point::DRIVER_INIT];
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const DISARMED: AtomicBool = AtomicBool::new(false);
+
+    static ARMED: [AtomicBool; POINTS.len()] = [DISARMED; POINTS.len()];
+
+    /// The instance each point is armed for. `None` if any check of the point fails.
+    static TARGETS: IRQSafeNullLock<[Option<&'static str>; POINTS.len()]> =
+        IRQSafeNullLock::new([None; POINTS.len()]);
+
+    fn index(name: &str) -> Option<usize> {
+        POINTS.iter().position(|point| *point == name)
+    }
+
+    pub fn arm(name: &str, target: Option<&'static str>) -> Result<(), &'static str> {
+        let index = index(name).ok_or("Unknown fault point")?;
+
+        TARGETS.lock(|targets| {
+            targets[index] = target;
+            ARMED[index].store(true, Ordering::Relaxed);
+        });
+
+        Ok(())
+    }
+
+    pub fn disarm_all() {
+        TARGETS.lock(|targets| {
+            for (armed, target) in ARMED.iter().zip(targets.iter_mut()) {
+                armed.store(false, Ordering::Relaxed);
+                *target = None;
+            }
+        });
+    }
+
+    pub fn should_fail(name: &str, instance: Option<&str>) -> bool {
+        let index = match index(name) {
+            None => return false,
+            Some(index) => index,
+        };
+
+        TARGETS.lock(|targets| {
+            if targets[index].is_some() && targets[index] != instance {
+                return false;
+            }
+
+            targets[index] = None;
+            ARMED[index].swap(false, Ordering::Relaxed)
+        })
+    }
+}
+
+/// Make the next check of the fault point `name` fail.
+#[cfg(feature = "fault_injection")]
+pub fn arm(name: &str) -> Result<(), &'static str> {
+    registry::arm(name, None)
+}
+
+/// Make the next check of the fault point `name` for `instance` fail. Checks for other instances
+/// pass.
+#[cfg(feature = "fault_injection")]
+pub fn arm_for(name: &str, instance: &'static str) -> Result<(), &'static str> {
+    registry::arm(name, Some(instance))
+}
+
+/// Disarm all fault points, e.g. after a test whose armed point was not reached.
+#[cfg(feature = "fault_injection")]
+pub fn disarm_all() {
+    registry::disarm_all()
+}
+
+/// Returns true if the fault point `name` was armed, and disarms it.
+#[cfg(feature = "fault_injection")]
+pub fn should_fail(name: &str) -> bool {
+    registry::should_fail(name, None)
+}
+
+/// Returns true if the fault point `name` was armed for `instance` or for any instance, and
+/// disarms it.
+#[cfg(feature = "fault_injection")]
+pub fn should_fail_for(name: &str, instance: &str) -> bool {
+    registry::should_fail(name, Some(instance))
+}
+
+/// Returns true if the fault point `name` was armed. Never, without fault injection.
+#[cfg(not(feature = "fault_injection"))]
+#[inline(always)]
+pub fn should_fail(_name: &str) -> bool {
+    false
+}
+
+/// Returns true if the fault point `name` was armed for `instance`. Never, without fault injection.
+#[cfg(not(feature = "fault_injection"))]
+#[inline(always)]
+pub fn should_fail_for(_name: &str, _instance: &str) -> bool {
+    false
+}
+
+#[cfg(feature = "fault_injection")]
+pub(crate) mod selftests {
+    use crate::{
+        bsp::{self, drivers::bcm2711_pm_wdog::PMWatchdog},
+        driver, fault,
+        memory::mmu::{
+            self, AccessPermissions, AttributeFields, MemAttributes, MemoryRegion, PageAddress,
+        },
+        selftest::SelfTest,
+        synchronization::Mutex,
+    };
+
+    pub(crate) static TESTS: &[SelfTest] = &[
+        SelfTest {
+            name: "injected map_at fault",
+            run: test_injected_map_at_fault,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "injected page_alloc fault",
+            run: test_injected_page_alloc_fault,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "injected driver_init fault leaves only its driver out",
+            run: test_injected_driver_init_fault,
+            destructive: false,
+            needs_irqs: false,
+        },
+    ];
+
+    /// Arm `point`, run `f` and check that it failed because of the fault.
+    fn expect_injected_fault<T>(
+        point: &str,
+        f: impl FnOnce() -> Result<T, &'static str>,
+    ) -> Result<Option<T>, &'static str> {
+        fault::arm(point)?;
+        let result = f();
+        // In case the point was not reached.
+        fault::disarm_all();
+
+        match result {
+            Err(fault::INJECTED_FAULT) => Ok(None),
+            Err(_) => Err("Failed, but not because of the injected fault"),
+            Ok(x) => Ok(Some(x)),
+        }
+    }
+
+    fn test_injected_map_at_fault() -> Result<(), &'static str> {
+        let virt_region = bsp::memory::mmu::virt_heap_region();
+        let phys_region = MemoryRegion::new(
+            PageAddress::from(virt_region.start_page.address().as_usize()),
+            PageAddress::from(virt_region.end_page_exclusive.address().as_usize()),
+        );
+        let attr = AttributeFields {
+            mem_attributes: MemAttributes::CacheableDRAM,
+            acc_perms: AccessPermissions::ReadWrite,
+            executable: false,
+        };
+
+        // The fault fires before the tables are touched, which can not be changed after kernel
+        // init.
+        let result = expect_injected_fault(fault::point::MAP_AT, || unsafe {
+            mmu::kernel_map_at("selftest", &virt_region, &phys_region, &attr)
+        })?;
+
+        if result.is_some() {
+            return Err("Mapping succeeded despite the injected fault");
+        }
+
+        if fault::should_fail(fault::point::MAP_AT) {
+            return Err("Fault point still armed after firing");
+        }
+
+        Ok(())
+    }
+
+    fn test_injected_page_alloc_fault() -> Result<(), &'static str> {
+        let num_pages = core::num::NonZeroUsize::new(1).unwrap();
+        let alloc = || {
+            mmu::page_alloc::KERNEL_MMIO_VA_ALLOCATOR.lock(|allocator| allocator.alloc(num_pages))
+        };
+
+        if let Some(region) = expect_injected_fault(fault::point::PAGE_ALLOC, alloc)? {
+            mmu::page_alloc::KERNEL_MMIO_VA_ALLOCATOR.lock(|allocator| allocator.free(region))?;
+            return Err("Allocation succeeded despite the injected fault");
+        }
+
+        Ok(())
+    }
+
+    fn test_injected_driver_init_fault() -> Result<(), &'static str> {
+        if driver::DRIVER_MANAGER.init_error(PMWatchdog::COMPATIBLE) != Some(fault::INJECTED_FAULT)
+        {
+            return Err("Watchdog init did not fail");
+        }
+
+        if bsp::driver::pm_watchdog().is_some() {
+            return Err("Failed watchdog driver is available");
+        }
+
+        // The drivers around it must have come up regardless.
+        if bsp::driver::gpio().is_none() || bsp::driver::mailbox().is_none() {
+            return Err("Driver next to the failed one is missing");
+        }
+
+        if fault::should_fail_for(fault::point::DRIVER_INIT, PMWatchdog::COMPATIBLE) {
+            return Err("Fault point still armed after firing");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,584 @@
+//! Minimal GDB Remote Serial Protocol stub over the console.
+//!
+//! The stub is entered from the exception handler when a `brk` instruction is executed. It then
+//! takes over the console until the debugger continues or detaches. Supported packets:
+//!
+//! - `?`: stop reason.
+//! - `g`/`G`: read/write all registers.
+//! - `m`/`M`: read/write memory. Only mapped memory can be accessed, everything else is answered
+//!   with an error. Code is mapped read-only, so `M` can not patch it.
+//! - `Z0`/`z0`: insert/remove a software breakpoint. The `brk` is written through a writable alias
+//!   of the code that [`init()`] maps, since the code itself is mapped read-only.
+//! - `c`: continue, optionally at a new address.
+//! - `s`: single-step, optionally at a new address. IRQs stay masked during the step, otherwise the
+//!   step would end in the IRQ handler.
+//! - `D`: detach.
+//! - `k`: kill, which resets the board.
+//!
+//! All other packets are answered with an empty packet, which tells gdb that they are not
+//! supported.
+//!
+//! The stub is only built with the `gdbstub` feature. The code alias weakens the write protection
+//! of the kernel code for the whole uptime, so it must not be part of regular builds.
+
+use crate::{
+    bsp::{self, memory::mmu::kernel_page_attributes},
+    console::{self, interface::Read, interface::Write},
+    cpu,
+    memory::{
+        mmu::{self, AccessPermissions, MemoryRegion},
+        Address, Virtual,
+    },
+    synchronization::{IRQSafeNullLock, InitStateLock, Mutex, ReadWriteExclusive},
+};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Maximum size of a packet's payload. Also reported to gdb, so that it never sends more.
+const PACKET_BUF_SIZE: usize = 1024;
+
+/// Signal reported as stop reason, SIGTRAP.
+const STOP_SIGNAL: u8 = 5;
+
+/// Error reported for memory accesses to unmapped or read-only memory, EFAULT.
+const ERROR_FAULT: &str = "E0e";
+
+/// Error reported for malformed packets, EINVAL.
+const ERROR_INVALID: &str = "E16";
+
+/// Maximum number of software breakpoints inserted at the same time.
+const MAX_BREAKPOINTS: usize = 16;
+
+/// Encoding of `brk #0`, the instruction inserted for a software breakpoint.
+const BREAKPOINT_INSTRUCTION: u32 = 0xD420_0000;
+
+/// PSTATE bits in the saved program status.
+const SPSR_SS: u32 = 1 << 21;
+const SPSR_DAIF: u32 = 0b1111 << 6;
+const SPSR_D: u32 = 1 << 9;
+const SPSR_I: u32 = 1 << 7;
+
+/// Set while a debugger is attached, i.e. waits for a stop reply after it continued the target.
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+
+/// Set while a single step is in flight.
+static STEPPING: AtomicBool = AtomicBool::new(false);
+
+/// The DAIF bits of the stepped code, which are changed for the duration of the step.
+static STEP_SAVED_DAIF: AtomicU32 = AtomicU32::new(0);
+
+/// The writable alias of the code, set up by [`init()`].
+static CODE_ALIAS: InitStateLock<Option<CodeAlias>> = InitStateLock::new(None);
+
+/// The currently inserted software breakpoints.
+static BREAKPOINTS: IRQSafeNullLock<[Option<Breakpoint>; MAX_BREAKPOINTS]> =
+    IRQSafeNullLock::new([None; MAX_BREAKPOINTS]);
+
+/// The kernel code and the start of its writable alias.
+#[derive(Copy, Clone)]
+struct CodeAlias {
+    code: MemoryRegion<Virtual>,
+    alias_start: Address<Virtual>,
+}
+
+impl CodeAlias {
+    /// Returns the writable address of the instruction at `addr`, if it is in the code.
+    fn writable(&self, addr: usize) -> Option<*mut u32> {
+        if addr % 4 != 0 || !self.code.contains(Address::new(addr)) {
+            return None;
+        }
+
+        let offset = addr - self.code.start_page.address().as_usize();
+        Some((self.alias_start + offset).as_usize() as *mut u32)
+    }
+}
+
+/// A software breakpoint and the instruction it replaced.
+#[derive(Copy, Clone)]
+struct Breakpoint {
+    addr: usize,
+    original: u32,
+}
+
+/// The register file in the layout of gdb's `g` packet for AArch64.
+#[derive(Default)]
+pub struct GdbRegisters {
+    /// x0 to x30.
+    pub x: [u64; 31],
+    pub sp: u64,
+    pub pc: u64,
+    pub cpsr: u32,
+}
+
+/// What the stub should do after a packet was handled.
+enum Action {
+    Stay,
+    Resume,
+    Step,
+}
+
+/// A fixed-size packet buffer.
+struct Packet {
+    buf: [u8; PACKET_BUF_SIZE],
+    len: usize,
+}
+
+impl Packet {
+    const fn new() -> Self {
+        Self {
+            buf: [0; PACKET_BUF_SIZE],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Append a byte. Returns false if the packet is full.
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len == PACKET_BUF_SIZE {
+            return false;
+        }
+
+        self.buf[self.len] = byte;
+        self.len += 1;
+        true
+    }
+
+    fn push_str(&mut self, s: &str) {
+        for byte in s.bytes() {
+            self.push(byte);
+        }
+    }
+
+    fn push_hex_u8(&mut self, byte: u8) {
+        self.push(hex_digit(byte >> 4));
+        self.push(hex_digit(byte & 0xf));
+    }
+
+    /// Append a number in hex without leading zeros, as used for sizes in replies.
+    fn push_hex_number(&mut self, value: usize) {
+        let num_digits = (usize::BITS - value.leading_zeros()).div_ceil(4).max(1);
+
+        for i in (0..num_digits).rev() {
+            self.push(hex_digit((value >> (i * 4)) as u8));
+        }
+    }
+
+    /// Append the lowest `num_bytes` bytes of `value` in target (little endian) byte order.
+    fn push_hex_le(&mut self, value: u64, num_bytes: usize) {
+        for byte in value.to_le_bytes().iter().take(num_bytes) {
+            self.push_hex_u8(*byte);
+        }
+    }
+}
+
+fn hex_digit(nibble: u8) -> u8 {
+    b"0123456789abcdef"[(nibble & 0xf) as usize]
+}
+
+fn parse_hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Parse a big endian hex number as used for addresses and lengths.
+fn parse_hex(s: &[u8]) -> Option<usize> {
+    if s.is_empty() {
+        return None;
+    }
+
+    s.iter().try_fold(0usize, |acc, c| {
+        acc.checked_mul(16)?
+            .checked_add(parse_hex_digit(*c)? as usize)
+    })
+}
+
+fn parse_hex_u8(s: &[u8]) -> Option<u8> {
+    Some((parse_hex_digit(s[0])? << 4) | parse_hex_digit(s[1])?)
+}
+
+/// Parse `num_bytes` bytes of hex encoded data in target (little endian) byte order.
+fn parse_hex_le(s: &[u8], num_bytes: usize) -> Option<u64> {
+    if s.len() < num_bytes * 2 {
+        return None;
+    }
+
+    let mut value = 0u64;
+    for i in 0..num_bytes {
+        value |= u64::from(parse_hex_u8(&s[i * 2..])?) << (i * 8);
+    }
+
+    Some(value)
+}
+
+/// Parse `addr,len` and return the rest of the input after it.
+fn parse_addr_len(s: &[u8]) -> Option<(usize, usize, &[u8])> {
+    let comma = s.iter().position(|c| *c == b',')?;
+    let end = s.iter().position(|c| *c == b':').unwrap_or(s.len());
+
+    let addr = parse_hex(&s[..comma])?;
+    let len = parse_hex(s.get(comma + 1..end)?)?;
+    let rest = s.get(end + 1..).unwrap_or(&[]);
+
+    Some((addr, len, rest))
+}
+
+/// Check that every page touched by the range is mapped, and writable if requested.
+fn range_accessible(addr: usize, len: usize, write: bool) -> bool {
+    let end = match addr.checked_add(len) {
+        None => return false,
+        Some(end) => end,
+    };
+
+    let mut page = Address::<Virtual>::new(addr).align_down_page();
+    while page.as_usize() < end {
+        if !mmu::is_mapped(page) {
+            return false;
+        }
+
+        if write {
+            match kernel_page_attributes(page.into()) {
+                Ok(attr) if attr.acc_perms == AccessPermissions::ReadWrite => (),
+                _ => return false,
+            }
+        }
+
+        page = page + bsp::memory::mmu::MSKernel::SIZE;
+    }
+
+    true
+}
+
+/// Receive a packet, verify its checksum and acknowledge it.
+fn receive_packet(packet: &mut Packet) {
+    let console = console::console();
+
+    loop {
+        // Wait for the start of a packet. Anything else, e.g. stray acks, is dropped.
+        while console.read_char() as u8 != b'$' {}
+
+        packet.clear();
+        let mut checksum: u8 = 0;
+        let mut overflow = false;
+
+        loop {
+            let c = console.read_char() as u8;
+            if c == b'#' {
+                break;
+            }
+
+            checksum = checksum.wrapping_add(c);
+            overflow |= !packet.push(c);
+        }
+
+        let high = console.read_char() as u8;
+        let low = console.read_char() as u8;
+
+        if !overflow && parse_hex_u8(&[high, low]) == Some(checksum) {
+            console.write_char('+');
+            return;
+        }
+
+        console.write_char('-');
+    }
+}
+
+/// Send a packet and retransmit it until gdb acknowledges it.
+fn send_packet(packet: &Packet) {
+    let console = console::console();
+    let checksum = packet
+        .as_bytes()
+        .iter()
+        .fold(0u8, |acc, c| acc.wrapping_add(*c));
+
+    loop {
+        console.write_char('$');
+        for c in packet.as_bytes() {
+            console.write_char(*c as char);
+        }
+        console.write_char('#');
+        console.write_char(hex_digit(checksum >> 4) as char);
+        console.write_char(hex_digit(checksum & 0xf) as char);
+        console.flush();
+
+        if console.read_char() as u8 == b'+' {
+            return;
+        }
+    }
+}
+
+fn read_registers(regs: &GdbRegisters, reply: &mut Packet) {
+    for x in regs.x {
+        reply.push_hex_le(x, 8);
+    }
+    reply.push_hex_le(regs.sp, 8);
+    reply.push_hex_le(regs.pc, 8);
+    reply.push_hex_le(u64::from(regs.cpsr), 4);
+}
+
+fn write_registers(regs: &mut GdbRegisters, data: &[u8], reply: &mut Packet) {
+    let mut values = [0u64; 34];
+
+    for (i, value) in values.iter_mut().enumerate() {
+        let num_bytes = if i == 33 { 4 } else { 8 };
+        match data.get(i * 16..).and_then(|s| parse_hex_le(s, num_bytes)) {
+            None => return reply.push_str(ERROR_INVALID),
+            Some(v) => *value = v,
+        }
+    }
+
+    regs.x.copy_from_slice(&values[..31]);
+    regs.sp = values[31];
+    regs.pc = values[32];
+    regs.cpsr = values[33] as u32;
+
+    reply.push_str("OK");
+}
+
+fn read_memory(args: &[u8], reply: &mut Packet) {
+    let (addr, len) = match parse_addr_len(args) {
+        Some((addr, len, _)) if len <= PACKET_BUF_SIZE / 2 => (addr, len),
+        _ => return reply.push_str(ERROR_INVALID),
+    };
+
+    if !range_accessible(addr, len, false) {
+        return reply.push_str(ERROR_FAULT);
+    }
+
+    for i in 0..len {
+        let byte = unsafe { core::ptr::read_volatile((addr + i) as *const u8) };
+        reply.push_hex_u8(byte);
+    }
+}
+
+fn write_memory(args: &[u8], reply: &mut Packet) {
+    let (addr, len, data) = match parse_addr_len(args) {
+        Some((addr, len, data)) if data.len() == len * 2 => (addr, len, data),
+        _ => return reply.push_str(ERROR_INVALID),
+    };
+
+    if !range_accessible(addr, len, true) {
+        return reply.push_str(ERROR_FAULT);
+    }
+
+    for i in 0..len {
+        let byte = match parse_hex_u8(&data[i * 2..]) {
+            None => return reply.push_str(ERROR_INVALID),
+            Some(byte) => byte,
+        };
+
+        unsafe { core::ptr::write_volatile((addr + i) as *mut u8, byte) };
+    }
+
+    reply.push_str("OK");
+}
+
+/// Write an instruction and make it visible to instruction fetches.
+fn patch_instruction(writable: *mut u32, instruction: u32) {
+    unsafe { core::ptr::write_volatile(writable, instruction) };
+
+    // The instruction cache does not snoop the data cache. Push the new instruction out to memory
+    // and drop stale copies of the old one.
+    cpu::cache::clean_invalidate_dcache_range(Address::new(writable as usize), 4);
+    cpu::cache::invalidate_icache();
+}
+
+fn insert_breakpoint(addr: usize) -> Result<(), &'static str> {
+    let writable = CODE_ALIAS
+        .read(|alias| alias.and_then(|alias| alias.writable(addr)))
+        .ok_or("Not an instruction of the kernel code")?;
+
+    BREAKPOINTS.lock(|breakpoints| {
+        if breakpoints.iter().flatten().any(|bp| bp.addr == addr) {
+            return Ok(());
+        }
+
+        let slot = breakpoints
+            .iter_mut()
+            .find(|bp| bp.is_none())
+            .ok_or("Too many breakpoints")?;
+
+        let original = unsafe { core::ptr::read_volatile(writable) };
+        patch_instruction(writable, BREAKPOINT_INSTRUCTION);
+        *slot = Some(Breakpoint { addr, original });
+
+        Ok(())
+    })
+}
+
+fn remove_breakpoint(addr: usize) -> Result<(), &'static str> {
+    let writable = CODE_ALIAS
+        .read(|alias| alias.and_then(|alias| alias.writable(addr)))
+        .ok_or("Not an instruction of the kernel code")?;
+
+    BREAKPOINTS.lock(|breakpoints| {
+        let slot = breakpoints
+            .iter_mut()
+            .find(|bp| bp.is_some_and(|bp| bp.addr == addr))
+            .ok_or("No breakpoint at this address")?;
+
+        if let Some(bp) = slot.take() {
+            patch_instruction(writable, bp.original);
+        }
+
+        Ok(())
+    })
+}
+
+/// Handle `Z0,addr,kind` and `z0,addr,kind`. Other breakpoint types are not supported.
+fn change_breakpoint(args: &[u8], insert: bool, reply: &mut Packet) {
+    let addr = match args.strip_prefix(b"0,").and_then(parse_addr_len) {
+        None => return,
+        Some((addr, _, _)) => addr,
+    };
+
+    let result = if insert {
+        insert_breakpoint(addr)
+    } else {
+        remove_breakpoint(addr)
+    };
+
+    match result {
+        Ok(()) => reply.push_str("OK"),
+        Err(_) => reply.push_str(ERROR_FAULT),
+    }
+}
+
+fn handle_packet(regs: &mut GdbRegisters, packet: &[u8], reply: &mut Packet) -> Action {
+    let (command, args) = match packet.split_first() {
+        None => return Action::Stay,
+        Some((command, args)) => (*command, args),
+    };
+
+    match command {
+        b'?' => {
+            reply.push(b'S');
+            reply.push_hex_u8(STOP_SIGNAL);
+        }
+        b'g' => read_registers(regs, reply),
+        b'G' => write_registers(regs, args, reply),
+        b'm' => read_memory(args, reply),
+        b'M' => write_memory(args, reply),
+        b'Z' => change_breakpoint(args, true, reply),
+        b'z' => change_breakpoint(args, false, reply),
+        b'c' => {
+            if let Some(addr) = parse_hex(args) {
+                regs.pc = addr as u64;
+            }
+            return Action::Resume;
+        }
+        b's' => {
+            if let Some(addr) = parse_hex(args) {
+                regs.pc = addr as u64;
+            }
+            return Action::Step;
+        }
+        b'D' => {
+            reply.push_str("OK");
+            send_packet(reply);
+            ATTACHED.store(false, Ordering::Relaxed);
+            return Action::Resume;
+        }
+        b'k' => bsp::reset::reboot(),
+        b'q' if args.starts_with(b"Supported") => {
+            reply.push_str("PacketSize=");
+            reply.push_hex_number(PACKET_BUF_SIZE);
+        }
+        _ => (),
+    }
+
+    Action::Stay
+}
+
+/// Map the writable alias of the kernel code, through which breakpoints are inserted.
+///
+/// Must be called during kernel init, the translation tables are read-only afterwards. Without the
+/// alias, `Z0` is answered with an error.
+///
+/// # Safety
+///
+/// - See [`bsp::memory::mmu::kernel_map_code_alias()`].
+pub unsafe fn init() -> Result<(), &'static str> {
+    let alias_start = bsp::memory::mmu::kernel_map_code_alias()?;
+
+    CODE_ALIAS.write(|alias| {
+        *alias = Some(CodeAlias {
+            code: bsp::memory::mmu::virt_code_region(),
+            alias_start,
+        })
+    });
+
+    Ok(())
+}
+
+/// Returns true if the debugger inserted a breakpoint at `addr`.
+///
+/// A `brk` inserted by the debugger is removed by it again, while one compiled into the code has to
+/// be stepped over when resuming.
+pub fn is_inserted_breakpoint(addr: usize) -> bool {
+    BREAKPOINTS.lock(|breakpoints| breakpoints.iter().flatten().any(|bp| bp.addr == addr))
+}
+
+/// Set up the return into the interrupted code to execute a single instruction.
+fn start_step(regs: &mut GdbRegisters) {
+    STEP_SAVED_DAIF.store(regs.cpsr & SPSR_DAIF, Ordering::Relaxed);
+    STEPPING.store(true, Ordering::Relaxed);
+
+    // Debug exceptions must be unmasked to take the step exception. IRQs stay masked, otherwise a
+    // pending IRQ would be stepped into instead of the next instruction.
+    regs.cpsr = (regs.cpsr & !SPSR_D) | SPSR_I | SPSR_SS;
+    cpu::set_single_step(true);
+}
+
+/// Undo [`start_step()`] after the step exception was taken.
+fn finish_step(regs: &mut GdbRegisters) {
+    if !STEPPING.swap(false, Ordering::Relaxed) {
+        return;
+    }
+
+    cpu::set_single_step(false);
+    regs.cpsr = (regs.cpsr & !(SPSR_DAIF | SPSR_SS)) | STEP_SAVED_DAIF.load(Ordering::Relaxed);
+}
+
+/// Hand control to the debugger until it continues, single-steps or detaches.
+///
+/// Called from the exception handler with the registers of the interrupted code. Changes made by
+/// the debugger are written back into `regs`.
+pub fn handle_exception(regs: &mut GdbRegisters) {
+    let mut packet = Packet::new();
+    let mut reply = Packet::new();
+
+    // The heartbeat does not run while the target is stopped. Stop the watchdog for the rest of the
+    // run instead of having it reset the board in the middle of a debug session.
+    bsp::watchdog::disable();
+    finish_step(regs);
+
+    // A debugger that continued the target waits for the stop reply. A newly attaching one asks
+    // for it with `?` instead.
+    if ATTACHED.load(Ordering::Relaxed) {
+        reply.push(b'S');
+        reply.push_hex_u8(STOP_SIGNAL);
+        send_packet(&reply);
+    }
+
+    loop {
+        receive_packet(&mut packet);
+        ATTACHED.store(true, Ordering::Relaxed);
+        reply.clear();
+
+        match handle_packet(regs, packet.as_bytes(), &mut reply) {
+            Action::Resume => return,
+            Action::Step => return start_step(regs),
+            Action::Stay => send_packet(&reply),
+        }
+    }
+}
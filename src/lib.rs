@@ -18,12 +18,20 @@ pub mod console;
 pub mod cpu;
 pub mod driver;
 pub mod exception;
+pub mod fault;
+#[cfg(feature = "gdbstub")]
+pub mod gdbstub;
 pub mod memory;
 pub mod panic;
 pub mod print;
+pub mod qemu;
+pub mod selftest;
 pub mod state;
 pub mod synchronization;
 pub mod time;
+pub mod trace;
+pub mod util;
+pub mod version;
 
 /// Convert a size into human readable format.
 pub const fn size_human_readable_ceil(size: usize) -> (usize, &'static str) {
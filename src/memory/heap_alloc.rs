@@ -1,51 +1,348 @@
 use crate::{
-    bsp::memory::mmu::virt_heap_region,
-    info,
-    memory::{Address, Virtual},
+    bsp::memory::mmu::{
+        kernel_page_attributes, virt_heap_region, virt_heap_reserve_region, MSKernel,
+    },
+    info, is_aligned,
+    memory::{mmu::AccessPermissions, Address, Virtual},
     size_human_readable_ceil, synchronization,
     synchronization::IRQSafeNullLock,
-    warn,
+    trace, warn,
 };
 use alloc::alloc::{GlobalAlloc, Layout};
-use core::sync::atomic::{AtomicBool, Ordering};
-use linked_list_allocator::Heap as LinkedListHeap;
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
 use synchronization::Mutex;
 
+// Built in both configurations, so that the self-tests cover it even when it is not the heap.
+pub mod buddy;
+#[cfg(not(feature = "buddy_allocator"))]
+mod linked_list;
+
+/// The heap implementation, chosen at compile time.
+#[cfg(not(feature = "buddy_allocator"))]
+type KernelHeap = linked_list::LinkedListHeap;
+
+/// The heap implementation, chosen at compile time.
+#[cfg(feature = "buddy_allocator")]
+type KernelHeap = buddy::BuddyHeap;
+
 #[global_allocator]
 pub static KERNEL_HEAP_ALLOCATOR: HeapAllocator = HeapAllocator::new();
 
+/// Number of pages at the end of the heap region that are reserved for the page pool.
+///
+/// Limited to the 64 bits of the pool's bitmap. The 32 pages take 2 MiB of the 16 MiB heap region
+/// with the 64 KiB granule, 128 KiB with the 4 KiB one. Page-aligned allocations that do not fit
+/// fall back to the heap.
+const PAGE_POOL_NUM_PAGES: usize = 32;
+
+/// Pattern written over freed memory with the `heap_poison` feature.
+#[cfg(feature = "heap_poison")]
+const HEAP_POISON_BYTE: u8 = 0xA5;
+
+/// Allocations with at least this alignment are served from the page pool.
+///
+/// The linked-list heap satisfies large alignments by skipping over memory, which wastes up to the
+/// alignment for every such allocation and fragments the heap when mixed with small allocations.
+const PAGE_POOL_MIN_ALIGN: usize = MSKernel::SIZE;
+
+/// Usage of a heap.
+pub struct HeapStats {
+    /// Bytes handed out to allocations.
+    pub used: usize,
+    /// Bytes that are still available.
+    pub free: usize,
+    /// Bytes lost to rounding up the size of allocations, if the heap does that.
+    pub internal_fragmentation: Option<usize>,
+}
+
+/// A heap implementation behind [`HeapAllocator`].
+///
+/// Implementations only manage memory. Locking, statistics and the page pool are common to all
+/// of them.
+pub trait KernelAllocator {
+    /// Hand the memory to the heap.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called once, before any allocation.
+    /// - The memory must be mapped read-write and not be used by anything else.
+    unsafe fn init(&mut self, start: *mut u8, size: usize);
+
+    /// Allocate memory for the layout, or return `None` if there is no fitting free memory.
+    fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// Free memory.
+    ///
+    /// # Safety
+    ///
+    /// - `ptr` must have been returned by `alloc()` with the same layout and not be freed yet.
+    unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout);
+
+    /// Return the current usage.
+    fn stats(&self) -> HeapStats;
+}
+
 /// Query the BSP for the heap region and initialize the kernel's heap allocator with it.
-pub fn kernel_init_heap_allocator() {
+///
+/// The region must be large enough for the page pool plus some heap and must be mapped read-write.
+/// Calling this again after a successful initialization is a no-op.
+pub fn kernel_init_heap_allocator() -> Result<(), &'static str> {
+    // Acquire pairs with the Release store below, so that a core which sees the flag set also
+    // sees the initialized allocator.
     static INIT_DONE: AtomicBool = AtomicBool::new(false);
-    if INIT_DONE.load(Ordering::Relaxed) {
+    if INIT_DONE.load(Ordering::Acquire) {
         warn!("Already initialized");
-        return;
+        return Ok(());
     }
 
     let region = virt_heap_region();
+    let reserve = virt_heap_reserve_region();
+
+    // Carve the page pool from the end of the heap region.
+    let pool_size = PAGE_POOL_NUM_PAGES * MSKernel::SIZE;
+    if region.size() <= pool_size {
+        return Err("Heap region is too small, check the linker script");
+    }
+
+    // The reserve is only handed to the heap on exhaustion, when a failure is much harder to
+    // report, so check it up front as well.
+    for page in [
+        region.start_page,
+        region.end_inclusive_page_addr(),
+        reserve.start_page,
+        reserve.end_inclusive_page_addr(),
+    ] {
+        let attributes = kernel_page_attributes(page).map_err(|_| "Heap region is not mapped")?;
+
+        if attributes.acc_perms != AccessPermissions::ReadWrite {
+            return Err("Heap region is not mapped read-write");
+        }
+    }
+
+    let heap_start = region.start_page.address().as_usize();
+    let heap_size = region.size() - pool_size;
+    let reserve_start = reserve.start_page.address().as_usize();
 
     KERNEL_HEAP_ALLOCATOR.inner.lock(|inner| unsafe {
         inner.init(
-            region.start_page.address().as_usize() as *mut u8,
-            region.size(),
+            heap_start as *mut u8,
+            heap_size,
+            reserve_start as *mut u8,
+            reserve.size(),
         )
     });
 
-    INIT_DONE.store(true, Ordering::Relaxed);
+    KERNEL_HEAP_ALLOCATOR
+        .pages
+        .lock(|pages| pages.init(heap_start + heap_size, PAGE_POOL_NUM_PAGES));
+
+    INIT_DONE.store(true, Ordering::Release);
+
+    Ok(())
+}
+
+/// A page-granular allocator for allocations with large alignment.
+///
+/// The pool lives in the identity mapped heap region, so the returned pages can be handed to DMA
+/// engines as-is.
+///
+/// It is carved from the end of the heap region rather than served by a frame allocator, since the
+/// kernel has none: DRAM outside the kernel binary is not managed, and the translation tables can
+/// not map new pages after init. The pool is therefore fixed to [`PAGE_POOL_NUM_PAGES`] at boot and
+/// does not grow.
+struct PagePool {
+    start: usize,
+    num_pages: usize,
+    /// Bit `n` is set if page `n` is allocated.
+    used: u64,
+}
+
+impl PagePool {
+    const fn empty() -> Self {
+        Self {
+            start: 0,
+            num_pages: 0,
+            used: 0,
+        }
+    }
+
+    fn init(&mut self, start: usize, num_pages: usize) {
+        assert!(num_pages <= u64::BITS as usize);
+
+        self.start = start;
+        self.num_pages = num_pages;
+        self.used = 0;
+    }
+
+    /// Return the bitmap mask for `count` pages starting at page `index`.
+    fn mask(index: usize, count: usize) -> u64 {
+        let bits = if count >= u64::BITS as usize {
+            u64::MAX
+        } else {
+            (1 << count) - 1
+        };
+
+        bits << index
+    }
+
+    /// Return true if the pointer was allocated from this pool.
+    fn contains(&self, ptr: *mut u8) -> bool {
+        let addr = ptr as usize;
+
+        addr >= self.start && addr < self.start + self.num_pages * MSKernel::SIZE
+    }
+
+    /// Allocate a contiguous run of pages that satisfies the layout, first fit.
+    fn alloc(&mut self, layout: Layout) -> Option<*mut u8> {
+        let count = layout.size().div_ceil(MSKernel::SIZE).max(1);
+        if count > self.num_pages {
+            return None;
+        }
+
+        for index in 0..=(self.num_pages - count) {
+            let addr = self.start + index * MSKernel::SIZE;
+            let mask = Self::mask(index, count);
+
+            if is_aligned(addr, layout.align()) && (self.used & mask) == 0 {
+                self.used |= mask;
+                return Some(addr as *mut u8);
+            }
+        }
+
+        None
+    }
+
+    /// Free pages previously allocated with the same layout.
+    fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let count = layout.size().div_ceil(MSKernel::SIZE).max(1);
+        let index = (ptr as usize - self.start) / MSKernel::SIZE;
+
+        self.used &= !Self::mask(index, count);
+    }
+
+    fn used_pages(&self) -> usize {
+        self.used.count_ones() as usize
+    }
+}
+
+/// A heap that falls back to a second heap in a reserve once it is exhausted.
+///
+/// The reserve is only handed its memory on first use, so that it stays untouched as long as the
+/// heap suffices.
+///
+/// The reserve is a fixed region of the linker script, not pages taken from a frame allocator on
+/// exhaustion. The kernel has no frame allocator that manages DRAM outside its binary, and the
+/// translation tables are read-only after init, so memory found at exhaustion time could not be
+/// mapped anymore. The region is mapped with the kernel binary instead, and its size in
+/// `kernel.ld` caps the extension. Once activated, the reserve stays part of the heap.
+struct ReservedHeap {
+    heap: KernelHeap,
+    reserve: KernelHeap,
+    reserve_start: usize,
+    reserve_size: usize,
+    /// Set once the reserve memory has been handed to `reserve`.
+    reserve_active: bool,
+}
+
+impl ReservedHeap {
+    const fn empty() -> Self {
+        Self {
+            heap: KernelHeap::empty(),
+            reserve: KernelHeap::empty(),
+            reserve_start: 0,
+            reserve_size: 0,
+            reserve_active: false,
+        }
+    }
+
+    /// Hand the heap memory to the heap and remember the reserve memory for later.
+    ///
+    /// # Safety
+    ///
+    /// - Both memory ranges must be mapped read-write, must not overlap and must not be used by
+    ///   anything else.
+    unsafe fn init(
+        &mut self,
+        start: *mut u8,
+        size: usize,
+        reserve_start: *mut u8,
+        reserve_size: usize,
+    ) {
+        self.heap.init(start, size);
+        self.reserve_start = reserve_start as usize;
+        self.reserve_size = reserve_size;
+        self.reserve_active = false;
+    }
+
+    /// Allocate from the heap, or from the reserve if the heap has no fitting free memory.
+    fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        if let Some(allocation) = self.heap.alloc(layout) {
+            return Some(allocation);
+        }
+
+        if !self.reserve_active {
+            unsafe {
+                self.reserve
+                    .init(self.reserve_start as *mut u8, self.reserve_size)
+            };
+            self.reserve_active = true;
+        }
+
+        self.reserve.alloc(layout)
+    }
+
+    /// Free memory from either heap.
+    ///
+    /// # Safety
+    ///
+    /// - `ptr` must have been returned by `alloc()` with the same layout and not be freed yet.
+    unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let addr = ptr.as_ptr() as usize;
+
+        if addr >= self.reserve_start && addr < self.reserve_start + self.reserve_size {
+            self.reserve.dealloc(ptr, layout);
+        } else {
+            self.heap.dealloc(ptr, layout);
+        }
+    }
+
+    /// Return the usage of the heap, not including the reserve.
+    fn stats(&self) -> HeapStats {
+        self.heap.stats()
+    }
+
+    /// Return the usage of the reserve, or `None` if it was not needed so far.
+    fn reserve_stats(&self) -> Option<HeapStats> {
+        self.reserve_active.then(|| self.reserve.stats())
+    }
 }
 
 /// A heap allocator that can be lazyily initialized.
+///
+/// Allocations with an alignment of at least a page are served from a small page pool, everything
+/// else from the heap implementation selected with the `buddy_allocator` feature. Once the heap is
+/// exhausted, allocations fall back to a second heap in the reserve region after the heap guard.
 pub struct HeapAllocator {
-    inner: IRQSafeNullLock<LinkedListHeap>,
+    inner: IRQSafeNullLock<ReservedHeap>,
+    pages: IRQSafeNullLock<PagePool>,
+    num_allocs: AtomicUsize,
+    num_frees: AtomicUsize,
+    /// Highest heap usage so far, in bytes.
+    peak_used: AtomicUsize,
 }
 
+/// Print an allocation or free. Only visible with the trace log level, since it is far too verbose
+/// for anything that allocates regularly.
 #[inline(always)]
 fn debug_print_alloc_dealloc(operation: &'static str, ptr: *mut u8, layout: Layout) {
     let size = layout.size();
     let (size_h, size_unit) = size_human_readable_ceil(size);
     let addr = Address::<Virtual>::new(ptr as usize);
 
-    info!(
+    trace!(
         "Kernel Heap: {}\n
         Size:     {:#x} ({} {})\n
         Start:    {}\n
@@ -60,8 +357,17 @@ fn debug_print_alloc_dealloc(operation: &'static str, ptr: *mut u8, layout: Layo
     );
 }
 
+/// Only reached after `HeapAllocator::alloc()` failed even with the reserve, and only
+/// for infallible allocations. Fallible APIs like `Vec::try_reserve()` get an error instead.
 #[alloc_error_handler]
 fn alloc_error_handler(layout: Layout) -> ! {
+    let HeapStats { used, free, .. } = KERNEL_HEAP_ALLOCATOR.inner.lock(|inner| inner.stats());
+    warn!(
+        "Out of memory: {:?} failed with {} Byte free and {} Byte used",
+        layout, free, used
+    );
+    KERNEL_HEAP_ALLOCATOR.print_usage();
+
     panic!("Allocation error: {:?}", layout)
 }
 
@@ -69,15 +375,102 @@ impl HeapAllocator {
     /// Create an instance.
     pub const fn new() -> Self {
         Self {
-            inner: IRQSafeNullLock::new(LinkedListHeap::empty()),
+            inner: IRQSafeNullLock::new(ReservedHeap::empty()),
+            pages: IRQSafeNullLock::new(PagePool::empty()),
+            num_allocs: AtomicUsize::new(0),
+            num_frees: AtomicUsize::new(0),
+            peak_used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Allocate from the heap implementation and record the new usage. Warns once the heap is
+    /// exhausted and the reserve is handed out.
+    ///
+    /// The reserve region is mapped together with the kernel binary, since the translation tables
+    /// can not be changed anymore after kernel init.
+    fn heap_alloc(&self, layout: Layout) -> Option<*mut u8> {
+        let (allocation, activated) = self.inner.lock(|inner| {
+            let was_active = inner.reserve_active;
+            let allocation = inner.alloc(layout);
+            self.peak_used
+                .fetch_max(inner.stats().used, Ordering::Relaxed);
+
+            (allocation, !was_active && inner.reserve_active)
+        });
+
+        if activated {
+            let (size_h, size_unit) = size_human_readable_ceil(virt_heap_reserve_region().size());
+            warn!(
+                "!!! Kernel heap exhausted by {:?}. Falling back to the {} {} reserve.",
+                layout, size_h, size_unit
+            );
+            warn!("!!! The heap region is too small, check the linker script.");
         }
+
+        allocation.map(NonNull::as_ptr)
+    }
+
+    /// Allocate memory for the layout, or return `None` if there is none.
+    ///
+    /// Unlike infallible allocations, e.g. `Box::new()`, a failure never reaches the allocation
+    /// error handler, so the caller can recover from it. Zero-sized layouts are rejected. Free the
+    /// memory with `GlobalAlloc::dealloc()` and the same layout.
+    pub fn try_alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        if layout.size() == 0 {
+            return None;
+        }
+
+        NonNull::new(unsafe { GlobalAlloc::alloc(self, layout) })
+    }
+
+    /// Return the highest number of bytes that were in use on the heap at any time.
+    ///
+    /// Allocations from the page pool and the reserve are not included.
+    pub fn peak_usage(&self) -> usize {
+        self.peak_used.load(Ordering::Relaxed)
+    }
+
+    /// Return true if the heap was exhausted and allocations fall back to the reserve.
+    pub fn emergency_extended(&self) -> bool {
+        self.inner.lock(|inner| inner.reserve_active)
+    }
+
+    /// Return the number of successful allocations since boot.
+    pub fn total_allocations(&self) -> usize {
+        self.num_allocs.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of allocations that have not been freed yet.
+    pub fn live_allocations(&self) -> usize {
+        // Load frees first, so that a concurrent alloc/free pair can not make the result negative.
+        let frees = self.num_frees.load(Ordering::Relaxed);
+        let allocs = self.num_allocs.load(Ordering::Relaxed);
+
+        allocs.saturating_sub(frees)
+    }
+
+    /// Panic if the number of live allocations differs from `baseline`.
+    ///
+    /// Take the baseline with `live_allocations()` before a scope and check it afterwards to
+    /// confirm that everything allocated in the scope was freed.
+    pub fn assert_no_leaks(&self, baseline: usize) {
+        let live = self.live_allocations();
+
+        assert!(
+            live == baseline,
+            "Heap leak detected: {} live allocations, expected {}",
+            live,
+            baseline
+        );
     }
 
     /// Print the current heap usage.
     pub fn print_usage(&self) {
-        let (used, free) = KERNEL_HEAP_ALLOCATOR
-            .inner
-            .lock(|inner| (inner.used(), inner.free()));
+        let HeapStats {
+            used,
+            free,
+            internal_fragmentation,
+        } = KERNEL_HEAP_ALLOCATOR.inner.lock(|inner| inner.stats());
 
         if used >= 1024 {
             let (used_h, used_unit) = size_human_readable_ceil(used);
@@ -92,20 +485,58 @@ impl HeapAllocator {
         } else {
             info!("      Free: {} Byte", free);
         }
+
+        let peak = self.peak_usage();
+        if peak >= 1024 {
+            let (peak_h, peak_unit) = size_human_readable_ceil(peak);
+            info!("      Peak: {} Byte ({} {})", peak, peak_h, peak_unit);
+        } else {
+            info!("      Peak: {} Byte", peak);
+        }
+
+        if let Some(fragmentation) = internal_fragmentation {
+            info!("      Lost to rounding: {} Byte", fragmentation);
+        }
+
+        let (pool_used, pool_total) = self
+            .pages
+            .lock(|pages| (pages.used_pages(), pages.num_pages));
+        info!(
+            "      Page pool: {} of {} pages used",
+            pool_used, pool_total
+        );
+
+        if let Some(HeapStats { used, free, .. }) = self.inner.lock(|inner| inner.reserve_stats()) {
+            info!("      Reserve: {} Byte used, {} Byte free", used, free);
+        }
+
+        info!(
+            "      Allocations: {}, Frees: {}, Live: {}",
+            self.total_allocations(),
+            self.num_frees.load(Ordering::Relaxed),
+            self.live_allocations()
+        );
     }
 }
 
 unsafe impl GlobalAlloc for HeapAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let result = KERNEL_HEAP_ALLOCATOR
-            .inner
-            .lock(|inner| inner.allocate_first_fit(layout).ok());
+        let mut result = None;
+
+        if layout.align() >= PAGE_POOL_MIN_ALIGN {
+            result = self.pages.lock(|pages| pages.alloc(layout));
+        }
+
+        // Fall back to the heap if the pool is exhausted.
+        if result.is_none() {
+            result = self.heap_alloc(layout);
+        }
 
         match result {
             None => core::ptr::null_mut(),
-            Some(allocation) => {
-                let ptr = allocation.as_ptr();
-
+            Some(ptr) => {
+                self.num_allocs.fetch_add(1, Ordering::Relaxed);
+                trace::event(trace::id::ALLOC, layout.size() as u64);
                 debug_print_alloc_dealloc("Allocation", ptr, layout);
 
                 ptr
@@ -114,10 +545,172 @@ unsafe impl GlobalAlloc for HeapAllocator {
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        KERNEL_HEAP_ALLOCATOR
-            .inner
-            .lock(|inner| inner.deallocate(core::ptr::NonNull::new_unchecked(ptr), layout));
+        #[cfg(feature = "heap_poison")]
+        core::ptr::write_bytes(ptr, HEAP_POISON_BYTE, layout.size());
+
+        let from_pool = self.pages.lock(|pages| {
+            if !pages.contains(ptr) {
+                return false;
+            }
 
+            pages.dealloc(ptr, layout);
+            true
+        });
+
+        if !from_pool {
+            let ptr = NonNull::new_unchecked(ptr);
+            self.inner.lock(|inner| inner.dealloc(ptr, layout));
+        }
+
+        self.num_frees.fetch_add(1, Ordering::Relaxed);
+        trace::event(trace::id::DEALLOC, layout.size() as u64);
         debug_print_alloc_dealloc("Free", ptr, layout);
     }
 }
+
+pub(crate) mod selftests {
+    use super::{ReservedHeap, KERNEL_HEAP_ALLOCATOR};
+    use crate::{bsp, memory, selftest::SelfTest};
+    use alloc::{
+        alloc::{GlobalAlloc, Layout},
+        boxed::Box,
+        vec::Vec,
+    };
+
+    pub(crate) static TESTS: &[SelfTest] = &[
+        SelfTest {
+            name: "heap alloc/free balance",
+            run: test_heap_balance,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "heap peak usage",
+            run: test_heap_peak,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "fallible heap allocation",
+            run: test_heap_try_alloc,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "exhausted heap falls back to the reserve",
+            run: test_heap_reserve,
+            destructive: false,
+            needs_irqs: false,
+        },
+    ];
+
+    fn test_heap_balance() -> Result<(), &'static str> {
+        let baseline = KERNEL_HEAP_ALLOCATOR.live_allocations();
+
+        {
+            let boxed = Box::new(0xDEAD_BEEFu32);
+            let mut numbers: Vec<u64> = (0..64).collect();
+            numbers.push(*boxed as u64);
+
+            if numbers.iter().sum::<u64>() != (0..64).sum::<u64>() + 0xDEAD_BEEF {
+                return Err("Heap data corrupted");
+            }
+        }
+
+        if KERNEL_HEAP_ALLOCATOR.live_allocations() != baseline {
+            return Err("Allocations and frees do not balance");
+        }
+
+        Ok(())
+    }
+
+    fn test_heap_peak() -> Result<(), &'static str> {
+        const SIZE: usize = 4096;
+
+        let numbers: Vec<u8> = Vec::with_capacity(SIZE);
+        let peak = KERNEL_HEAP_ALLOCATOR.peak_usage();
+        drop(numbers);
+
+        if peak < SIZE {
+            return Err("Peak usage is below a live allocation");
+        }
+
+        if KERNEL_HEAP_ALLOCATOR.peak_usage() != peak {
+            return Err("Free changed the peak usage");
+        }
+
+        Ok(())
+    }
+
+    fn test_heap_try_alloc() -> Result<(), &'static str> {
+        let layout = Layout::from_size_align(256, 16).map_err(|_| "Invalid layout")?;
+
+        let ptr = KERNEL_HEAP_ALLOCATOR
+            .try_alloc(layout)
+            .ok_or("Small allocation failed")?;
+        unsafe { KERNEL_HEAP_ALLOCATOR.dealloc(ptr.as_ptr(), layout) };
+
+        let empty = Layout::from_size_align(0, 1).map_err(|_| "Invalid layout")?;
+        if KERNEL_HEAP_ALLOCATOR.try_alloc(empty).is_some() {
+            return Err("Zero-sized allocation succeeded");
+        }
+
+        Ok(())
+    }
+
+    /// Exhaust a private heap of one page, with one page of reserve, so that the kernel heap keeps
+    /// its reserve untouched.
+    fn test_heap_reserve() -> Result<(), &'static str> {
+        let page_size = bsp::memory::mmu::MSKernel::SIZE;
+        let memory = memory::alloc_zeroed_pages(2)?;
+        let start = memory.as_ptr();
+        let reserve_start = start as usize + page_size;
+
+        let mut heap = ReservedHeap::empty();
+        unsafe { heap.init(start, page_size, reserve_start as *mut u8, page_size) };
+
+        // Twice the page in chunks, so that the loop below always exhausts the heap.
+        let layout = Layout::from_size_align(page_size / 8, 16).map_err(|_| "Invalid layout")?;
+        let mut chunks = Vec::with_capacity(16);
+
+        let mut result = Err("Heap was not exhausted");
+        while chunks.len() < 16 {
+            let ptr = match heap.alloc(layout) {
+                None => {
+                    result = Err("Allocation failed when the heap ran out");
+                    break;
+                }
+                Some(ptr) => ptr,
+            };
+            chunks.push(ptr);
+
+            if ptr.as_ptr() as usize >= reserve_start {
+                result = if !heap.reserve_active {
+                    Err("Reserve used without being activated")
+                } else if heap.reserve_stats().map_or(0, |stats| stats.used) == 0 {
+                    Err("Reserve usage not accounted")
+                } else {
+                    Ok(())
+                };
+                break;
+            }
+
+            if heap.reserve_active {
+                result = Err("Reserve activated while the heap had memory");
+                break;
+            }
+        }
+
+        for ptr in chunks {
+            unsafe { heap.dealloc(ptr, layout) };
+        }
+
+        if result.is_ok() && (heap.stats().used != 0 || heap.reserve_stats().unwrap().used != 0) {
+            result = Err("Frees did not return memory to the right heap");
+        }
+
+        unsafe { memory::dealloc_pages(memory, 2) };
+
+        result
+    }
+}
@@ -0,0 +1,252 @@
+//! Binary buddy heap.
+//!
+//! The heap is split into blocks of `MIN_BLOCK_SIZE << order` bytes, each aligned to its size
+//! relative to the heap start. An allocation takes the smallest free block that fits and splits
+//! bigger blocks into halves, the buddies, as needed. A freed block is merged with its buddy as
+//! long as that is free as well.
+//!
+//! Sizes are rounded up to a power of two, so that many allocations of the same size do not
+//! fragment the heap. The memory lost to rounding is reported as internal fragmentation.
+
+use super::{HeapStats, KernelAllocator};
+use crate::is_aligned;
+use core::{alloc::Layout, ptr::NonNull};
+
+/// Size of the smallest block. Must be able to hold a `FreeBlock`.
+const MIN_BLOCK_SIZE: usize = 16;
+
+/// Number of block sizes. Blocks of the highest order are bigger than any heap.
+const NUM_ORDERS: usize = 32;
+
+/// Link of a free-list, stored in the free block itself.
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+/// A heap that keeps one free-list per block size.
+pub struct BuddyHeap {
+    start: usize,
+    size: usize,
+    free_lists: [Option<NonNull<FreeBlock>>; NUM_ORDERS],
+    /// Bytes requested by the outstanding allocations.
+    requested: usize,
+    /// Bytes of the blocks handed out for the outstanding allocations.
+    allocated: usize,
+}
+
+// The free-lists only point into the heap memory, which belongs to the allocator.
+unsafe impl Send for BuddyHeap {}
+
+const fn block_size(order: usize) -> usize {
+    MIN_BLOCK_SIZE << order
+}
+
+impl BuddyHeap {
+    /// Create an instance without memory.
+    pub const fn empty() -> Self {
+        Self {
+            start: 0,
+            size: 0,
+            free_lists: [None; NUM_ORDERS],
+            requested: 0,
+            allocated: 0,
+        }
+    }
+
+    /// Return the order of the smallest block that satisfies the layout.
+    fn order_for(layout: Layout) -> Option<usize> {
+        let size = layout
+            .size()
+            .max(layout.align())
+            .max(MIN_BLOCK_SIZE)
+            .checked_next_power_of_two()?;
+        let order = (size / MIN_BLOCK_SIZE).trailing_zeros() as usize;
+
+        (order < NUM_ORDERS).then_some(order)
+    }
+
+    unsafe fn push(&mut self, order: usize, addr: usize) {
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock {
+            next: self.free_lists[order],
+        });
+
+        self.free_lists[order] = NonNull::new(block);
+    }
+
+    fn pop(&mut self, order: usize) -> Option<usize> {
+        let block = self.free_lists[order]?;
+        self.free_lists[order] = unsafe { block.as_ref().next };
+
+        Some(block.as_ptr() as usize)
+    }
+
+    /// Cover the heap from `offset` to `size` with free blocks and make `size` the heap size.
+    ///
+    /// Uses the biggest blocks that are aligned to their size. A tail smaller than the smallest
+    /// block is left unused.
+    unsafe fn add_free_blocks(&mut self, mut offset: usize, size: usize) {
+        self.size = size;
+
+        while offset + MIN_BLOCK_SIZE <= size {
+            let order = (0..NUM_ORDERS)
+                .rev()
+                .find(|order| {
+                    offset % block_size(*order) == 0 && offset + block_size(*order) <= size
+                })
+                .unwrap();
+
+            self.push(order, self.start + offset);
+            offset += block_size(order);
+        }
+    }
+
+    /// Take the block at `addr` out of the free-list of `order`. Returns false if it is not free.
+    fn remove(&mut self, order: usize, addr: usize) -> bool {
+        let mut link = &mut self.free_lists[order];
+
+        while let Some(block) = *link {
+            if block.as_ptr() as usize == addr {
+                *link = unsafe { block.as_ref().next };
+                return true;
+            }
+
+            link = unsafe { &mut (*block.as_ptr()).next };
+        }
+
+        false
+    }
+}
+
+impl KernelAllocator for BuddyHeap {
+    unsafe fn init(&mut self, start: *mut u8, size: usize) {
+        self.start = start as usize;
+        self.add_free_blocks(0, size);
+    }
+
+    fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let order = Self::order_for(layout)?;
+
+        // Blocks are only aligned relative to the heap start.
+        if !is_aligned(self.start, layout.align()) {
+            return None;
+        }
+
+        let mut current = (order..NUM_ORDERS).find(|order| self.free_lists[*order].is_some())?;
+        let addr = self.pop(current)?;
+
+        // Keep the lower half and free the upper one until the block has the right size.
+        while current > order {
+            current -= 1;
+            unsafe { self.push(current, addr + block_size(current)) };
+        }
+
+        self.requested += layout.size();
+        self.allocated += block_size(order);
+
+        NonNull::new(addr as *mut u8)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let mut order = Self::order_for(layout).unwrap();
+        let mut offset = ptr.as_ptr() as usize - self.start;
+
+        self.requested -= layout.size();
+        self.allocated -= block_size(order);
+
+        while order < NUM_ORDERS - 1 {
+            let buddy = offset ^ block_size(order);
+
+            if buddy + block_size(order) > self.size || !self.remove(order, self.start + buddy) {
+                break;
+            }
+
+            offset = offset.min(buddy);
+            order += 1;
+        }
+
+        self.push(order, self.start + offset);
+    }
+
+    fn stats(&self) -> HeapStats {
+        HeapStats {
+            used: self.requested,
+            free: self.size - self.allocated,
+            internal_fragmentation: Some(self.allocated - self.requested),
+        }
+    }
+}
+
+pub(crate) mod selftests {
+    use super::BuddyHeap;
+    use crate::{
+        bsp,
+        memory::{self, heap_alloc::KernelAllocator},
+        selftest::SelfTest,
+    };
+    use alloc::{alloc::Layout, vec::Vec};
+
+    pub(crate) static TESTS: &[SelfTest] = &[SelfTest {
+        name: "buddy blocks split, align and merge",
+        run: test_buddy_split_merge,
+        destructive: false,
+        needs_irqs: false,
+    }];
+
+    fn test_buddy_split_merge() -> Result<(), &'static str> {
+        // Mixed orders, so that blocks of several sizes are split off the same top-order block.
+        const SIZES: [usize; 8] = [16, 48, 512, 24, 4096, 100, 16, 2000];
+
+        let page_size = bsp::memory::mmu::MSKernel::SIZE;
+        let memory = memory::alloc_zeroed_pages(1)?;
+        let start = memory.as_ptr() as usize;
+
+        let mut heap = BuddyHeap::empty();
+        unsafe { heap.init(memory.as_ptr(), page_size) };
+
+        let mut blocks = Vec::with_capacity(SIZES.len());
+        let mut result = Ok(());
+        for size in SIZES {
+            let layout = Layout::from_size_align(size, 8).map_err(|_| "Invalid layout")?;
+            let ptr = match heap.alloc(layout) {
+                None => {
+                    result = Err("Allocation failed");
+                    break;
+                }
+                Some(ptr) => ptr,
+            };
+            blocks.push((ptr, layout));
+
+            // Blocks are aligned to their power of two size relative to the heap start.
+            if (ptr.as_ptr() as usize - start) % size.next_power_of_two() != 0 {
+                result = Err("Block is not aligned to its size");
+                break;
+            }
+        }
+
+        // Free every other block first, so that merges are attempted while buddies are still in
+        // use.
+        let (first, second): (Vec<_>, Vec<_>) = (0..blocks.len()).partition(|i| i % 2 == 0);
+        for i in first.into_iter().chain(second) {
+            let (ptr, layout) = blocks[i];
+            unsafe { heap.dealloc(ptr, layout) };
+        }
+
+        // Only succeeds if all blocks coalesced back into the single top-order block.
+        let whole = Layout::from_size_align(page_size, 8).map_err(|_| "Invalid layout")?;
+        if result.is_ok() {
+            result = match heap.alloc(whole) {
+                None => Err("Free blocks did not merge back into the top-order block"),
+                Some(ptr) if ptr.as_ptr() as usize != start => Err("Top-order block moved"),
+                Some(ptr) => {
+                    unsafe { heap.dealloc(ptr, whole) };
+                    Ok(())
+                }
+            };
+        }
+
+        unsafe { memory::dealloc_pages(memory, 1) };
+
+        result
+    }
+}
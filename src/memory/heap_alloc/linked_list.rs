@@ -0,0 +1,40 @@
+//! First-fit linked-list heap, backed by the `linked_list_allocator` crate.
+
+use super::{HeapStats, KernelAllocator};
+use core::{alloc::Layout, ptr::NonNull};
+use linked_list_allocator::Heap;
+
+/// A heap that keeps its free memory in a list of holes.
+///
+/// Allocations take exactly their size, but finding a hole is O(n) in the number of holes and many
+/// allocations of mixed sizes fragment the heap.
+pub struct LinkedListHeap(Heap);
+
+impl LinkedListHeap {
+    /// Create an instance without memory.
+    pub const fn empty() -> Self {
+        Self(Heap::empty())
+    }
+}
+
+impl KernelAllocator for LinkedListHeap {
+    unsafe fn init(&mut self, start: *mut u8, size: usize) {
+        self.0.init(start, size)
+    }
+
+    fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        self.0.allocate_first_fit(layout).ok()
+    }
+
+    unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        self.0.deallocate(ptr, layout)
+    }
+
+    fn stats(&self) -> HeapStats {
+        HeapStats {
+            used: self.0.used(),
+            free: self.0.free(),
+            internal_fragmentation: None,
+        }
+    }
+}
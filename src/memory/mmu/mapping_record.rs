@@ -2,7 +2,7 @@ use crate::{
     bsp::memory::mmu::MSKernel,
     info,
     memory::{
-        mmu::{AccessPermissions, AttributeFields, MMIODescriptor, MemAttributes, MemoryRegion},
+        mmu::{AttributeFields, MMIODescriptor, MemAttributes, MemoryRegion},
         Address, Physical, Virtual,
     },
     size_human_readable_ceil, synchronization,
@@ -26,6 +26,37 @@ pub fn kernel_add_mapping_record(
     KERNEL_MAPPING_RECORDS.write(|records| records.add(name, virt_region, phys_region, attr))
 }
 
+/// Check that the entry of the mapping that covers exactly `virt_region` can be removed.
+///
+/// Fails if there is no such entry, or if it is shared by several users.
+pub fn kernel_check_mapping_record_removable(
+    virt_region: &MemoryRegion<Virtual>,
+) -> Result<(), &'static str> {
+    KERNEL_MAPPING_RECORDS.read(|records| records.removable_index(virt_region).map(|_| ()))
+}
+
+/// Remove the entry of the mapping that covers exactly `virt_region`.
+///
+/// Fails if there is no such entry, or if it is shared by several users.
+pub fn kernel_remove_mapping_record(
+    virt_region: &MemoryRegion<Virtual>,
+) -> Result<(), &'static str> {
+    KERNEL_MAPPING_RECORDS.write(|records| records.remove(virt_region))
+}
+
+/// Update the attributes of the entry of the mapping that covers exactly `virt_region`, if there
+/// is one.
+pub fn kernel_update_mapping_record_attributes(
+    virt_region: &MemoryRegion<Virtual>,
+    attr: &AttributeFields,
+) {
+    KERNEL_MAPPING_RECORDS.write(|records| {
+        if let Some(index) = records.find_exact(virt_region) {
+            records.inner[index].as_mut().unwrap().attribute_fields = *attr;
+        }
+    })
+}
+
 /// Tries to add device as a user to the existing record.
 pub fn kernel_try_add_device_record_mmio_user(
     new_user: &'static str,
@@ -44,6 +75,14 @@ pub fn kernel_try_add_device_record_mmio_user(
     })
 }
 
+/// Return the name of a recorded mapping that overlaps `virt_region`, and the first page they
+/// share.
+pub fn kernel_find_overlapping_mapping_record(
+    virt_region: &MemoryRegion<Virtual>,
+) -> Option<(&'static str, Address<Virtual>)> {
+    KERNEL_MAPPING_RECORDS.read(|records| records.find_overlapping(virt_region))
+}
+
 /// Human-readable print of all recorded kernel mappings.
 pub fn print_kernel_mappings() {
     KERNEL_MAPPING_RECORDS.read(|mr| mr.print());
@@ -143,6 +182,25 @@ impl MappingRecords {
             })
     }
 
+    fn find_overlapping(
+        &self,
+        virt_region: &MemoryRegion<Virtual>,
+    ) -> Option<(&'static str, Address<Virtual>)> {
+        let region_start = virt_region.start_page.address();
+        let region_end_exclusive = virt_region.end_page_exclusive.address();
+
+        self.inner.iter().flatten().find_map(|x| {
+            let start = x.virt_start_addr;
+            let end_exclusive = start + x.num_pages * MSKernel::SIZE;
+
+            if start >= region_end_exclusive || region_start >= end_exclusive {
+                return None;
+            }
+
+            Some((x.users[0].unwrap_or("Unknown"), start.max(region_start)))
+        })
+    }
+
     pub fn add(
         &mut self,
         name: &'static str,
@@ -164,6 +222,39 @@ impl MappingRecords {
         Ok(())
     }
 
+    fn find_exact(&self, virt_region: &MemoryRegion<Virtual>) -> Option<usize> {
+        self.inner.iter().position(|x| {
+            x.is_some_and(|x| {
+                x.virt_start_addr == virt_region.start_page.address()
+                    && x.num_pages == virt_region.num_pages()
+            })
+        })
+    }
+
+    /// Return the index of the entry that covers exactly `virt_region`, if it can be removed.
+    fn removable_index(&self, virt_region: &MemoryRegion<Virtual>) -> Result<usize, &'static str> {
+        let index = self
+            .find_exact(virt_region)
+            .ok_or("No mapping record for the region")?;
+
+        if self.inner[index].unwrap().users[1].is_some() {
+            return Err("Mapping is shared by several users");
+        }
+
+        Ok(index)
+    }
+
+    pub fn remove(&mut self, virt_region: &MemoryRegion<Virtual>) -> Result<(), &'static str> {
+        let index = self.removable_index(virt_region)?;
+
+        // Keep the used entries at the front, which `sort()` relies on.
+        let size = self.size();
+        self.inner.copy_within(index + 1..size, index);
+        self.inner[size - 1] = None;
+
+        Ok(())
+    }
+
     pub fn print(&self) {
         info!("      -------------------------------------------------------------------------------------------------------------------------------------------");
         info!(
@@ -181,21 +272,9 @@ impl MappingRecords {
 
             let (size, unit) = size_human_readable_ceil(size);
 
-            let attr = match i.attribute_fields.mem_attributes {
-                MemAttributes::CacheableDRAM => "Cache",
-                MemAttributes::Device => "Device",
-            };
-
-            let acc_p = match i.attribute_fields.acc_perms {
-                AccessPermissions::ReadOnly => "RO",
-                AccessPermissions::ReadWrite => "RW",
-            };
-
-            let xn = if i.attribute_fields.executable {
-                "XN"
-            } else {
-                "X"
-            };
+            let attr = i.attribute_fields.mem_attributes.abbreviation();
+            let acc_p = i.attribute_fields.acc_perms.abbreviation();
+            let xn = i.attribute_fields.executable_abbreviation();
 
             info!(
                 "      {}..{} --> {}..{} | {:>3} {} | {:<3} {} {:<2} | {}",
@@ -24,20 +24,27 @@ use core::{
 
 use crate::{
     bsp::memory::mmu::{virt_mmio_remap_region, MSKernel, KERNEL_TRANSLATION_TABLES},
-    is_aligned,
+    cpu, fault, is_aligned,
     memory::{
         mmu::{
-            mapping_record::{kernel_add_mapping_record, kernel_try_add_device_record_mmio_user},
+            mapping_record::{
+                kernel_add_mapping_record, kernel_check_mapping_record_removable,
+                kernel_find_overlapping_mapping_record, kernel_remove_mapping_record,
+                kernel_try_add_device_record_mmio_user, kernel_update_mapping_record_attributes,
+            },
             translation_table::TranslationTable,
         },
         Address, AddressType, Physical, Virtual,
     },
+    state::{init_phase_tracker, InitPhase},
     synchronization::{Mutex, ReadWriteExclusive},
+    util::ArrayString,
     warn,
 };
 
 pub static MMU: Aarch64Mmu = arch_mmu::Aarch64Mmu;
 
+pub type MS4KiB = MemorySize<{ 4 * 1024 }>;
 pub type MS64KiB = MemorySize<{ 64 * 1024 }>;
 pub type MS512MiB = MemorySize<{ 512 * 1024 * 1024 }>;
 
@@ -45,6 +52,10 @@ pub type MS512MiB = MemorySize<{ 512 * 1024 * 1024 }>;
 pub trait MemoryManagementUnit {
     /// Turns on the MMU for the first time and enables data and instruction caching.
     ///
+    /// The table walker must see the tables as they were written. Callers must make sure that no
+    /// stale cache lines shadow them, see [`kernel_map_binary()`]. The implementation issues the
+    /// barrier that completes outstanding writes.
+    ///
     /// # Safety
     ///
     /// - Changes the HW's global state.
@@ -53,22 +64,55 @@ pub trait MemoryManagementUnit {
         phys_tables_base_addr: Address<Physical>,
     ) -> Result<(), MMUEnableError>;
 
+    /// Turns off the MMU together with data and instruction caching.
+    ///
+    /// The data cache is written back completely before, so that memory holds the latest data
+    /// once it is accessed without caching.
+    ///
+    /// # Safety
+    ///
+    /// - Changes the HW's global state.
+    /// - The executing code, its stack and everything it accesses afterwards must be identity
+    ///   mapped.
+    unsafe fn disable_mmu_and_caching(&self) -> Result<(), MMUEnableError>;
+
     /// Returns true if the MMU is enabled, false otherwise.
     fn is_enabled(&self) -> bool;
+
+    /// Invalidate the TLB entries of `num_pages` pages starting at `start` on all cores.
+    ///
+    /// Must be called after the descriptors of the pages were changed.
+    fn invalidate_tlb_range(&self, start: Address<Virtual>, num_pages: usize);
+
+    /// Invalidate all TLB entries of the kernel's translation regime on all cores.
+    ///
+    /// Must be called after changes too big for `invalidate_tlb_range()`.
+    fn invalidate_tlb_all(&self);
+
+    /// Returns true if a read from the virtual address would succeed, without accessing it.
+    fn is_mapped(&self, virt_addr: Address<Virtual>) -> bool;
 }
 
+/// Capacity of the message of [`MMUEnableError::WithContext`].
+pub const MMU_ENABLE_ERROR_MESSAGE_SIZE: usize = 96;
+
 /// MMU enable errors variants.
 #[derive(Debug)]
 pub enum MMUEnableError {
     AlreadyEnabled,
+    AlreadyDisabled,
     Other(&'static str),
+    /// Like `Other`, but with the values that explain the failure, e.g. register contents.
+    WithContext(ArrayString<MMU_ENABLE_ERROR_MESSAGE_SIZE>),
 }
 
 impl Display for MMUEnableError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             MMUEnableError::AlreadyEnabled => write!(f, "MMU is already enabled"),
+            MMUEnableError::AlreadyDisabled => write!(f, "MMU is already disabled"),
             MMUEnableError::Other(x) => write!(f, "{}", x),
+            MMUEnableError::WithContext(x) => write!(f, "{}", x),
         }
     }
 }
@@ -144,7 +188,25 @@ unsafe fn kernel_map_at_unchecked(
     phys_region: &MemoryRegion<Physical>,
     attr: &AttributeFields,
 ) -> Result<(), &'static str> {
-    KERNEL_TRANSLATION_TABLES.write(|tables| tables.map_at(virt_region, phys_region, attr))?;
+    if fault::should_fail(fault::point::MAP_AT) {
+        return Err(fault::INJECTED_FAULT);
+    }
+
+    let result =
+        KERNEL_TRANSLATION_TABLES.write(|tables| tables.map_at(virt_region, phys_region, attr));
+
+    // Two regions that round to the same page are a linker script problem. Name the culprits
+    // instead of only reporting the page as taken.
+    if result.is_err() {
+        if let Some((other, page)) = kernel_find_overlapping_mapping_record(virt_region) {
+            warn!(
+                "Mapping of '{}' overlaps '{}' at page {}",
+                name, other, page
+            );
+        }
+    }
+    result?;
+
     if let Err(x) = kernel_add_mapping_record(name, virt_region, phys_region, attr) {
         warn!("{}", x);
     }
@@ -174,6 +236,128 @@ pub unsafe fn kernel_map_at(
     Ok(())
 }
 
+/// Map `size` bytes at `phys_start` to `virt_start`, widened to whole pages.
+///
+/// Returns `virt_start`, i.e. the virtual address of the first requested byte.
+///
+/// # Safety
+///
+/// - See `kernel_map_at()`.
+pub unsafe fn kernel_map_range(
+    name: &'static str,
+    virt_start: Address<Virtual>,
+    phys_start: Address<Physical>,
+    size: usize,
+    attr: &AttributeFields,
+) -> Result<Address<Virtual>, &'static str> {
+    if size == 0 {
+        return Err("Requested 0 bytes");
+    }
+
+    if virt_start.offset_into_page() != phys_start.offset_into_page() {
+        return Err("Virtual and physical start have different offsets into their pages");
+    }
+
+    let virt_end_exclusive = virt_start
+        .as_usize()
+        .checked_add(size)
+        .ok_or("Virtual range overflows")?;
+    let phys_end_exclusive = phys_start
+        .as_usize()
+        .checked_add(size)
+        .ok_or("Physical range overflows")?;
+
+    let virt_region = MemoryRegion::new(
+        PageAddress::from(virt_start.align_down_page()),
+        PageAddress::from(Address::<Virtual>::new(virt_end_exclusive).align_up_page()),
+    );
+    let phys_region = MemoryRegion::new(
+        PageAddress::from(phys_start.align_down_page()),
+        PageAddress::from(Address::<Physical>::new(phys_end_exclusive).align_up_page()),
+    );
+
+    kernel_map_at(name, &virt_region, &phys_region, attr)?;
+
+    Ok(virt_start)
+}
+
+/// Remove a mapping from the kernel translation tables.
+///
+/// `virt_region` must be exactly a region that was mapped before, e.g. by a driver that failed to
+/// initialize. Mappings shared by several users are refused. MMIO virtual pages are given back to
+/// their allocator if possible.
+///
+/// # Safety
+///
+/// - The region must not be accessed anymore.
+pub unsafe fn kernel_unmap(virt_region: &MemoryRegion<Virtual>) -> Result<(), &'static str> {
+    // The record is only dropped once the pages are really gone, so that a failed unmap leaves
+    // everything as it was.
+    kernel_check_mapping_record_removable(virt_region)?;
+
+    KERNEL_TRANSLATION_TABLES.write(|tables| tables.unmap(virt_region))?;
+    MMU.invalidate_tlb_range(virt_region.start_page.address(), virt_region.num_pages());
+
+    kernel_remove_mapping_record(virt_region)?;
+
+    if virt_mmio_remap_region().overlaps(virt_region) {
+        if let Err(x) =
+            page_alloc::KERNEL_MMIO_VA_ALLOCATOR.lock(|allocator| allocator.free(*virt_region))
+        {
+            warn!("MMIO virtual pages not reclaimed: {}", x);
+        }
+    }
+
+    Ok(())
+}
+
+/// Change the attributes of mapped pages, e.g. to make them read-only.
+///
+/// Only the access permissions and executability can be changed. The memory type is fixed for the
+/// lifetime of a mapping, since changing it in place would need break-before-make.
+///
+/// The mapping record is only updated if `virt_region` covers a whole recorded mapping.
+///
+/// # Safety
+///
+/// - Same as `kernel_map_at()`. Code that relies on the old attributes, e.g. writes to a page made
+///   read-only, faults afterwards.
+pub unsafe fn kernel_change_attributes(
+    virt_region: &MemoryRegion<Virtual>,
+    attr: &AttributeFields,
+) -> Result<(), &'static str> {
+    KERNEL_TRANSLATION_TABLES.write(|tables| tables.change_attributes(virt_region, attr))?;
+    MMU.invalidate_tlb_range(virt_region.start_page.address(), virt_region.num_pages());
+
+    kernel_update_mapping_record_attributes(virt_region, attr);
+
+    Ok(())
+}
+
+/// Make the `.data.ro_after_init` section read-only.
+///
+/// Statics placed there with `#[link_section = ".data.ro_after_init"]` can only be written during
+/// kernel init. Later writes fault.
+///
+/// # Safety
+///
+/// - Must be called at the end of kernel init, after the last write to the section.
+pub unsafe fn mark_ro_after_init() -> Result<(), &'static str> {
+    let virt_region = crate::bsp::memory::mmu::virt_ro_after_init_region();
+    if virt_region.num_pages() == 0 {
+        return Ok(());
+    }
+
+    kernel_change_attributes(
+        &virt_region,
+        &AttributeFields {
+            mem_attributes: MemAttributes::CacheableDRAM,
+            acc_perms: AccessPermissions::ReadOnly,
+            executable: false,
+        },
+    )
+}
+
 /// MMIO remapping in the kernel translation tables.
 ///
 /// Typically used by device drivers.
@@ -219,12 +403,43 @@ pub unsafe fn kernel_map_mmio(
     Ok(virt_addr + offset_into_start_page)
 }
 
+/// Map pages a second time, at virtual pages taken from the MMIO remap region.
+///
+/// Used for an alias with other attributes than the primary mapping, e.g. a writable view of
+/// read-only memory. Returns the virtual address of the first page.
+///
+/// # Safety
+///
+/// - Same as `kernel_map_at_unchecked()`. Writes through the alias bypass the protection of the
+///   primary mapping.
+pub unsafe fn kernel_map_alias(
+    name: &'static str,
+    phys_region: &MemoryRegion<Physical>,
+    attr: &AttributeFields,
+) -> Result<Address<Virtual>, &'static str> {
+    let num_pages = match NonZeroUsize::new(phys_region.num_pages()) {
+        None => return Err("Requested 0 pages"),
+        Some(x) => x,
+    };
+
+    let virt_region =
+        page_alloc::KERNEL_MMIO_VA_ALLOCATOR.lock(|allocator| allocator.alloc(num_pages))?;
+
+    kernel_map_at_unchecked(name, &virt_region, phys_region, attr)?;
+
+    Ok(virt_region.start_page.address())
+}
+
 /// Map the kernel's binary. Returns the translation table's base address.
 ///
+/// Completes [`InitPhase::KernelMapped`].
+///
 /// # Safety
 ///
 /// - See [`bsp::memory::mmu::kernel_map_binary()`].
 pub unsafe fn kernel_map_binary() -> Result<Address<Physical>, &'static str> {
+    init_phase_tracker().assert_ready_for(InitPhase::KernelMapped);
+
     let phys_kernel_tables_base_addr = KERNEL_TRANSLATION_TABLES.write(|tables| {
         tables.init();
         tables.phys_base_address()
@@ -232,18 +447,66 @@ pub unsafe fn kernel_map_binary() -> Result<Address<Physical>, &'static str> {
 
     crate::bsp::memory::mmu::kernel_map_binary()?;
 
+    // The tables were written with data caching off, but the caches may still hold lines of that
+    // memory from before, e.g. from the firmware. Once caching is on, the walker could hit those
+    // instead of the fresh tables. Only invalidate: cleaning would write such stale dirty lines
+    // back over the tables.
+    KERNEL_TRANSLATION_TABLES.read(|tables| {
+        cpu::cache::invalidate_dcache_range(
+            Address::new(tables as *const _ as usize),
+            core::mem::size_of_val(tables),
+        )
+    });
+
+    init_phase_tracker().complete(InitPhase::KernelMapped);
+
     Ok(phys_kernel_tables_base_addr)
 }
 
+/// Check that the installed tables map the kernel binary as intended.
+///
+/// # Panics
+///
+/// - See [`bsp::memory::mmu::verify_kernel_mappings()`].
+pub fn verify_kernel_mappings() {
+    crate::bsp::memory::mmu::verify_kernel_mappings()
+}
+
 /// Enable the MMU and data + instruction caching.
 ///
+/// Completes [`InitPhase::MmuEnabled`].
+///
 /// # Safety
 ///
 /// - Crucial function during kernel init. Changes the the complete memory view of the processor.
 pub unsafe fn enable_mmu_and_caching(
     phys_tables_base_addr: Address<Physical>,
 ) -> Result<(), MMUEnableError> {
-    MMU.enable_mmu_and_caching(phys_tables_base_addr)
+    init_phase_tracker().assert_ready_for(InitPhase::MmuEnabled);
+
+    MMU.enable_mmu_and_caching(phys_tables_base_addr)?;
+
+    init_phase_tracker().complete(InitPhase::MmuEnabled);
+
+    Ok(())
+}
+
+/// Disable the MMU and data + instruction caching.
+///
+/// # Safety
+///
+/// - Changes the the complete memory view of the processor. Everything used afterwards must be
+///   identity mapped.
+pub unsafe fn disable_mmu_and_caching() -> Result<(), MMUEnableError> {
+    MMU.disable_mmu_and_caching()
+}
+
+/// Check if a virtual address can be read without faulting.
+///
+/// The check is done by the hardware translation, so it is exact and never touches the address
+/// itself. Use it before dereferencing addresses from untrusted sources.
+pub fn is_mapped(virt_addr: Address<Virtual>) -> bool {
+    MMU.is_mapped(virt_addr)
 }
 
 /// Human-readable print of all recorded kernel mappings.
@@ -259,6 +522,16 @@ pub enum MemAttributes {
     Device,
 }
 
+impl MemAttributes {
+    /// Short name used when printing mappings.
+    pub fn abbreviation(self) -> &'static str {
+        match self {
+            MemAttributes::CacheableDRAM => "Cache",
+            MemAttributes::Device => "Device",
+        }
+    }
+}
+
 /// Architecture agnostic access permissions.
 #[allow(missing_docs)]
 #[derive(Copy, Clone, Debug, Eq, PartialOrd, PartialEq)]
@@ -267,15 +540,55 @@ pub enum AccessPermissions {
     ReadWrite,
 }
 
+impl AccessPermissions {
+    /// Short name used when printing mappings.
+    pub fn abbreviation(self) -> &'static str {
+        match self {
+            AccessPermissions::ReadOnly => "RO",
+            AccessPermissions::ReadWrite => "RW",
+        }
+    }
+}
+
 /// Collection of memory attributes.
 #[allow(missing_docs)]
-#[derive(Copy, Clone, Debug, Eq, PartialOrd, PartialEq)]
+#[derive(Copy, Clone, Eq, PartialOrd, PartialEq)]
 pub struct AttributeFields {
     pub mem_attributes: MemAttributes,
     pub acc_perms: AccessPermissions,
     pub executable: bool,
 }
 
+impl AttributeFields {
+    /// Short name of the executability used when printing mappings.
+    pub fn executable_abbreviation(&self) -> &'static str {
+        if self.executable {
+            "X"
+        } else {
+            "XN"
+        }
+    }
+}
+
+/// Prints e.g. `[Cache RW XN]`.
+impl Display for AttributeFields {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "[{} {} {}]",
+            self.mem_attributes.abbreviation(),
+            self.acc_perms.abbreviation(),
+            self.executable_abbreviation()
+        )
+    }
+}
+
+impl Debug for AttributeFields {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
 /// A wrapper type around [Address] that ensures page alignment.
 #[derive(Copy, Clone, Debug, Eq, PartialOrd, PartialEq)]
 pub struct PageAddress<T: AddressType> {
@@ -388,10 +701,57 @@ impl<T: AddressType> MemoryRegion<T> {
 
     /// Checks if there is an overlap with another memory region.
     pub fn overlaps(&self, other_region: &Self) -> bool {
-        let self_range = self.as_range();
+        // Checking only the endpoints of the other region would miss it enclosing this one.
+        self.start_page < other_region.end_page_exclusive
+            && other_region.start_page < self.end_page_exclusive
+    }
 
-        self_range.contains(&other_region.start_page)
-            || self_range.contains(&other_region.end_inclusive_page_addr())
+    /// Returns the pages contained in both regions, or `None` if they have no page in common.
+    pub fn intersection(&self, other_region: &Self) -> Option<Self> {
+        if !self.overlaps(other_region) {
+            return None;
+        }
+
+        // Page addresses are only partially ordered, so pick by hand.
+        let start_page = if self.start_page > other_region.start_page {
+            self.start_page
+        } else {
+            other_region.start_page
+        };
+        let end_page_exclusive = if self.end_page_exclusive < other_region.end_page_exclusive {
+            self.end_page_exclusive
+        } else {
+            other_region.end_page_exclusive
+        };
+
+        // An empty region inside the other one still "overlaps" it, but shares no page with it.
+        if start_page >= end_page_exclusive {
+            return None;
+        }
+
+        Some(Self {
+            start_page,
+            end_page_exclusive,
+        })
+    }
+
+    /// Returns the smallest region that contains both regions, including any gap between them.
+    pub fn bounding(&self, other_region: &Self) -> Self {
+        let start_page = if self.start_page < other_region.start_page {
+            self.start_page
+        } else {
+            other_region.start_page
+        };
+        let end_page_exclusive = if self.end_page_exclusive > other_region.end_page_exclusive {
+            self.end_page_exclusive
+        } else {
+            other_region.end_page_exclusive
+        };
+
+        Self {
+            start_page,
+            end_page_exclusive,
+        }
     }
 
     /// Returns the number of pages contained in this region.
@@ -401,11 +761,10 @@ impl<T: AddressType> MemoryRegion<T> {
 
     /// Returns the size in bytes of this region.
     pub fn size(&self) -> usize {
-        // Invariant: start <= end_exclusive, so do unchecked arithmetic.
-        let end_exclusive = self.end_page_exclusive.address().as_usize();
-        let start = self.start_page.address().as_usize();
-
-        end_exclusive - start
+        // Invariant: start <= end_exclusive, so this never panics.
+        self.start_page
+            .address()
+            .distance_to(self.end_page_exclusive.address())
     }
 
     /// Splits the MemoryRegion like:
@@ -475,3 +834,199 @@ impl MMIODescriptor {
         }
     }
 }
+
+pub(crate) mod selftests {
+    use crate::{
+        bsp,
+        memory::{
+            self,
+            mmu::{self, MemAttributes, MemoryRegion, PageAddress},
+        },
+        selftest::SelfTest,
+        synchronization::{IRQSafeNullLock, Mutex},
+        warn,
+    };
+
+    pub(crate) static TESTS: &[SelfTest] = &[
+        SelfTest {
+            name: "region overlap cases",
+            run: test_region_overlaps,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "region intersection and bounding",
+            run: test_region_intersection_bounding,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "MMIO map/unmap round trip",
+            run: test_mmio_map_unmap,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "TLB range invalidation covers every page",
+            run: test_tlbi_operands,
+            destructive: false,
+            needs_irqs: false,
+        },
+    ];
+
+    fn test_region_overlaps() -> Result<(), &'static str> {
+        let pages = |start: usize, end_exclusive: usize| {
+            let page_size = bsp::memory::mmu::MSKernel::SIZE;
+            MemoryRegion::<memory::Virtual>::new(
+                PageAddress::from(start * page_size),
+                PageAddress::from(end_exclusive * page_size),
+            )
+        };
+        let region = pages(4, 8);
+
+        let cases = [
+            ("Enclosing", pages(2, 10), true),
+            ("Enclosed", pages(5, 7), true),
+            ("Equal", pages(4, 8), true),
+            ("Partial left", pages(2, 5), true),
+            ("Partial right", pages(7, 10), true),
+            ("Adjacent left", pages(2, 4), false),
+            ("Adjacent right", pages(8, 10), false),
+            ("Disjoint", pages(10, 12), false),
+        ];
+
+        for (name, other, expected) in cases {
+            if region.overlaps(&other) != expected || other.overlaps(&region) != expected {
+                warn!("{} region: expected overlap {}", name, expected);
+                return Err("Wrong overlap result");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn test_region_intersection_bounding() -> Result<(), &'static str> {
+        const NUM_PAGES: usize = 6;
+        let page_size = bsp::memory::mmu::MSKernel::SIZE;
+        let pages = |start: usize, end_exclusive: usize| {
+            MemoryRegion::<memory::Virtual>::new(
+                PageAddress::from(start * page_size),
+                PageAddress::from(end_exclusive * page_size),
+            )
+        };
+
+        // Every pair of regions within a few pages, including empty ones, against the page indices.
+        let ranges =
+            || (0..=NUM_PAGES).flat_map(|start| (start..=NUM_PAGES).map(move |end| (start, end)));
+
+        for (a_start, a_end) in ranges() {
+            for (b_start, b_end) in ranges() {
+                let a = pages(a_start, a_end);
+                let b = pages(b_start, b_end);
+
+                let common_start = a_start.max(b_start);
+                let common_end = a_end.min(b_end);
+                let expected = (common_start < common_end).then(|| pages(common_start, common_end));
+
+                if a.intersection(&b) != expected || b.intersection(&a) != expected {
+                    return Err("Wrong intersection");
+                }
+
+                let expected = pages(a_start.min(b_start), a_end.max(b_end));
+                if a.bounding(&b) != expected || b.bounding(&a) != expected {
+                    return Err("Wrong bounding region");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Result of `mmio_map_unmap_round_trip()`, or `None` if `run_init()` was not called.
+    static MMIO_ROUND_TRIP_RESULT: IRQSafeNullLock<Option<Result<(), &'static str>>> =
+        IRQSafeNullLock::new(None);
+
+    /// Map an MMIO page that no driver uses, check the mapping and unmap it again.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called during kernel init with IRQs masked, since it changes the translation
+    ///   tables.
+    unsafe fn mmio_map_unmap_round_trip() -> Result<(), &'static str> {
+        // The EMMC2 controller, which has no driver. It is only mapped, never accessed.
+        let descriptor = mmu::MMIODescriptor::new(memory::Address::new(0xFE34_0000), 4);
+
+        let free_before =
+            mmu::page_alloc::KERNEL_MMIO_VA_ALLOCATOR.lock(|allocator| allocator.num_free_pages());
+
+        let virt_addr = mmu::kernel_map_mmio("selftest", &descriptor)?;
+        let virt_region = MemoryRegion::new(
+            PageAddress::from(virt_addr),
+            PageAddress::from(virt_addr + bsp::memory::mmu::MSKernel::SIZE),
+        );
+
+        let mut result = Ok(());
+        match bsp::memory::mmu::kernel_page_attributes(virt_region.start_page) {
+            Ok(attr) if attr.mem_attributes == MemAttributes::Device => (),
+            Ok(_) => result = Err("MMIO page not mapped as device memory"),
+            Err(_) => result = Err("MMIO page not mapped"),
+        }
+
+        mmu::kernel_unmap(&virt_region)?;
+        result?;
+
+        if mmu::is_mapped(virt_addr) {
+            return Err("MMIO page still mapped after unmap");
+        }
+
+        let free_after =
+            mmu::page_alloc::KERNEL_MMIO_VA_ALLOCATOR.lock(|allocator| allocator.num_free_pages());
+        if free_after != free_before {
+            return Err("Unmap did not give back the virtual page");
+        }
+
+        Ok(())
+    }
+
+    /// Run the self-tests that change the translation tables. Their results are reported by the
+    /// registry entries.
+    ///
+    /// # Safety
+    ///
+    /// - Must be called during kernel init with IRQs masked, after the drivers were initialized.
+    pub(crate) unsafe fn run_init() {
+        let result = mmio_map_unmap_round_trip();
+
+        MMIO_ROUND_TRIP_RESULT.lock(|recorded| *recorded = Some(result));
+    }
+
+    fn test_mmio_map_unmap() -> Result<(), &'static str> {
+        MMIO_ROUND_TRIP_RESULT
+            .lock(|result| *result)
+            .unwrap_or(Err("Did not run during kernel init"))
+    }
+
+    fn test_tlbi_operands() -> Result<(), &'static str> {
+        let start = bsp::memory::mmu::virt_heap_region().start_page.address();
+        let num_pages = 3;
+
+        let mut num_operands = 0;
+        for (i, operand) in mmu::tlbi_va_operands(start, num_pages).enumerate() {
+            let page = start + i * bsp::memory::mmu::MSKernel::SIZE;
+            if operand != (page.as_usize() >> 12) as u64 {
+                return Err("Wrong operand");
+            }
+            num_operands += 1;
+        }
+
+        if num_operands != num_pages {
+            return Err("Wrong number of tlbi instructions");
+        }
+
+        if mmu::tlbi_va_operands(start, 0).next().is_some() {
+            return Err("Invalidation of 0 pages is not empty");
+        }
+
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use super::MemoryRegion;
 use crate::{
+    fault,
     memory::{AddressType, Virtual},
     synchronization::IRQSafeNullLock,
     warn,
@@ -35,6 +36,10 @@ impl<ATYPE: AddressType> PageAllocator<ATYPE> {
         &mut self,
         num_requested_pages: NonZeroUsize,
     ) -> Result<MemoryRegion<ATYPE>, &'static str> {
+        if fault::should_fail(fault::point::PAGE_ALLOC) {
+            return Err(fault::INJECTED_FAULT);
+        }
+
         if self.pool.is_none() {
             return Err("Allocator not initialized");
         }
@@ -44,4 +49,63 @@ impl<ATYPE: AddressType> PageAllocator<ATYPE> {
             .unwrap()
             .take_first_n_pages(num_requested_pages)
     }
+
+    /// Return the number of pages that are left in the pool.
+    pub fn num_free_pages(&self) -> usize {
+        self.pool.map_or(0, |pool| pool.num_pages())
+    }
+
+    /// Give back pages from `alloc()`.
+    ///
+    /// Pages are taken from the front of a single pool, so only the most recent allocation can be
+    /// given back. Anything else is refused and stays allocated.
+    pub fn free(&mut self, region: MemoryRegion<ATYPE>) -> Result<(), &'static str> {
+        let pool = self.pool.as_mut().ok_or("Allocator not initialized")?;
+
+        if region.end_page_exclusive != pool.start_page {
+            return Err("Only the most recent allocation can be freed");
+        }
+
+        pool.start_page = region.start_page;
+
+        Ok(())
+    }
+}
+
+pub(crate) mod selftests {
+    use crate::{memory::mmu, selftest::SelfTest, synchronization::Mutex};
+
+    pub(crate) static TESTS: &[SelfTest] = &[SelfTest {
+        name: "MMIO VA alloc/free restores the pool",
+        run: test_mmio_va_free,
+        destructive: false,
+        needs_irqs: false,
+    }];
+
+    fn test_mmio_va_free() -> Result<(), &'static str> {
+        let num_pages = core::num::NonZeroUsize::new(2).unwrap();
+
+        mmu::page_alloc::KERNEL_MMIO_VA_ALLOCATOR.lock(|allocator| {
+            let before = allocator.num_free_pages();
+
+            let first = allocator.alloc(num_pages)?;
+            let second = allocator.alloc(num_pages)?;
+            if allocator.num_free_pages() != before - 2 * num_pages.get() {
+                return Err("Allocation did not take the pages from the pool");
+            }
+
+            // Only the most recent allocation is adjacent to the pool.
+            if allocator.free(first).is_ok() {
+                return Err("Free of an older allocation was accepted");
+            }
+
+            allocator.free(second)?;
+            allocator.free(first)?;
+            if allocator.num_free_pages() != before {
+                return Err("Free did not restore the pool");
+            }
+
+            Ok(())
+        })
+    }
 }
@@ -21,6 +21,9 @@ pub trait TranslationTable {
 
     /// Map the given virtual memory region to the given physical memory region.
     ///
+    /// Implementations may use larger blocks instead of pages if the regions allow it. Such blocks
+    /// can only be unmapped or changed as a whole.
+    ///
     /// # Safety
     ///
     /// - Using wrong attributes can cause multiple issues of different nature in the system.
@@ -35,6 +38,32 @@ pub trait TranslationTable {
         attr: &AttributeFields,
     ) -> Result<(), &'static str>;
 
+    /// Remove the mappings of all pages of `virt_region`.
+    ///
+    /// Fails without changing anything if any of the pages is not mapped. The caller must
+    /// invalidate the TLB entries of the region afterwards.
+    ///
+    /// # Safety
+    ///
+    /// - The region must not be accessed anymore.
+    unsafe fn unmap(&mut self, virt_region: &MemoryRegion<Virtual>) -> Result<(), &'static str>;
+
+    /// Change the access permissions and executability of all pages of `virt_region`, keeping
+    /// their physical addresses.
+    ///
+    /// Fails without changing anything if any of the pages is not mapped, or if `attr` has another
+    /// memory type than a page. The caller must invalidate the TLB entries of the region
+    /// afterwards.
+    ///
+    /// # Safety
+    ///
+    /// - Same as `map_at()`, minus the aliasing part.
+    unsafe fn change_attributes(
+        &mut self,
+        virt_region: &MemoryRegion<Virtual>,
+        attr: &AttributeFields,
+    ) -> Result<(), &'static str>;
+
     /// Try to get the attributes of a page.
     ///
     /// Will only succeed if there exists a valid mapping for the input page.
@@ -42,4 +71,13 @@ pub trait TranslationTable {
         &self,
         virt_page_addr: PageAddress<Virtual>,
     ) -> Result<AttributeFields, &'static str>;
+
+    /// Translate a virtual address to the physical address it is mapped to, e.g. for handing a
+    /// buffer to a DMA engine.
+    ///
+    /// Fails if the address is outside of the table or its page is not mapped.
+    fn try_virt_to_phys(
+        &self,
+        virt_addr: Address<Virtual>,
+    ) -> Result<Address<Physical>, &'static str>;
 }
@@ -1,18 +1,74 @@
 pub mod heap_alloc;
 pub mod mmu;
-
-use crate::{align_down, align_up, bsp::memory::mmu::MSKernel, is_aligned};
+pub mod safe_access;
+pub mod slab;
+pub mod stack;
+
+use crate::{
+    align_down, align_up,
+    bsp::memory::mmu::MSKernel,
+    is_aligned,
+    state::{init_phase_tracker, InitPhase},
+};
+use alloc::alloc::{GlobalAlloc, Layout};
 use core::{
     marker::PhantomData,
     ops::{Add, Sub},
+    ptr::NonNull,
 };
 
+pub use safe_access::{safe_read32, safe_write32};
+
 use self::{heap_alloc::kernel_init_heap_allocator, mmu::kernel_init_mmio_va_allocator};
 
 /// Finish initialization of the MMU subsystem.
-pub fn post_enable_init() {
+///
+/// Completes [`InitPhase::MemoryInit`].
+pub fn post_enable_init() -> Result<(), &'static str> {
+    init_phase_tracker().assert_ready_for(InitPhase::MemoryInit);
+
     kernel_init_mmio_va_allocator();
-    kernel_init_heap_allocator();
+    kernel_init_heap_allocator()?;
+
+    init_phase_tracker().complete(InitPhase::MemoryInit);
+
+    Ok(())
+}
+
+/// Return the layout of `num_pages` contiguous, page aligned pages.
+fn pages_layout(num_pages: usize) -> Result<Layout, &'static str> {
+    if num_pages == 0 {
+        return Err("Number of pages must be greater than zero");
+    }
+
+    let size = num_pages
+        .checked_mul(MSKernel::SIZE)
+        .ok_or("Number of pages is too large")?;
+
+    Layout::from_size_align(size, MSKernel::SIZE).map_err(|_| "Invalid page layout")
+}
+
+/// Allocate `num_pages` contiguous, page aligned and zeroed pages from the kernel heap.
+///
+/// The heap is identity mapped, so the returned address is also the physical address of the
+/// pages. Free them with [`dealloc_pages`].
+pub fn alloc_zeroed_pages(num_pages: usize) -> Result<NonNull<u8>, &'static str> {
+    let layout = pages_layout(num_pages)?;
+    // The default `alloc_zeroed()` of `GlobalAlloc` clears the pages with a single memset.
+    let ptr = unsafe { heap_alloc::KERNEL_HEAP_ALLOCATOR.alloc_zeroed(layout) };
+
+    NonNull::new(ptr).ok_or("Out of memory")
+}
+
+/// Free pages previously allocated with [`alloc_zeroed_pages`].
+///
+/// # Safety
+///
+/// - `ptr` and `num_pages` must match a previous call to [`alloc_zeroed_pages`].
+pub unsafe fn dealloc_pages(ptr: NonNull<u8>, num_pages: usize) {
+    let layout = pages_layout(num_pages).unwrap();
+
+    heap_alloc::KERNEL_HEAP_ALLOCATOR.dealloc(ptr.as_ptr(), layout);
 }
 
 /// Metadata trait for marking the type of an address.
@@ -76,6 +132,18 @@ impl<T: AddressType> Address<T> {
     pub const fn offset_into_page(&self) -> usize {
         self.value & MSKernel::MASK
     }
+
+    /// Return the number of bytes from this address up to `other`.
+    ///
+    /// # Panics
+    ///
+    /// - If `other` lies below this address.
+    pub const fn distance_to(self, other: Address<T>) -> usize {
+        match other.value.checked_sub(self.value) {
+            None => panic!("Underflow on Address::distance_to"),
+            Some(x) => x,
+        }
+    }
 }
 
 impl<T: AddressType> Add<usize> for Address<T> {
@@ -90,6 +158,8 @@ impl<T: AddressType> Add<usize> for Address<T> {
     }
 }
 
+/// Kept for existing callers. The difference of two addresses is a distance, so prefer
+/// [`Address::distance_to()`], which returns it as a byte count.
 impl<T: AddressType> Sub<Address<T>> for Address<T> {
     type Output = Self;
 
@@ -130,3 +200,30 @@ impl core::fmt::Display for Address<Virtual> {
         write!(f, "{:04x}", q1)
     }
 }
+
+pub(crate) mod selftests {
+    use crate::{bsp, memory, selftest::SelfTest};
+
+    pub(crate) static TESTS: &[SelfTest] = &[SelfTest {
+        name: "zeroed page allocation",
+        run: test_zeroed_pages,
+        destructive: false,
+        needs_irqs: false,
+    }];
+
+    fn test_zeroed_pages() -> Result<(), &'static str> {
+        let pages = memory::alloc_zeroed_pages(2)?;
+        let size = 2 * bsp::memory::mmu::MSKernel::SIZE;
+
+        let zeroed = unsafe { core::slice::from_raw_parts(pages.as_ptr(), size) }
+            .iter()
+            .all(|byte| *byte == 0);
+        unsafe { memory::dealloc_pages(pages, 2) };
+
+        if !zeroed {
+            return Err("Pages are not zeroed");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! Memory accesses that recover from faults.
+//!
+//! Before the access, the executing core's recovery slot is armed with the address of recovery
+//! code. If the access faults, the synchronous exception handler finds the armed slot and resumes
+//! there instead of panicking, and the access returns an error.
+
+#[path = "../arch/aarch64/memory/safe_access.rs"]
+mod arch_safe_access;
+
+use crate::{
+    bsp::cpu::NUM_CORES,
+    cpu, exception, is_aligned,
+    memory::{Address, Virtual},
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const DISARMED: AtomicUsize = AtomicUsize::new(0);
+
+/// Per-core address to resume at if the current access faults. Zero if no access is in progress.
+static RECOVERY_ADDR: [AtomicUsize; NUM_CORES] = [DISARMED; NUM_CORES];
+
+/// Called by the exception handler on a data abort. Returns the address to resume at if the fault
+/// was caused by a safe access of the executing core.
+pub fn take_fault_recovery() -> Option<usize> {
+    let slot = &RECOVERY_ADDR[cpu::smp::core_id::<usize>()];
+
+    match slot.load(Ordering::Relaxed) {
+        0 => None,
+        addr => {
+            slot.store(0, Ordering::Relaxed);
+            Some(addr)
+        }
+    }
+}
+
+/// Read a u32 from a virtual address that might not be mapped.
+pub fn safe_read32(addr: Address<Virtual>) -> Result<u32, &'static str> {
+    if !is_aligned(addr.as_usize(), 4) {
+        return Err("Unaligned address");
+    }
+
+    // IRQs are masked, so that a fault in an IRQ handler is never mistaken for ours.
+    exception::asynchronous::exec_with_irq_masked(|| {
+        let slot = &RECOVERY_ADDR[cpu::smp::core_id::<usize>()];
+
+        unsafe { arch_safe_access::read32(addr.as_usize(), slot) }
+    })
+    .ok_or("Access faulted")
+}
+
+/// Write a u32 to a virtual address that might not be mapped or writable.
+pub fn safe_write32(addr: Address<Virtual>, value: u32) -> Result<(), &'static str> {
+    if !is_aligned(addr.as_usize(), 4) {
+        return Err("Unaligned address");
+    }
+
+    let ok = exception::asynchronous::exec_with_irq_masked(|| {
+        let slot = &RECOVERY_ADDR[cpu::smp::core_id::<usize>()];
+
+        unsafe { arch_safe_access::write32(addr.as_usize(), value, slot) }
+    });
+
+    if !ok {
+        return Err("Access faulted");
+    }
+
+    Ok(())
+}
+
+pub(crate) mod selftests {
+    use crate::{bsp, memory, selftest::SelfTest};
+
+    pub(crate) static TESTS: &[SelfTest] = &[SelfTest {
+        name: "write to heap guard faults safely",
+        run: test_heap_guard_write_faults,
+        destructive: false,
+        needs_irqs: false,
+    }];
+
+    fn test_heap_guard_write_faults() -> Result<(), &'static str> {
+        let guard = bsp::memory::mmu::virt_heap_guard_region()
+            .start_page
+            .address();
+
+        if memory::safe_write32(guard, 0xDEAD_BEEF).is_ok() {
+            return Err("Write to heap guard page did not fault");
+        }
+
+        // A mapped target must still be written after the recovered fault.
+        let mut target = 0u32;
+        let addr = memory::Address::<memory::Virtual>::new(&mut target as *mut u32 as usize);
+        memory::safe_write32(addr, 0xDEAD_BEEF)?;
+        if memory::safe_read32(addr)? != 0xDEAD_BEEF {
+            return Err("Write to mapped address was lost");
+        }
+
+        Ok(())
+    }
+}
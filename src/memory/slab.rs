@@ -0,0 +1,203 @@
+//! Fixed-size object allocator.
+//!
+//! Used for kernel objects that are created and destroyed in IRQ context, e.g. the armed timers of
+//! [`crate::time::timer`].
+
+use core::{
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Source of the ids that tie a [`SlabIndex`] to its slab. Zero is never handed out.
+static NEXT_SLAB_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Handle to an object allocated from a [`Slab`].
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct SlabIndex {
+    slab_id: usize,
+    slot: usize,
+}
+
+/// A pool of up to `N` objects of type `T` with O(1) allocation and free.
+///
+/// Objects live in a fixed array and free slots are chained in a free-list, so the slab does not
+/// depend on the global heap and is deterministic enough for IRQ context. For shared use, wrap it
+/// in a lock, e.g. `IRQSafeNullLock<Slab<T, N>>`.
+pub struct Slab<T, const N: usize> {
+    objects: [MaybeUninit<T>; N],
+    /// For each free slot, the index of the next free slot. `N` terminates the list.
+    next_free: [usize; N],
+    in_use: [bool; N],
+    free_head: usize,
+    num_used: usize,
+    /// Taken from `NEXT_SLAB_ID` on the first allocation, since `new()` is const.
+    id: usize,
+}
+
+impl<T, const N: usize> Slab<T, { N }> {
+    /// Create an instance with all slots free.
+    pub const fn new() -> Self {
+        let mut next_free = [0; N];
+        let mut i = 0;
+        while i < N {
+            next_free[i] = i + 1;
+            i += 1;
+        }
+
+        Self {
+            // An array of `MaybeUninit` does not need initialization.
+            objects: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+            next_free,
+            in_use: [false; N],
+            free_head: 0,
+            num_used: 0,
+            id: 0,
+        }
+    }
+
+    /// Return the slot of `index`, or an error if the index was handed out by another slab.
+    fn slot(&self, index: SlabIndex) -> Result<usize, &'static str> {
+        if index.slab_id != self.id || index.slot >= N {
+            return Err("Slab index belongs to another slab");
+        }
+
+        Ok(index.slot)
+    }
+
+    /// Move `value` into a free slot and return its handle.
+    pub fn alloc(&mut self, value: T) -> Result<SlabIndex, &'static str> {
+        let index = self.free_head;
+        if index == N {
+            return Err("Slab is full");
+        }
+
+        if self.id == 0 {
+            self.id = NEXT_SLAB_ID.fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.free_head = self.next_free[index];
+        self.objects[index].write(value);
+        self.in_use[index] = true;
+        self.num_used += 1;
+
+        Ok(SlabIndex {
+            slab_id: self.id,
+            slot: index,
+        })
+    }
+
+    /// Free the slot of `index` and return the object that was stored in it.
+    ///
+    /// Fails for indexes of other slabs and for slots that are not allocated.
+    pub fn free(&mut self, index: SlabIndex) -> Result<T, &'static str> {
+        let index = self.slot(index)?;
+        if !self.in_use[index] {
+            return Err("Slab slot is not allocated");
+        }
+
+        self.in_use[index] = false;
+        self.next_free[index] = self.free_head;
+        self.free_head = index;
+        self.num_used -= 1;
+
+        // The slot was in use, so it holds an initialized object.
+        Ok(unsafe { self.objects[index].assume_init_read() })
+    }
+
+    /// Return a reference to the object of `index`.
+    pub fn get(&self, index: SlabIndex) -> Option<&T> {
+        let index = self.slot(index).ok()?;
+        if !self.in_use[index] {
+            return None;
+        }
+
+        Some(unsafe { self.objects[index].assume_init_ref() })
+    }
+
+    /// Return a mutable reference to the object of `index`.
+    pub fn get_mut(&mut self, index: SlabIndex) -> Option<&mut T> {
+        let index = self.slot(index).ok()?;
+        if !self.in_use[index] {
+            return None;
+        }
+
+        Some(unsafe { self.objects[index].assume_init_mut() })
+    }
+
+    /// Return the number of allocated slots.
+    pub fn len(&self) -> usize {
+        self.num_used
+    }
+
+    /// Return true if no slot is allocated.
+    pub fn is_empty(&self) -> bool {
+        self.num_used == 0
+    }
+
+    /// Return the total number of slots.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Drop for Slab<T, { N }> {
+    fn drop(&mut self) {
+        for (object, in_use) in self.objects.iter_mut().zip(self.in_use.iter()) {
+            if *in_use {
+                unsafe { object.assume_init_drop() };
+            }
+        }
+    }
+}
+
+pub(crate) mod selftests {
+    use super::Slab;
+    use crate::selftest::SelfTest;
+
+    pub(crate) static TESTS: &[SelfTest] = &[SelfTest {
+        name: "slab alloc/free",
+        run: test_slab,
+        destructive: false,
+        needs_irqs: false,
+    }];
+
+    fn test_slab() -> Result<(), &'static str> {
+        let mut slab: Slab<u32, 4> = Slab::new();
+
+        let a = slab.alloc(1)?;
+        let b = slab.alloc(2)?;
+        if slab.free(a)? != 1 || slab.get(b) != Some(&2) {
+            return Err("Slab returned wrong object");
+        }
+        slab.free(b)?;
+
+        if !slab.is_empty() {
+            return Err("Slab not empty after freeing everything");
+        }
+
+        // The foreign index points at a slot that is in use in both slabs, so only the slab id
+        // tells them apart. The last index of the bigger slab is out of range for the small one.
+        let first = slab.alloc(3)?;
+        let second = slab.alloc(5)?;
+        let mut other: Slab<u32, 8> = Slab::new();
+        let foreign = other.alloc(4)?;
+        let mut out_of_range = foreign;
+        for value in 0..4 {
+            out_of_range = other.alloc(value)?;
+        }
+
+        if slab.free(foreign).is_ok() || slab.get(foreign).is_some() {
+            return Err("Slab accepted an index of another slab");
+        }
+
+        if slab.free(out_of_range).is_ok() {
+            return Err("Slab accepted an out of range index");
+        }
+
+        if slab.free(first)? != 3 || slab.free(second)? != 5 {
+            return Err("Rejected index changed the slab");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+//! Boot core stack usage measurement.
+//!
+//! The boot code paints the stack with a known pattern before any Rust code runs. The deepest
+//! point the stack ever reached is then found by scanning for the first overwritten word.
+
+use crate::{bsp, info, warn};
+
+/// Pattern the boot code paints the boot core stack with.
+///
+/// Chosen to be encodable as an immediate of a single `mov` instruction.
+pub const STACK_PAINT_PATTERN: u64 = 0xAAAA_AAAA_AAAA_AAAA;
+
+/// Usage in percent above which the stack is considered too small.
+const STACK_USAGE_LIMIT_PERCENT: usize = 75;
+
+/// Measured usage of the boot core stack.
+#[derive(Copy, Clone)]
+pub struct StackUsage {
+    /// Bytes that were used at some point since boot.
+    pub used: usize,
+
+    /// Bytes available for measurement.
+    pub size: usize,
+}
+
+impl StackUsage {
+    /// Usage in percent.
+    pub fn percent(&self) -> usize {
+        self.used * 100 / self.size
+    }
+}
+
+/// Measure the high-water mark of the boot core stack.
+pub fn boot_core_stack_usage() -> StackUsage {
+    let (start, end) = bsp::memory::linker_layout().boot_core_stack_paint_range();
+    let (start, end) = (start.as_usize(), end.as_usize());
+
+    // The stack grows down, so the first overwritten word from the bottom marks its deepest point.
+    let mut addr = start;
+    while addr < end {
+        let word = unsafe { core::ptr::read_volatile(addr as *const u64) };
+        if word != STACK_PAINT_PATTERN {
+            break;
+        }
+        addr += core::mem::size_of::<u64>();
+    }
+
+    StackUsage {
+        used: end - addr,
+        size: end - start,
+    }
+}
+
+/// Check that the boot core stack has enough headroom and report its usage.
+///
+/// Should be called after the deepest init call, so that the measurement covers it.
+pub fn check_boot_core_stack() {
+    let usage = boot_core_stack_usage();
+
+    if usage.percent() >= STACK_USAGE_LIMIT_PERCENT {
+        warn!(
+            "Boot core stack usage of {} KiB of {} KiB ({}%) exceeds the {}% limit",
+            usage.used / 1024,
+            usage.size / 1024,
+            usage.percent(),
+            STACK_USAGE_LIMIT_PERCENT
+        );
+    }
+
+    info!(
+        "Boot core stack: {} KiB of {} KiB used ({}%)",
+        usage.used / 1024,
+        usage.size / 1024,
+        usage.percent()
+    );
+}
@@ -1,6 +1,49 @@
-use core::panic::PanicInfo;
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicU8, Ordering},
+};
 
-use crate::{cpu, exception::local_irq_mask, println, time::uptime};
+use crate::{
+    bsp, cpu,
+    exception::{self, local_irq_mask},
+    println,
+    time::uptime,
+    trace,
+};
+
+/// What to do after a panic message has been printed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PanicPolicy {
+    /// Park the core.
+    Halt = 0,
+    /// Reset the board. The shutdown hooks are skipped, since they could wait for a lock that the
+    /// panicking code holds.
+    Reboot = 1,
+    /// Jump back into the kernelloader, so that a fixed kernel can be uploaded without a power
+    /// cycle.
+    ///
+    /// This is the risky variant: the loader expects the state the firmware left behind, so the
+    /// MMU and caches must be turned off and the UART left usable before jumping. A panic from
+    /// within the UART driver can make the loader unreachable.
+    ReturnToLoader = 2,
+}
+
+static PANIC_POLICY: AtomicU8 = AtomicU8::new(PanicPolicy::Halt as u8);
+
+/// Set the behavior after a panic. The default is [`PanicPolicy::Halt`].
+pub fn set_policy(policy: PanicPolicy) {
+    PANIC_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Return the current panic policy.
+pub fn policy() -> PanicPolicy {
+    match PANIC_POLICY.load(Ordering::Relaxed) {
+        1 => PanicPolicy::Reboot,
+        2 => PanicPolicy::ReturnToLoader,
+        _ => PanicPolicy::Halt,
+    }
+}
 
 /// Stop immediately if called a second time.
 ///
@@ -25,6 +68,9 @@ fn panic_prevent_reenter() {
         return;
     }
 
+    // A shutdown hook that panicked while handling the first panic.
+    bsp::reset::resume_interrupted_shutdown();
+
     cpu::wait_forever()
 }
 
@@ -53,5 +99,20 @@ fn panic(info: &PanicInfo) -> ! {
         info.message().unwrap_or(&format_args!("")),
     );
 
+    exception::print_register_snapshot();
+    trace::dump();
+
+    // A panic in a shutdown hook must not keep the board from going down.
+    bsp::reset::resume_interrupted_shutdown();
+
+    match policy() {
+        PanicPolicy::Halt => (),
+        PanicPolicy::Reboot => bsp::reset::reset_board(),
+        PanicPolicy::ReturnToLoader => bsp::reset::return_to_loader(),
+    }
+
+    // Power the core down if the firmware supports it, park it otherwise.
+    let _ = cpu::psci::cpu_off();
+
     cpu::wait_forever()
 }
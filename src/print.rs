@@ -1,5 +1,32 @@
 use crate::console;
-use core::fmt;
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Verbosity of the kernel log.
+///
+/// `info!` and `warn!` messages are always printed. `trace!` messages are only printed if the log
+/// level is set to `Trace`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LogLevel {
+    Info = 0,
+    Trace = 1,
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Set the verbosity of the kernel log.
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Return true if messages of the given level are printed.
+#[inline(always)]
+pub fn log_level_enabled(level: LogLevel) -> bool {
+    LOG_LEVEL.load(Ordering::Relaxed) >= level as u8
+}
 
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
@@ -68,3 +95,29 @@ macro_rules! warn {
         ));
     })
 }
+
+/// Prints a trace message, with a newline. Only printed if the trace log level is enabled.
+#[macro_export]
+macro_rules! trace {
+    ($string:expr) => ({
+        if $crate::print::log_level_enabled($crate::print::LogLevel::Trace) {
+            let timestamp = $crate::time::uptime();
+
+            $crate::print::_print(format_args_nl!(
+                concat!("[{:#?}] TRACE ", $string),
+                timestamp,
+            ));
+        }
+    });
+    ($format_string:expr, $($arg:tt)*) => ({
+        if $crate::print::log_level_enabled($crate::print::LogLevel::Trace) {
+            let timestamp = $crate::time::uptime();
+
+            $crate::print::_print(format_args_nl!(
+                concat!("[{:#?}] TRACE ", $format_string),
+                timestamp,
+                $($arg)*
+            ));
+        }
+    })
+}
@@ -0,0 +1,44 @@
+//! QEMU semihosting.
+//!
+//! Lets the kernel end an emulator run with an exit code, so that automated runs can be asserted
+//! on. Only works under QEMU started with `-semihosting`. Everywhere else, the semihosting call is
+//! an undefined instruction and traps.
+//!
+//! Exit code 0 means success. Any other code is the reason for a failure, e.g. the number of the
+//! first failed self-test as returned by `selftest::run_all()`.
+
+#[path = "arch/aarch64/qemu.rs"]
+mod arch_qemu;
+
+use crate::{console, console::interface::Write, cpu};
+
+/// Semihosting operation to end the application.
+const SYS_EXIT: u32 = 0x18;
+
+/// Exit reason for a normal application exit, which makes QEMU use the given exit code.
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x2_0026;
+
+/// Exit QEMU with the given exit code.
+pub fn exit(code: u32) -> ! {
+    console::console().flush();
+
+    let parameters: [u64; 2] = [ADP_STOPPED_APPLICATION_EXIT, u64::from(code)];
+    unsafe {
+        arch_qemu::semihosting_call(SYS_EXIT, parameters.as_ptr() as u64);
+    }
+
+    // Only reached if the emulator ignored the request.
+    cpu::wait_forever()
+}
+
+/// Exit QEMU with exit code 0.
+pub fn exit_success() -> ! {
+    exit(0)
+}
+
+/// Exit QEMU with a failure's reason code as exit code.
+///
+/// A reason code of 0 is reported as 1, so that the failure can not be mistaken for a success.
+pub fn exit_failure(reason_code: u32) -> ! {
+    exit(reason_code.max(1))
+}
@@ -0,0 +1,152 @@
+//! On-target smoke tests.
+//!
+//! Each test exercises one subsystem and reports an error string on failure. Tests live next to
+//! the code they exercise, in a `selftests` module whose `TESTS` are listed in [`SUITES`].
+//! `run_all()` runs them all and prints a pass/fail line per test.
+//!
+//! Tests that change the translation tables can only run during kernel init. `run_init()` runs
+//! them there and their registry entries report the recorded results. Likewise, faults in driver
+//! init are armed by `arm_init_faults()` before the drivers come up, and checked afterwards.
+//!
+//! Destructive tests change state that the running kernel relies on, e.g. drive a GPIO pin or put
+//! the console UART in loopback. `run_boot()` leaves them out, they only run on request with
+//! `run_all()`, e.g. from the `selftest` demos.
+//!
+//! Tests that take IRQs are skipped, not failed, when the kernel runs in polled mode because the
+//! interrupt controller did not come up. Skips are counted separately and do not fail a run.
+//!
+//! Tests are numbered from 1 in registry order. The number of the first failed test is returned
+//! by `run_all()` and used as QEMU exit code in headless runs, so that a failed CI run points at
+//! the culprit.
+
+use crate::{bsp, console, info, memory, synchronization, time, util, warn};
+#[cfg(feature = "fault_injection")]
+use crate::{bsp::drivers::bcm2711_pm_wdog::PMWatchdog, fault};
+
+/// A single self-test.
+pub struct SelfTest {
+    pub name: &'static str,
+    pub run: fn() -> Result<(), &'static str>,
+    /// The test disturbs the running kernel and is not run at boot.
+    pub destructive: bool,
+    /// The test takes IRQs and is skipped if the interrupt controller did not come up.
+    pub needs_irqs: bool,
+}
+
+/// The tests of all modules, in the order they are run.
+static SUITES: &[&[SelfTest]] = &[
+    memory::heap_alloc::selftests::TESTS,
+    memory::selftests::TESTS,
+    memory::heap_alloc::buddy::selftests::TESTS,
+    memory::slab::selftests::TESTS,
+    console::selftests::TESTS,
+    bsp::drivers::bcm2xxx_gpio::selftests::TESTS,
+    time::selftests::TESTS,
+    bsp::memory::mmu::selftests::TESTS,
+    memory::safe_access::selftests::TESTS,
+    memory::mmu::selftests::TESTS,
+    memory::mmu::page_alloc::selftests::TESTS,
+    synchronization::selftests::TESTS,
+    time::timer::selftests::TESTS,
+    bsp::drivers::gicv2::selftests::TESTS,
+    util::rng::selftests::TESTS,
+    util::ring_buffer::selftests::TESTS,
+    util::array_string::selftests::TESTS,
+    #[cfg(not(feature = "mini_uart"))]
+    bsp::drivers::bcm2xxx_pl011::selftests::TESTS,
+    #[cfg(feature = "fault_injection")]
+    fault::selftests::TESTS,
+];
+
+fn tests() -> impl Iterator<Item = &'static SelfTest> {
+    SUITES.iter().flat_map(|suite| suite.iter())
+}
+
+/// Arm the faults that self-tests expect to have fired during driver init. Without fault
+/// injection, there are none.
+///
+/// The watchdog is made to fail, as the kernel does without it, so fault injection builds boot
+/// without a watchdog.
+pub fn arm_init_faults() -> Result<(), &'static str> {
+    #[cfg(feature = "fault_injection")]
+    fault::arm_for(fault::point::DRIVER_INIT, PMWatchdog::COMPATIBLE)?;
+
+    Ok(())
+}
+
+/// Run the self-tests that change the translation tables. Their results are reported by
+/// `run_all()`.
+///
+/// # Safety
+///
+/// - Must be called during kernel init with IRQs masked, after the drivers were initialized.
+pub unsafe fn run_init() {
+    memory::mmu::selftests::run_init();
+}
+
+/// Run the non-destructive self-tests, as done at boot, and print the results. Returns the number
+/// of the first failed test, if any.
+pub fn run_boot() -> Result<(), usize> {
+    run(false)
+}
+
+/// Run all self-tests, including the destructive ones, and print the results. Returns the number
+/// of the first failed test, if any.
+pub fn run_all() -> Result<(), usize> {
+    run(true)
+}
+
+fn run(include_destructive: bool) -> Result<(), usize> {
+    let mut num_tests = 0;
+    let mut num_failed = 0;
+    let mut num_left_out = 0;
+    let mut num_skipped = 0;
+    let mut first_failed = None;
+
+    for (number, test) in (1..).zip(tests()) {
+        if test.destructive && !include_destructive {
+            num_left_out += 1;
+            continue;
+        }
+
+        if test.needs_irqs {
+            if let Some(e) = bsp::driver::interrupt_controller_error() {
+                info!(
+                    "      [SKIP] #{} {}: IRQs not available: {}",
+                    number, test.name, e
+                );
+                num_skipped += 1;
+                continue;
+            }
+        }
+
+        num_tests += 1;
+        match (test.run)() {
+            Ok(()) => info!("      [PASS] #{} {}", number, test.name),
+            Err(e) => {
+                warn!("      [FAIL] #{} {}: {}", number, test.name, e);
+                num_failed += 1;
+                first_failed.get_or_insert(number);
+            }
+        }
+    }
+
+    info!(
+        "      {} of {} self-tests passed, {} skipped",
+        num_tests - num_failed,
+        num_tests,
+        num_skipped
+    );
+
+    if num_left_out > 0 {
+        info!(
+            "      {} destructive self-tests left out, run them with the selftest demo",
+            num_left_out
+        );
+    }
+
+    match first_failed {
+        None => Ok(()),
+        Some(number) => Err(number),
+    }
+}
@@ -1,3 +1,4 @@
+use crate::trace;
 use core::sync::atomic::{AtomicU8, Ordering};
 
 static STATE_MANAGER: StateManager = StateManager::new();
@@ -66,5 +67,112 @@ impl StateManager {
         {
             panic!("transition_to_single_core_main() called while state != Init");
         }
+
+        trace::event(
+            trace::id::STATE_TRANSITION,
+            u64::from(Self::SINGLE_CORE_MAIN),
+        );
+    }
+}
+
+static INIT_PHASE_TRACKER: InitPhaseTracker = InitPhaseTracker::new();
+
+/// The major steps of `kernel_init()`, in the order they must run.
+#[derive(Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum InitPhase {
+    /// No init step has run yet.
+    Boot = 0,
+    KernelMapped,
+    MmuEnabled,
+    MemoryInit,
+    BspDriverInit,
+    DriverInit,
+}
+
+impl InitPhase {
+    const ALL: [Self; 6] = [
+        Self::Boot,
+        Self::KernelMapped,
+        Self::MmuEnabled,
+        Self::MemoryInit,
+        Self::BspDriverInit,
+        Self::DriverInit,
+    ];
+
+    /// Name of the step that completes this phase, for use in error messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Boot => "boot",
+            Self::KernelMapped => "kernel mapping",
+            Self::MmuEnabled => "MMU enable",
+            Self::MemoryInit => "memory init",
+            Self::BspDriverInit => "BSP driver init",
+            Self::DriverInit => "driver init",
+        }
+    }
+
+    /// The phase that must be completed before this one can start.
+    fn prior(self) -> Option<Self> {
+        (self as usize).checked_sub(1).map(|prior| Self::ALL[prior])
+    }
+}
+
+/// Tracks the completed phases of `kernel_init()` to enforce their order.
+pub struct InitPhaseTracker(AtomicU8);
+
+/// Return a reference to the global InitPhaseTracker.
+pub fn init_phase_tracker() -> &'static InitPhaseTracker {
+    &INIT_PHASE_TRACKER
+}
+
+impl InitPhaseTracker {
+    /// Create a new instance.
+    pub const fn new() -> Self {
+        Self(AtomicU8::new(InitPhase::Boot as u8))
+    }
+
+    /// Return the last completed phase.
+    pub fn current(&self) -> InitPhase {
+        let phase = self.0.load(Ordering::Acquire);
+
+        match InitPhase::ALL.get(phase as usize) {
+            Some(phase) => *phase,
+            None => panic!("Invalid INIT_PHASE"),
+        }
+    }
+
+    /// Assert that the phase prior to `phase` is completed, i.e. that the step completing `phase`
+    /// may run now.
+    pub fn assert_ready_for(&self, phase: InitPhase) {
+        let current = self.current();
+
+        if let Some(prior) = phase.prior() {
+            if current < prior {
+                panic!("{} called before {}", phase.name(), prior.name());
+            }
+        }
+
+        if current >= phase {
+            panic!("{} called after {}", phase.name(), current.name());
+        }
+    }
+
+    /// Mark `phase` as completed.
+    pub fn complete(&self, phase: InitPhase) {
+        self.assert_ready_for(phase);
+        self.0.store(phase as u8, Ordering::Release);
+    }
+
+    /// Mark all phases before `phase` as completed without running them.
+    ///
+    /// For binaries that do not need the earlier phases, e.g. the kernelloader, which runs with the
+    /// MMU off.
+    pub fn skip_to(&self, phase: InitPhase) {
+        if let Some(prior) = phase.prior() {
+            if self.current() < prior {
+                self.0.store(prior as u8, Ordering::Release);
+            }
+        }
     }
 }
@@ -2,7 +2,11 @@ use crate::{
     exception::asynchronous::exec_with_irq_masked, exception::local_irq_enabled,
     state::state_manager,
 };
-use core::cell::UnsafeCell;
+use core::{
+    cell::UnsafeCell,
+    hint,
+    sync::atomic::{AtomicIsize, AtomicUsize, Ordering},
+};
 
 /// Any object implementing this trait guarantees exclusive access to the data wrapped within
 /// the Mutex for the duration of the provided closure.
@@ -109,3 +113,191 @@ impl<T> ReadWriteExclusive for InitStateLock<T> {
         f(data)
     }
 }
+
+/// A reader-writer spin lock for data that is mostly read, but also written after kernel init.
+///
+/// The lock state is a counter: positive values count the readers, -1 marks a writer. Readers and
+/// writers both mask IRQs on the executing core while they wait for and hold the lock, like
+/// [`IRQSafeNullLock`]. An IRQ handler that takes the lock can therefore never interrupt a holder
+/// on the same core and spin on it forever, e.g. a reader that a writer from another core queued
+/// up behind. Once a writer waits, new readers hold back until it got its turn, so a steady stream
+/// of readers can not starve it.
+///
+/// Readers must not nest on the same core: a writer from another core that queued up in between
+/// would wait for the outer reader, while the inner one waits for the writer.
+///
+/// Relies on exclusive load/store instructions, so it must only be used with the MMU and caching
+/// enabled.
+pub struct RwSpinLock<T>
+where
+    T: ?Sized,
+{
+    state: AtomicIsize,
+    writers_waiting: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T> Send for RwSpinLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for RwSpinLock<T> where T: ?Sized + Send + Sync {}
+
+impl<T> RwSpinLock<T> {
+    const WRITER: isize = -1;
+
+    /// Create an instance.
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicIsize::new(0),
+            writers_waiting: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+impl<T> ReadWriteExclusive for RwSpinLock<T> {
+    type Data = T;
+
+    fn write<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
+        exec_with_irq_masked(|| {
+            self.writers_waiting.fetch_add(1, Ordering::Relaxed);
+            while self
+                .state
+                .compare_exchange_weak(0, Self::WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                hint::spin_loop();
+            }
+            self.writers_waiting.fetch_sub(1, Ordering::Relaxed);
+
+            let data = unsafe { &mut *self.data.get() };
+            let ret = f(data);
+
+            self.state.store(0, Ordering::Release);
+            ret
+        })
+    }
+
+    fn read<'a, R>(&'a self, f: impl FnOnce(&'a Self::Data) -> R) -> R {
+        exec_with_irq_masked(|| {
+            loop {
+                if self.writers_waiting.load(Ordering::Relaxed) == 0 {
+                    let readers = self.state.load(Ordering::Relaxed);
+                    if readers >= 0
+                        && self
+                            .state
+                            .compare_exchange_weak(
+                                readers,
+                                readers + 1,
+                                Ordering::Acquire,
+                                Ordering::Relaxed,
+                            )
+                            .is_ok()
+                    {
+                        break;
+                    }
+                }
+                hint::spin_loop();
+            }
+
+            let data = unsafe { &*self.data.get() };
+            let ret = f(data);
+
+            self.state.fetch_sub(1, Ordering::Release);
+            ret
+        })
+    }
+}
+
+pub(crate) mod selftests {
+    use super::{ReadWriteExclusive, RwSpinLock};
+    use crate::{exception, selftest::SelfTest, time};
+    use core::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    pub(crate) static TESTS: &[SelfTest] = &[
+        SelfTest {
+            name: "rw spin lock",
+            run: test_rw_spin_lock,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "rw spin lock against timer IRQ readers",
+            run: test_rw_spin_lock_contention,
+            destructive: false,
+            needs_irqs: true,
+        },
+    ];
+
+    fn test_rw_spin_lock() -> Result<(), &'static str> {
+        let lock = RwSpinLock::new(0u64);
+
+        lock.write(|value| *value = 42);
+        if lock.read(|value| *value) != 42 {
+            return Err("Write not visible to reader");
+        }
+
+        // The lock must be free again after both kinds of access.
+        lock.write(|value| *value += 1);
+        if lock.read(|value| *value) != 43 {
+            return Err("Second write not visible to reader");
+        }
+
+        Ok(())
+    }
+
+    /// Shared between the writing thread and the reading timer callback of the contention test.
+    static CONTENDED: RwSpinLock<[u64; 2]> = RwSpinLock::new([0; 2]);
+
+    static CONTENDED_READS: AtomicUsize = AtomicUsize::new(0);
+
+    static CONTENDED_TORN_READS: AtomicUsize = AtomicUsize::new(0);
+
+    const CONTENDED_NUM_READS: usize = 32;
+
+    /// Read both halves and re-arm until enough reads were done. A reader that sees different
+    /// halves interrupted a writer in the middle of an update.
+    fn contended_reader() {
+        if CONTENDED.read(|value| value[0] != value[1]) {
+            CONTENDED_TORN_READS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if CONTENDED_READS.fetch_add(1, Ordering::Relaxed) + 1 < CONTENDED_NUM_READS {
+            let _ = time::timer::timer().arm(Duration::from_micros(20), contended_reader);
+        }
+    }
+
+    fn test_rw_spin_lock_contention() -> Result<(), &'static str> {
+        if !CONTENDED.read(|_| exception::local_irq_enabled()) {
+            return Err("Reader ran with IRQs unmasked");
+        }
+
+        CONTENDED_READS.store(0, Ordering::Relaxed);
+        CONTENDED_TORN_READS.store(0, Ordering::Relaxed);
+        time::timer::timer().arm(Duration::from_micros(20), contended_reader)?;
+
+        // Keep the lock busy, with the halves differing in the middle of every update. The timer
+        // IRQ can only read between the updates.
+        let deadline = time::uptime() + Duration::from_secs(1);
+        let mut counter = 0;
+        while CONTENDED_READS.load(Ordering::Relaxed) < CONTENDED_NUM_READS {
+            if time::uptime() > deadline {
+                return Err("Timer IRQ reader did not finish");
+            }
+
+            counter += 1;
+            CONTENDED.write(|value| {
+                value[0] = counter;
+                let _ = time::spin_for(Duration::from_micros(5));
+                value[1] = counter;
+            });
+        }
+
+        if CONTENDED_TORN_READS.load(Ordering::Relaxed) != 0 {
+            return Err("Reader saw a half-done write");
+        }
+
+        Ok(())
+    }
+}
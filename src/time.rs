@@ -1,7 +1,12 @@
 #[path = "arch/aarch64/time.rs"]
 mod arch_time;
+pub mod boot_timeline;
+pub mod timer;
+pub mod wall_clock;
 
+use crate::exception::asynchronous::ThreadContext;
 use core::{num::NonZeroU64, time::Duration};
+pub use wall_clock::{UnixTime, WallClock};
 
 const NANOSEC_PER_SEC: NonZeroU64 = NonZeroU64::new(1_000_000_000).unwrap();
 
@@ -9,10 +14,58 @@ pub fn uptime() -> Duration {
     arch_time::uptime()
 }
 
+/// The raw value of the system counter. Cheaper than [`uptime()`], but needs
+/// [`counter_to_duration()`] to be interpreted.
+#[inline(always)]
+pub fn counter() -> u64 {
+    arch_time::counter()
+}
+
+pub fn counter_to_duration(counter: u64) -> Duration {
+    arch_time::counter_to_duration(counter)
+}
+
 pub fn resolution() -> Duration {
     arch_time::resolution()
 }
 
+/// The current wall-clock time, or `None` if it was never set.
+pub fn wall_clock() -> Option<UnixTime> {
+    wall_clock::wall_clock().now()
+}
+
 pub fn spin_for(duration: Duration) -> Result<(), &'static str> {
     arch_time::spin_for(duration)
 }
+
+/// Block for at least `duration`.
+///
+/// Blocking is only allowed in thread context, which is proven by the `ThreadContext` token. There
+/// is no scheduler yet, so the core spins until the time has passed.
+pub fn sleep(_tc: &ThreadContext, duration: Duration) -> Result<(), &'static str> {
+    spin_for(duration)
+}
+
+pub(crate) mod selftests {
+    use crate::{selftest::SelfTest, time};
+    use core::time::Duration;
+
+    pub(crate) static TESTS: &[SelfTest] = &[SelfTest {
+        name: "uptime advances",
+        run: test_uptime_advances,
+        destructive: false,
+        needs_irqs: false,
+    }];
+
+    fn test_uptime_advances() -> Result<(), &'static str> {
+        let before = time::uptime();
+        time::spin_for(Duration::from_micros(100))?;
+        let after = time::uptime();
+
+        if after <= before {
+            return Err("Uptime did not advance");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+//! Boot timing instrumentation.
+//!
+//! `kernel_init()` records a marker after each major step. Markers only use plain atomic loads and
+//! stores, so they work before the MMU and caches are enabled.
+
+use crate::info;
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// The recorded boot phases, in boot order.
+#[derive(Copy, Clone)]
+pub enum BootPhase {
+    /// First instruction of `kernel_init()`.
+    Start,
+    /// Kernel binary mapped in the translation tables.
+    MmuMapped,
+    /// MMU and caching enabled.
+    MmuEnabled,
+    /// Heap and MMIO allocators initialized.
+    MemoryInit,
+    /// All drivers and their IRQ handlers initialized.
+    DriversInit,
+    /// IRQs unmasked on the boot core.
+    IrqUnmasked,
+}
+
+const NUM_PHASES: usize = 6;
+
+const PHASE_NAMES: [&str; NUM_PHASES] = [
+    "Start",
+    "MMU map",
+    "MMU enable",
+    "Memory init",
+    "Driver init",
+    "IRQ unmask",
+];
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+/// Uptime in nanoseconds at which each phase was reached. Zero if it was not reached.
+static MARKERS_NS: [AtomicU64; NUM_PHASES] = [ZERO; NUM_PHASES];
+
+/// Record that the given phase was reached.
+pub fn mark(phase: BootPhase) {
+    MARKERS_NS[phase as usize].store(super::uptime().as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Print each recorded phase with its uptime and the time it took since the previous marker.
+pub fn print() {
+    let mut previous: Option<u64> = None;
+
+    for (name, marker) in PHASE_NAMES.iter().zip(MARKERS_NS.iter()) {
+        let at = marker.load(Ordering::Relaxed);
+        if at == 0 {
+            continue;
+        }
+
+        let at_duration = Duration::from_nanos(at);
+        match previous {
+            None => info!("      {:<12} at {:?}", name, at_duration),
+            Some(previous) => info!(
+                "      {:<12} at {:?} (+{:?})",
+                name,
+                at_duration,
+                Duration::from_nanos(at - previous)
+            ),
+        }
+
+        previous = Some(at);
+    }
+}
@@ -0,0 +1,384 @@
+//! Countdown timers.
+//!
+//! Armed timers live in a [`Slab`], so that arming them from IRQ context does not need the heap.
+//! A small array of slab indexes keeps them sorted by deadline. The physical timer's comparator is
+//! always programmed to the earliest deadline, and its IRQ handler fires every expired timer's
+//! callback.
+//!
+//! Callbacks run in IRQ context, one at a time and without the list locked. A callback may
+//! therefore arm or cancel timers, including ones that expired at the same time and did not fire
+//! yet. A callback that wants to run periodically arms itself again.
+
+use super::arch_time;
+use crate::{
+    bsp,
+    exception::asynchronous::{irq_manager, IRQHandler, IRQHandlerDescriptor},
+    memory::slab::{Slab, SlabIndex},
+    synchronization::{IRQSafeNullLock, Mutex},
+};
+use core::time::Duration;
+
+/// Maximum number of timers that can be armed at the same time.
+const MAX_TIMERS: usize = 16;
+
+static TIMER: Timer = Timer::new();
+
+/// Identifies an armed timer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimerHandle(u64);
+
+#[derive(Copy, Clone)]
+struct PendingTimer {
+    handle: TimerHandle,
+    deadline: Duration,
+    callback: fn(),
+}
+
+struct TimerQueue {
+    timers: Slab<PendingTimer, MAX_TIMERS>,
+    /// The armed timers, sorted by deadline, earliest first. Timers with the same deadline fire in
+    /// the order they were armed.
+    order: [Option<SlabIndex>; MAX_TIMERS],
+    next_handle: u64,
+}
+
+impl TimerQueue {
+    const fn new() -> Self {
+        Self {
+            timers: Slab::new(),
+            order: [None; MAX_TIMERS],
+            next_handle: 0,
+        }
+    }
+
+    fn pending(&self) -> impl Iterator<Item = &PendingTimer> {
+        self.order[..self.timers.len()]
+            .iter()
+            .flatten()
+            .filter_map(|index| self.timers.get(*index))
+    }
+
+    fn position(&self, handle: TimerHandle) -> Option<usize> {
+        self.pending().position(|timer| timer.handle == handle)
+    }
+
+    fn insert(&mut self, deadline: Duration, callback: fn()) -> Result<TimerHandle, &'static str> {
+        let num_pending = self.timers.len();
+        if num_pending == MAX_TIMERS {
+            return Err("Too many timers armed");
+        }
+
+        let handle = TimerHandle(self.next_handle);
+        self.next_handle += 1;
+
+        let index = self
+            .pending()
+            .position(|timer| timer.deadline > deadline)
+            .unwrap_or(num_pending);
+
+        let timer = self.timers.alloc(PendingTimer {
+            handle,
+            deadline,
+            callback,
+        })?;
+        self.order.copy_within(index..num_pending, index + 1);
+        self.order[index] = Some(timer);
+
+        Ok(handle)
+    }
+
+    fn remove(&mut self, index: usize) -> PendingTimer {
+        let num_pending = self.timers.len();
+        let timer = self.order[index].take().unwrap();
+
+        self.order.copy_within(index + 1..num_pending, index);
+        self.order[num_pending - 1] = None;
+
+        // Only indexes of allocated timers are kept in the order.
+        self.timers.free(timer).unwrap()
+    }
+
+    /// Remove and return the earliest timer if it expired at `now`.
+    fn pop_expired(&mut self, now: Duration) -> Option<PendingTimer> {
+        let earliest = *self.pending().next()?;
+
+        (earliest.deadline <= now).then(|| self.remove(0))
+    }
+
+    /// Program the comparator for the earliest deadline, or stop it if no timer is armed.
+    fn reprogram(&self) {
+        match self.pending().next() {
+            None => arch_time::disable_compare(),
+            // Deadlines were checked when they were armed, so this can not fail.
+            Some(timer) => {
+                let _ = arch_time::set_compare(timer.deadline);
+            }
+        }
+    }
+}
+
+/// Countdown timers backed by the physical timer's comparator.
+pub struct Timer {
+    queue: IRQSafeNullLock<TimerQueue>,
+}
+
+/// Return a reference to the global Timer.
+pub fn timer() -> &'static Timer {
+    &TIMER
+}
+
+impl Timer {
+    /// Create an instance.
+    const fn new() -> Self {
+        Self {
+            queue: IRQSafeNullLock::new(TimerQueue::new()),
+        }
+    }
+
+    /// Call `callback` from the timer IRQ once `timeout` has passed.
+    pub fn arm(&self, timeout: Duration, callback: fn()) -> Result<TimerHandle, &'static str> {
+        let deadline = super::uptime()
+            .checked_add(timeout)
+            .ok_or("Timer timeout too big")?;
+
+        self.arm_at(deadline, callback)
+    }
+
+    /// Call `callback` from the timer IRQ once the uptime reached `deadline`. A deadline in the
+    /// past fires right away.
+    pub fn arm_at(&self, deadline: Duration, callback: fn()) -> Result<TimerHandle, &'static str> {
+        self.queue.lock(|queue| {
+            // Rejects deadlines the comparator can not represent. It is set to the earliest
+            // deadline right after.
+            arch_time::set_compare(deadline)?;
+
+            let handle = queue.insert(deadline, callback);
+            queue.reprogram();
+
+            handle
+        })
+    }
+
+    /// Cancel a timer. Fails if it already fired or was canceled before.
+    pub fn cancel(&self, handle: TimerHandle) -> Result<(), &'static str> {
+        self.queue.lock(|queue| {
+            let index = queue.position(handle).ok_or("Timer not armed")?;
+
+            queue.remove(index);
+            queue.reprogram();
+
+            Ok(())
+        })
+    }
+
+    /// Return the time left until the timer fires, or `None` if it is not armed.
+    pub fn remaining(&self, handle: TimerHandle) -> Option<Duration> {
+        let deadline = self.queue.lock(|queue| {
+            queue
+                .pending()
+                .find(|timer| timer.handle == handle)
+                .map(|timer| timer.deadline)
+        })?;
+
+        Some(deadline.saturating_sub(super::uptime()))
+    }
+}
+
+impl IRQHandler for Timer {
+    fn handle(&self) -> Result<(), &'static str> {
+        // Take one timer at a time, so that a callback that cancels another expired timer is
+        // honored. Timers with near-identical deadlines all fire in this loop instead of raising
+        // one IRQ each.
+        loop {
+            let expired = self.queue.lock(|queue| {
+                let expired = queue.pop_expired(super::uptime());
+                if expired.is_none() {
+                    queue.reprogram();
+                }
+                expired
+            });
+
+            match expired {
+                None => return Ok(()),
+                Some(timer) => (timer.callback)(),
+            }
+        }
+    }
+}
+
+/// Register the timer IRQ handler.
+///
+/// # Safety
+///
+/// - Must be called during kernel init, after the interrupt controller was set up.
+pub unsafe fn init() -> Result<(), &'static str> {
+    arch_time::disable_compare();
+
+    let descriptor = IRQHandlerDescriptor {
+        number: bsp::execption::ARCH_TIMER_IRQ,
+        name: "Architectural timer",
+        handler: timer(),
+        affinity: None,
+        priority: None,
+    };
+
+    irq_manager().register_handler(descriptor)?;
+    irq_manager().enable(&bsp::execption::ARCH_TIMER_IRQ);
+
+    Ok(())
+}
+
+pub(crate) mod selftests {
+    use super::TimerHandle;
+    use crate::{
+        selftest::SelfTest,
+        synchronization::{IRQSafeNullLock, Mutex},
+        time,
+    };
+    use core::{
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    pub(crate) static TESTS: &[SelfTest] = &[
+        SelfTest {
+            name: "one-shot timer IRQ runs its callback",
+            run: test_one_shot_timer,
+            destructive: false,
+            needs_irqs: true,
+        },
+        SelfTest {
+            name: "timers a tick apart fire in order",
+            run: test_timers_tick_apart,
+            destructive: false,
+            needs_irqs: true,
+        },
+        SelfTest {
+            name: "timer canceled from another timer's callback",
+            run: test_timer_cancel_from_callback,
+            destructive: false,
+            needs_irqs: true,
+        },
+    ];
+
+    /// Number of timer callbacks that ran since the last reset. Each callback below records the
+    /// value it saw plus one, so 0 means that it did not fire.
+    static TIMER_SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+
+    static EARLY_TIMER_FIRED_AS: AtomicUsize = AtomicUsize::new(0);
+
+    static LATE_TIMER_FIRED_AS: AtomicUsize = AtomicUsize::new(0);
+
+    /// The timer that `canceling_timer()` cancels, and whether it managed to.
+    static TIMER_TO_CANCEL: IRQSafeNullLock<Option<TimerHandle>> = IRQSafeNullLock::new(None);
+
+    static TIMER_CANCELED: AtomicBool = AtomicBool::new(false);
+
+    fn reset_timer_callbacks() {
+        TIMER_SEQUENCE.store(0, Ordering::Relaxed);
+        EARLY_TIMER_FIRED_AS.store(0, Ordering::Relaxed);
+        LATE_TIMER_FIRED_AS.store(0, Ordering::Relaxed);
+        TIMER_CANCELED.store(false, Ordering::Relaxed);
+    }
+
+    fn early_timer() {
+        let number = TIMER_SEQUENCE.fetch_add(1, Ordering::Relaxed) + 1;
+        EARLY_TIMER_FIRED_AS.store(number, Ordering::Relaxed);
+    }
+
+    fn late_timer() {
+        let number = TIMER_SEQUENCE.fetch_add(1, Ordering::Relaxed) + 1;
+        LATE_TIMER_FIRED_AS.store(number, Ordering::Relaxed);
+    }
+
+    fn canceling_timer() {
+        early_timer();
+
+        if let Some(handle) = TIMER_TO_CANCEL.lock(|handle| handle.take()) {
+            if time::timer::timer().cancel(handle).is_ok() {
+                TIMER_CANCELED.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Spin until `fired` is set, or fail after a second.
+    fn wait_for_timer(fired: &AtomicUsize) -> Result<(), &'static str> {
+        let deadline = time::uptime() + Duration::from_secs(1);
+
+        while fired.load(Ordering::Relaxed) == 0 {
+            if time::uptime() > deadline {
+                return Err("Timer did not fire");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn test_one_shot_timer() -> Result<(), &'static str> {
+        reset_timer_callbacks();
+
+        let handle = time::timer::timer().arm(Duration::from_micros(100), early_timer)?;
+        wait_for_timer(&EARLY_TIMER_FIRED_AS)?;
+        // A one-shot timer must not come back.
+        time::spin_for(Duration::from_micros(200))?;
+
+        if TIMER_SEQUENCE.load(Ordering::Relaxed) != 1 {
+            return Err("Timer fired more than once");
+        }
+
+        if time::timer::timer().remaining(handle).is_some() {
+            return Err("Fired timer is still armed");
+        }
+
+        Ok(())
+    }
+
+    fn test_timers_tick_apart() -> Result<(), &'static str> {
+        reset_timer_callbacks();
+
+        // Arm the later deadline first, so that the earlier one has to be sorted in front of it.
+        let early = time::uptime() + Duration::from_micros(100);
+        time::timer::timer().arm_at(early + time::resolution(), late_timer)?;
+        time::timer::timer().arm_at(early, early_timer)?;
+
+        wait_for_timer(&EARLY_TIMER_FIRED_AS)?;
+        wait_for_timer(&LATE_TIMER_FIRED_AS)?;
+
+        if EARLY_TIMER_FIRED_AS.load(Ordering::Relaxed) != 1
+            || LATE_TIMER_FIRED_AS.load(Ordering::Relaxed) != 2
+        {
+            return Err("Timers fired out of order");
+        }
+
+        Ok(())
+    }
+
+    fn test_timer_cancel_from_callback() -> Result<(), &'static str> {
+        reset_timer_callbacks();
+
+        // The victim expires a tick after the canceling timer, so that both are usually handled in
+        // the same IRQ and the victim is already expired when it is canceled.
+        let deadline = time::uptime() + Duration::from_micros(100);
+        let victim = time::timer::timer().arm_at(deadline + time::resolution(), late_timer)?;
+        TIMER_TO_CANCEL.lock(|handle| *handle = Some(victim));
+        time::timer::timer().arm_at(deadline, canceling_timer)?;
+
+        wait_for_timer(&EARLY_TIMER_FIRED_AS)?;
+        // Give the victim the chance to fire, if canceling did not work.
+        time::spin_for(Duration::from_micros(200))?;
+
+        if !TIMER_CANCELED.load(Ordering::Relaxed) {
+            return Err("Cancel from a callback failed");
+        }
+
+        if LATE_TIMER_FIRED_AS.load(Ordering::Relaxed) != 0 {
+            return Err("Canceled timer fired");
+        }
+
+        if time::timer::timer().remaining(victim).is_some() {
+            return Err("Canceled timer is still armed");
+        }
+
+        Ok(())
+    }
+}
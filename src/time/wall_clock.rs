@@ -0,0 +1,120 @@
+//! Wall-clock time.
+//!
+//! The board has no battery backed RTC, so the wall-clock time is only known once something
+//! provides it, e.g. the host during the kernel upload. It is stored as the Unix time at uptime
+//! zero, so that it advances with the architectural timer from then on.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+static WALL_CLOCK: WallClock = WallClock::new();
+
+const SECS_PER_MINUTE: u64 = 60;
+const SECS_PER_HOUR: u64 = 60 * SECS_PER_MINUTE;
+const SECS_PER_DAY: u64 = 24 * SECS_PER_HOUR;
+
+/// A point in time as the duration since 1970-01-01 00:00:00 UTC.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UnixTime(pub Duration);
+
+impl UnixTime {
+    /// Create an instance from seconds since the epoch.
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(Duration::from_secs(secs))
+    }
+
+    /// Seconds since the epoch.
+    pub const fn as_secs(&self) -> u64 {
+        self.0.as_secs()
+    }
+}
+
+/// Prints the time as `YYYY-MM-DD hh:mm:ss` in UTC.
+impl fmt::Display for UnixTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.as_secs();
+        let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+        let secs_of_day = secs % SECS_PER_DAY;
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            secs_of_day / SECS_PER_HOUR,
+            secs_of_day % SECS_PER_HOUR / SECS_PER_MINUTE,
+            secs_of_day % SECS_PER_MINUTE
+        )
+    }
+}
+
+/// Convert days since the epoch into a (year, month, day) date of the proleptic Gregorian
+/// calendar.
+///
+/// Howard Hinnant's `civil_from_days`, restricted to dates after the epoch.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Shift the epoch to 0000-03-01, so that leap days are at the end of a year.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Wall-clock time as an offset to the uptime.
+pub struct WallClock {
+    /// Unix time in nanoseconds at uptime zero. Zero while the time was never set.
+    boot_time_nanos: AtomicU64,
+}
+
+/// Return a reference to the global WallClock.
+pub fn wall_clock() -> &'static WallClock {
+    &WALL_CLOCK
+}
+
+impl WallClock {
+    /// Create an instance.
+    const fn new() -> Self {
+        Self {
+            boot_time_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Set the current wall-clock time.
+    pub fn set(&self, now: UnixTime) -> Result<(), &'static str> {
+        let boot_time = now
+            .0
+            .checked_sub(super::uptime())
+            .ok_or("Wall-clock time is before boot")?;
+        let boot_time_nanos =
+            u64::try_from(boot_time.as_nanos()).map_err(|_| "Wall-clock time too big")?;
+
+        self.boot_time_nanos
+            .store(boot_time_nanos, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Return the current wall-clock time, or `None` if it was never set.
+    pub fn now(&self) -> Option<UnixTime> {
+        match self.boot_time_nanos.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(UnixTime(Duration::from_nanos(nanos) + super::uptime())),
+        }
+    }
+}
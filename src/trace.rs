@@ -0,0 +1,101 @@
+//! Binary trace log for post-mortem debugging.
+//!
+//! [`event()`] records a `(timestamp, id, arg)` tuple into a fixed-size ring, overwriting the
+//! oldest entry once it is full. Recording does no formatting and takes no lock, so it is cheap
+//! enough to be called from IRQ handlers and the allocator. [`dump()`] decodes the ring into
+//! human readable lines, e.g. from the panic handler.
+//!
+//! A slot is claimed with a single atomic increment and its fields are written afterwards, so an
+//! entry that is being recorded while the ring is dumped may show up torn. This is acceptable for
+//! a debugging aid and keeps the recording path short.
+//!
+//! Recording uses exclusive memory accesses and must therefore not happen before the MMU and
+//! caches are enabled.
+
+use crate::{println, time};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Number of entries in the ring. Must be a power of two.
+const NUM_ENTRIES: usize = 128;
+
+/// Event ids recorded by the kernel itself.
+pub mod id {
+    /// An IRQ was taken. The argument is unused.
+    pub const IRQ_ENTRY: u16 = 1;
+    /// All pending IRQs were handled.
+    pub const IRQ_EXIT: u16 = 2;
+    /// A heap allocation. The argument is its size.
+    pub const ALLOC: u16 = 3;
+    /// A heap deallocation. The argument is its size.
+    pub const DEALLOC: u16 = 4;
+    /// The kernel state changed. The argument is the new state.
+    pub const STATE_TRANSITION: u16 = 5;
+
+    /// Ids from here on are free for ad-hoc instrumentation.
+    pub const USER_START: u16 = 0x100;
+}
+
+struct Entry {
+    timestamp: AtomicU64,
+    id: AtomicU64,
+    arg: AtomicU64,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_ENTRY: Entry = Entry {
+    timestamp: AtomicU64::new(0),
+    id: AtomicU64::new(0),
+    arg: AtomicU64::new(0),
+};
+
+static ENTRIES: [Entry; NUM_ENTRIES] = [EMPTY_ENTRY; NUM_ENTRIES];
+
+/// Total number of events recorded. The next event goes to `NEXT % NUM_ENTRIES`.
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// Record an event.
+#[inline(always)]
+pub fn event(id: u16, arg: u64) {
+    const _: () = assert!(NUM_ENTRIES.is_power_of_two());
+
+    let index = NEXT.fetch_add(1, Ordering::Relaxed) & (NUM_ENTRIES - 1);
+    let entry = &ENTRIES[index];
+
+    entry.timestamp.store(time::counter(), Ordering::Relaxed);
+    entry.id.store(u64::from(id), Ordering::Relaxed);
+    entry.arg.store(arg, Ordering::Relaxed);
+}
+
+fn name(id: u16) -> Option<&'static str> {
+    let name = match id {
+        id::IRQ_ENTRY => "irq entry",
+        id::IRQ_EXIT => "irq exit",
+        id::ALLOC => "alloc",
+        id::DEALLOC => "dealloc",
+        id::STATE_TRANSITION => "state transition",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// Print the recorded events, oldest first.
+pub fn dump() {
+    let next = NEXT.load(Ordering::Relaxed);
+    let first = next.saturating_sub(NUM_ENTRIES);
+
+    println!("Trace log ({} of {} events):", next - first, next);
+
+    for i in first..next {
+        let entry = &ENTRIES[i & (NUM_ENTRIES - 1)];
+
+        let timestamp = time::counter_to_duration(entry.timestamp.load(Ordering::Relaxed));
+        let id = entry.id.load(Ordering::Relaxed) as u16;
+        let arg = entry.arg.load(Ordering::Relaxed);
+
+        match name(id) {
+            Some(name) => println!("      [{:#?}] {:<16} {:#x}", timestamp, name, arg),
+            None => println!("      [{:#?}] event {:#06x}     {:#x}", timestamp, id, arg),
+        }
+    }
+}
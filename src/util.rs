@@ -0,0 +1,8 @@
+//! Hardware independent helpers.
+
+pub(crate) mod array_string;
+pub(crate) mod ring_buffer;
+pub mod rng;
+
+pub use array_string::ArrayString;
+pub use ring_buffer::RingBuffer;
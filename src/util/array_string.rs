@@ -0,0 +1,174 @@
+//! Fixed-capacity strings.
+
+use core::fmt;
+
+/// Appended when a write does not fit.
+const ELLIPSIS: &str = "...";
+
+/// A string with a capacity of `N` bytes that lives on the stack or in a static.
+///
+/// Meant for messages that need dynamic values before the heap is up, e.g. driver errors. Writes
+/// that do not fit are truncated and marked with an ellipsis instead of failing, so `write!` into
+/// it never returns an error.
+#[derive(Clone, Copy)]
+pub struct ArrayString<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> ArrayString<N> {
+    /// Create an empty instance.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// Return the contents.
+    pub fn as_str(&self) -> &str {
+        // Only whole characters are ever copied in, so this can not fail.
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+
+    /// Return if a write was truncated.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Return the length of the longest prefix of `s` that fits into `space` bytes and ends on a
+    /// character boundary.
+    fn fitting_prefix(s: &str, space: usize) -> usize {
+        let mut end = s.len().min(space);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        end
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+}
+
+impl<const N: usize> Default for ArrayString<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for ArrayString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Ok(());
+        }
+
+        if s.len() <= N - self.len {
+            self.push_bytes(s.as_bytes());
+            return Ok(());
+        }
+
+        // Make room for the ellipsis, dropping already written characters if necessary.
+        self.truncated = true;
+        let space = N.saturating_sub(self.len + ELLIPSIS.len());
+        if space == 0 {
+            let keep = Self::fitting_prefix(self.as_str(), N.saturating_sub(ELLIPSIS.len()));
+            self.len = keep;
+        } else {
+            let end = Self::fitting_prefix(s, space);
+            self.push_bytes(&s.as_bytes()[..end]);
+        }
+
+        let ellipsis = &ELLIPSIS[..ELLIPSIS.len().min(N - self.len)];
+        self.push_bytes(ellipsis.as_bytes());
+
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Display for ArrayString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> fmt::Debug for ArrayString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+pub(crate) mod selftests {
+    use super::ArrayString;
+    use crate::selftest::SelfTest;
+
+    pub(crate) static TESTS: &[SelfTest] = &[
+        SelfTest {
+            name: "array string truncates at capacity",
+            run: test_array_string_truncation,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "array string keeps multi-byte chars whole",
+            run: test_array_string_utf8,
+            destructive: false,
+            needs_irqs: false,
+        },
+    ];
+
+    fn test_array_string_truncation() -> Result<(), &'static str> {
+        use core::fmt::Write;
+
+        let mut string = ArrayString::<8>::new();
+        let _ = write!(string, "{}", 1234_5678);
+        if string.as_str() != "12345678" || string.is_truncated() {
+            return Err("Write of exactly the capacity was changed");
+        }
+
+        // Already written characters make room for the ellipsis.
+        let _ = string.write_str("9");
+        if string.as_str() != "12345..." || !string.is_truncated() {
+            return Err("Overflow not truncated with an ellipsis");
+        }
+
+        // Nothing is appended after a truncation.
+        let _ = string.write_str("0");
+        if string.as_str() != "12345..." {
+            return Err("Write after a truncation changed the string");
+        }
+
+        let mut string = ArrayString::<8>::new();
+        let _ = write!(string, "NACK at {:#x}", 0x42);
+        if string.as_str() != "NACK ..." {
+            return Err("Formatted overflow not truncated");
+        }
+
+        Ok(())
+    }
+
+    fn test_array_string_utf8() -> Result<(), &'static str> {
+        use core::fmt::Write;
+
+        // The cut falls into the first euro sign, which takes 3 bytes.
+        let mut string = ArrayString::<8>::new();
+        let _ = string.write_str("abc\u{20AC}\u{20AC}");
+        if string.as_str() != "abc..." {
+            return Err("Truncation split a new character");
+        }
+
+        // The cut falls into the last already written euro sign.
+        let mut string = ArrayString::<8>::new();
+        let _ = string.write_str("ab\u{20AC}\u{20AC}");
+        let _ = string.write_str("c");
+        if string.as_str() != "ab\u{20AC}..." {
+            return Err("Truncation split a written character");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! Fixed-capacity byte queues.
+
+/// A FIFO queue of up to `N` bytes that lives in a static or a driver struct.
+///
+/// Meant for buffering between an IRQ handler and a reader. Pushing to a full buffer fails instead
+/// of overwriting, so that the caller can account for the lost byte.
+pub struct RingBuffer<const N: usize> {
+    buf: [u8; N],
+    /// Index of the oldest byte.
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    /// Create an empty instance.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Append a byte. Returns false if the buffer is full.
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.len == N {
+            return false;
+        }
+
+        self.buf[(self.head + self.len) % N] = byte;
+        self.len += 1;
+
+        true
+    }
+
+    /// Remove and return the oldest byte.
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let byte = self.buf[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+
+        Some(byte)
+    }
+
+    /// Drop all bytes.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Return the number of buffered bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return if no bytes are buffered.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) mod selftests {
+    use super::RingBuffer;
+    use crate::selftest::SelfTest;
+
+    pub(crate) static TESTS: &[SelfTest] = &[SelfTest {
+        name: "ring buffer keeps order across wrap-around",
+        run: test_ring_buffer,
+        destructive: false,
+        needs_irqs: false,
+    }];
+
+    fn test_ring_buffer() -> Result<(), &'static str> {
+        let mut buffer = RingBuffer::<4>::new();
+
+        // Move the head into the middle, so that the pushes below wrap around.
+        for byte in 0..3 {
+            buffer.push(byte);
+        }
+        for _ in 0..3 {
+            buffer.pop();
+        }
+
+        for byte in 10..14 {
+            if !buffer.push(byte) {
+                return Err("Push failed before the buffer was full");
+            }
+        }
+
+        if buffer.push(14) {
+            return Err("Push to a full buffer succeeded");
+        }
+
+        for byte in 10..14 {
+            if buffer.pop() != Some(byte) {
+                return Err("Bytes came out in the wrong order");
+            }
+        }
+
+        if !buffer.is_empty() || buffer.pop().is_some() {
+            return Err("Buffer not empty after popping everything");
+        }
+
+        Ok(())
+    }
+}
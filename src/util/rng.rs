@@ -0,0 +1,122 @@
+//! Pseudo random number generation.
+//!
+//! Not suitable for anything security related. Meant for test patterns and jitter, where a fixed
+//! seed makes runs reproducible.
+
+/// Seed used when reproducible sequences are wanted, e.g. in self-tests.
+pub const DEFAULT_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+/// Marsaglia's xorshift64 generator.
+#[derive(Clone)]
+pub struct Xorshift64 {
+    state: u64,
+}
+
+impl Xorshift64 {
+    /// Create an instance. A seed of zero, which would only ever produce zeros, is replaced by
+    /// [`DEFAULT_SEED`].
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            state: if seed == 0 { DEFAULT_SEED } else { seed },
+        }
+    }
+
+    /// Create an instance seeded from the timer counter, for when reproducibility does not matter.
+    pub fn from_uptime() -> Self {
+        Self::new(crate::time::uptime().as_nanos() as u64)
+    }
+
+    /// Return the next pseudo random number.
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+
+        x
+    }
+
+    /// Return a pseudo random number in `lo..hi`.
+    ///
+    /// Values that would favor the lower part of the range are rejected, so the result is
+    /// uniformly distributed.
+    pub fn next_range(&mut self, lo: u64, hi: u64) -> u64 {
+        assert!(lo < hi, "Empty range");
+
+        let span = hi - lo;
+        let zone = u64::MAX - (u64::MAX % span);
+
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return lo + x % span;
+            }
+        }
+    }
+}
+
+pub(crate) mod selftests {
+    use super::{Xorshift64, DEFAULT_SEED};
+    use crate::{selftest::SelfTest, time};
+    use core::time::Duration;
+
+    pub(crate) static TESTS: &[SelfTest] = &[
+        SelfTest {
+            name: "prng is reproducible",
+            run: test_prng,
+            destructive: false,
+            needs_irqs: false,
+        },
+        SelfTest {
+            name: "uptime seeded rng",
+            run: test_rng_from_uptime,
+            destructive: false,
+            needs_irqs: false,
+        },
+    ];
+
+    fn test_prng() -> Result<(), &'static str> {
+        let mut a = Xorshift64::new(DEFAULT_SEED);
+        let mut b = Xorshift64::new(DEFAULT_SEED);
+
+        for _ in 0..64 {
+            if a.next_u64() != b.next_u64() {
+                return Err("Same seed produced different sequences");
+            }
+
+            if !(10..20).contains(&a.next_range(10, 20)) {
+                return Err("Value out of range");
+            }
+            b.next_range(10, 20);
+        }
+
+        Ok(())
+    }
+
+    fn test_rng_from_uptime() -> Result<(), &'static str> {
+        let mut first = Xorshift64::from_uptime();
+        time::spin_for(Duration::from_micros(1))?;
+        let mut second = Xorshift64::from_uptime();
+
+        let mut previous = None;
+        let mut num_equal = 0;
+        for _ in 0..64 {
+            let value = first.next_u64();
+            if value == 0 || previous == Some(value) {
+                return Err("Generator is stuck");
+            }
+            previous = Some(value);
+
+            if value == second.next_u64() {
+                num_equal += 1;
+            }
+        }
+
+        if num_equal == 64 {
+            return Err("Different seeding times produced the same sequence");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+//! Build information.
+
+use crate::{bsp, info};
+
+/// Short hash of the git commit the kernel was built from, with a `-dirty` suffix if the tree had
+/// uncommitted changes.
+pub const GIT_COMMIT: &str = env!("RP4OS_GIT_COMMIT");
+
+/// Version of the compiler the kernel was built with.
+pub const RUSTC_VERSION: &str = env!("RP4OS_RUSTC_VERSION");
+
+/// Return the build profile.
+pub const fn profile() -> &'static str {
+    if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    }
+}
+
+/// Print which build is running on which board.
+pub fn print_banner() {
+    info!(
+        "[0] {} version {} ({}, {})",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        GIT_COMMIT,
+        profile()
+    );
+    info!("      Built with {}", RUSTC_VERSION);
+    match bsp::board_revision() {
+        Ok(revision) => info!(
+            "[1] Booting on: {} (revision {:#x})",
+            bsp::board_name(),
+            revision
+        ),
+        Err(_) => info!("[1] Booting on: {}", bsp::board_name()),
+    }
+}